@@ -0,0 +1,135 @@
+use crate::*;
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::env::current_dir;
+use std::fs::canonicalize;
+use std::path::{Path, PathBuf};
+
+/// Add the given paths to the staging area.
+pub fn add_files(add: cli::Add) -> Result<()> {
+    trace!("Adding files to index");
+    debug!("Got arguments: {:?}", add);
+
+    // get current directory
+    let root_path =
+        current_dir().with_context(|| "Add: Could not get the current working directory!")?;
+    let git_path = root_path.join(".git");
+    let db_path = git_path.join("objects");
+
+    // check if this is actually a repository.
+    if !is_repo(&root_path)? {
+        bail!("Add: {:?} is not a rit repository!", root_path);
+    }
+
+    let workspace = Workspace::new(&root_path).with_context(|| "Add: Could not load workspace!")?;
+    let database = Database::new(&db_path).with_context(|| "Add: Could not load database")?;
+    let mut index = Index::new(git_path.join("index"), database.get_algorithm())
+        .with_context(|| "Add: Could not create index")?;
+    index
+        .load_for_update()
+        .with_context(|| "Add: Could not load index")?;
+
+    // do not leave the index locked if anything goes wrong
+    if let Err(e) = stage_paths(&add.paths, &workspace, &database, &mut index) {
+        index
+            .release_lock()
+            .with_context(|| "Add: Could not release index lock")?;
+        return Err(e);
+    }
+
+    index
+        .write_updates()
+        .with_context(|| "Add: Could not write index")?;
+
+    info!("Add: OK");
+    Ok(())
+}
+
+/// Expand `paths` and record them in the `index`.
+/// Paths are only staged once all of them were resolved.
+fn stage_paths(
+    paths: &[PathBuf],
+    workspace: &Workspace,
+    database: &Database,
+    index: &mut Index,
+) -> Result<()> {
+    let mut files = Vec::new();
+    let mut deleted = Vec::new();
+    for path in paths {
+        let relative = relative_path(workspace.get_root_path(), path)?;
+        let name = path_to_string(&relative)?;
+        let found = workspace.list_files_in(&relative);
+
+        // entries below `path` that are gone from the workspace
+        for entry in index.get_entries() {
+            let tracked = Path::new(&entry.path);
+            if tracked.starts_with(&relative) && !found.iter().any(|f| f == tracked) {
+                deleted.push(entry.path.clone());
+            }
+        }
+
+        if found.is_empty() && !index.is_tracked(&name) {
+            bail!("Add: pathspec {:?} did not match any files", path);
+        }
+        files.extend(found);
+    }
+
+    for path in deleted {
+        debug!("Removing deleted {} from index", path);
+        index.remove(&path);
+    }
+
+    for path in files {
+        debug!("Staging {:?}", path);
+        // stream the file, large files never have to fit into memory
+        let (mut file, size) = workspace
+            .open_file(&path)
+            .with_context(|| format!("Add: Could not read {:?}", path))?;
+        let oid = database
+            .write_stream(Types::Blob, size, &mut file)
+            .with_context(|| "Add: Failed storing blob")?;
+
+        let stat = workspace
+            .read_metadata(&path)
+            .with_context(|| format!("Add: Could not stat {:?}", path))?;
+        index.add(path_to_string(&path)?, oid, &stat);
+    }
+
+    Ok(())
+}
+
+/// Turn `path` into a path relative to `root_path`, refusing anything outside
+/// of the repository.
+fn relative_path(root_path: &Path, path: &Path) -> Result<PathBuf> {
+    let root = canonicalize(root_path).with_context(|| "Add: Could not canonicalize root")?;
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        root.join(path)
+    };
+
+    // a deleted file cannot be canonicalized, resolve its parent instead
+    let resolved = match canonicalize(&absolute) {
+        Ok(resolved) => resolved,
+        Err(_) => {
+            let parent = absolute
+                .parent()
+                .and_then(|p| canonicalize(p).ok())
+                .with_context(|| format!("Add: pathspec {:?} did not match any files", path))?;
+            parent.join(absolute.file_name().unwrap_or_default())
+        }
+    };
+
+    match resolved.strip_prefix(&root) {
+        Ok(relative) if !relative.starts_with(".git") => Ok(relative.to_path_buf()),
+        _ => bail!("Add: {:?} is outside repository at {:?}", path, root),
+    }
+}
+
+/// Convert a relative path into the `/` separated form used by the `Index`.
+fn path_to_string(path: &Path) -> Result<String> {
+    Ok(path
+        .to_str()
+        .with_context(|| format!("Add: {:?} is not valid utf-8", path))?
+        .to_string())
+}
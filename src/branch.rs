@@ -0,0 +1,169 @@
+use crate::*;
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::env::current_dir;
+
+/// List, create, delete, rename or copy branches.
+pub fn manage_branches(branch: cli::Branch) -> Result<()> {
+    trace!("Managing branches");
+    debug!("Got arguments: {:?}", branch);
+
+    // get current directory
+    let root_path =
+        current_dir().with_context(|| "Branch: Could not get the current working directory!")?;
+    let git_path = root_path.join(".git");
+    let db_path = git_path.join("objects");
+
+    // check if this is actually a repository.
+    if !is_repo(&root_path)? {
+        bail!("Branch: {:?} is not a rit repository!", root_path);
+    }
+
+    let database = Database::new(&db_path).with_context(|| "Branch: Could not load database")?;
+    let refs = Refs::new(git_path).with_context(|| "Branch: Could not load refs")?;
+
+    if branch.delete || branch.force_delete {
+        delete_branches(&branch, &database, &refs)
+    } else if branch.r#move || branch.copy {
+        move_branch(&branch, &refs)
+    } else if branch.names.is_empty() {
+        list_branches(&branch, &database, &refs)
+    } else {
+        create_branch(&branch, &database, &refs)
+    }
+}
+
+/// Create a branch at the given start point or HEAD.
+fn create_branch(branch: &cli::Branch, database: &Database, refs: &Refs) -> Result<()> {
+    let name = &branch.names[0];
+    let start = branch.names.get(1).map_or(HEAD, |start| start.as_str());
+    let oid = Revision::new(refs, database, start)?
+        .resolve(Some(Types::Commit))
+        .with_context(|| format!("Branch: Not a valid object name: '{}'", start))?;
+
+    let reason = if refs.read_ref(&Refs::branch_ref(name))?.is_some() {
+        format!("branch: Reset to {}", start)
+    } else {
+        format!("branch: Created from {}", start)
+    };
+    refs.create_branch(name, oid, branch.force, &reason)
+        .with_context(|| format!("Branch: Could not create branch {}", name))
+}
+
+/// Delete every named branch, refusing unmerged ones unless forced.
+fn delete_branches(branch: &cli::Branch, database: &Database, refs: &Refs) -> Result<()> {
+    if branch.names.is_empty() {
+        bail!("Branch: branch name required");
+    }
+    let head = refs
+        .read_head()
+        .with_context(|| "Branch: Could not read HEAD")?;
+    let current = refs
+        .current_branch()
+        .with_context(|| "Branch: Could not read current branch")?;
+
+    for name in branch.names.iter() {
+        if current.as_deref() == Some(name.as_str()) {
+            bail!("Branch: Cannot delete branch '{}' checked out", name);
+        }
+        let oid = refs
+            .read_ref(&Refs::branch_ref(name))?
+            .with_context(|| format!("Branch: branch '{}' not found", name))?;
+
+        if !branch.force_delete && !branch.force {
+            let merged = match &head {
+                Some(head) => database.is_ancestor(&oid, head)?,
+                None => false,
+            };
+            if !merged {
+                bail!(
+                    "Branch: The branch '{}' is not fully merged.\n\
+                     If you are sure you want to delete it, run 'rit branch -D {}'.",
+                    name,
+                    name
+                );
+            }
+        }
+
+        refs.delete_branch(name)
+            .with_context(|| format!("Branch: Could not delete branch {}", name))?;
+        println!(
+            "Deleted branch {} (was {}).",
+            name,
+            database.short_oid(&oid)?
+        );
+    }
+
+    Ok(())
+}
+
+/// Rename or copy a branch, a single name applies to the current branch.
+fn move_branch(branch: &cli::Branch, refs: &Refs) -> Result<()> {
+    let (old, new) = match branch.names.as_slice() {
+        [new] => (
+            refs.current_branch()?
+                .with_context(|| "Branch: HEAD is detached, name the branch to rename")?,
+            new.clone(),
+        ),
+        [old, new] => (old.clone(), new.clone()),
+        _ => bail!("Branch: branch name required"),
+    };
+
+    if branch.r#move {
+        refs.rename_branch(&old, &new, branch.force)
+            .with_context(|| format!("Branch: Could not rename {} to {}", old, new))
+    } else {
+        refs.copy_branch(&old, &new, branch.force)
+            .with_context(|| format!("Branch: Could not copy {} to {}", old, new))
+    }
+}
+
+/// Print all branches, marking the current one.
+fn list_branches(branch: &cli::Branch, database: &Database, refs: &Refs) -> Result<()> {
+    let current = refs
+        .head_state()
+        .with_context(|| "Branch: Could not determine state of HEAD")?;
+    let mut lines = Vec::new();
+    if let HeadState::Detached(oid) = &current {
+        lines.push((
+            true,
+            format!("(HEAD detached at {})", database.short_oid(oid)?),
+            *oid,
+        ));
+    }
+    for name in refs.list_branches()? {
+        let is_current = current == HeadState::Branch(Refs::branch_ref(&name));
+        let oid = refs
+            .read_ref(&Refs::branch_ref(&name))?
+            .with_context(|| format!("Branch: Branch {} does not point anywhere", name))?;
+        lines.push((is_current, name, oid));
+    }
+
+    let width = lines
+        .iter()
+        .map(|(_, name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    for (is_current, name, oid) in lines {
+        let marker = if is_current { '*' } else { ' ' };
+        if !branch.verbose {
+            println!("{} {}", marker, name);
+            continue;
+        }
+
+        let subject = match database.load(&oid)? {
+            ParsedObject::Commit(commit) => commit.get_message().get_subject(),
+            _ => String::new(),
+        };
+        println!(
+            "{} {:width$} {} {}",
+            marker,
+            name,
+            database.short_oid(&oid)?,
+            subject,
+            width = width
+        );
+    }
+
+    Ok(())
+}
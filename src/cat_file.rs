@@ -1,82 +1,234 @@
 use crate::cli::CatFile;
-use crate::Types;
+use crate::*;
 use anyhow::{bail, Context, Result};
-use flate2::read::ZlibDecoder;
 use log::*;
-use ring::digest::SHA1_OUTPUT_LEN;
-use std::fs::File;
-use std::io::prelude::*;
-
-/// Print out the contents of a rit-object.
-pub fn print_object(cat_file: CatFile) -> Result<()> {
-    trace!("Printing rit-object {:?}", cat_file.path);
-    if !cat_file.path.exists() {
-        bail!("Git Object {:?} does not exits!", cat_file.path);
+use std::env::current_dir;
+use std::io::{self, BufRead, Write};
+
+/// A placeholder in a batch format.
+#[derive(Debug)]
+enum Atom {
+    ObjectName,
+    ObjectType,
+    ObjectSize,
+    ObjectSizeDisk,
+    DeltaBase,
+    Rest,
+}
+
+/// A piece of a batch format.
+#[derive(Debug)]
+enum FormatPart {
+    Literal(String),
+    Atom(Atom),
+}
+
+/// Print the contents, type or size of a rit-object.
+/// Returns the exit code, which is only non-zero for a missing object with `-e`.
+pub fn print_object(cat_file: CatFile) -> Result<i32> {
+    trace!("Printing rit-object {:?}", cat_file.args);
+
+    // get current directory
+    let root_path =
+        current_dir().with_context(|| "CatFile: Could not get the current working directory!")?;
+    let git_path = root_path.join(".git");
+    let db_path = git_path.join("objects");
+
+    // check if this is actually a repository.
+    if !is_repo(&root_path)? {
+        bail!("CatFile: {:?} is not a rit repository!", root_path);
+    }
+
+    let database = Database::new(&db_path).with_context(|| "CatFile: Could not load database")?;
+    let refs = Refs::new(git_path).with_context(|| "CatFile: Could not load refs")?;
+
+    if cat_file.batch.is_some() || cat_file.batch_check.is_some() {
+        print_batch(&cat_file, &database, &refs).with_context(|| "CatFile: Batch mode failed")?;
+        return Ok(0);
+    }
+
+    let (expected, expr) = match cat_file.args.as_slice() {
+        [expr] => (None, expr),
+        [type_, expr] => (Some(type_.parse::<Types>()?), expr),
+        _ => bail!("CatFile: expected an object"),
+    };
+    let mode_given = cat_file.r#type || cat_file.size || cat_file.pretty || cat_file.exists;
+    if mode_given == expected.is_some() {
+        bail!("CatFile: give either one of -t, -s, -p, -e or a type");
+    }
+
+    let oid = Revision::new(&refs, &database, expr).and_then(|rev| rev.resolve(expected));
+    if cat_file.exists {
+        let found = matches!(&oid, Ok(oid) if database.read_object(oid).is_ok());
+        return Ok(if found { 0 } else { 1 });
+    }
+    let oid = oid?;
+    let (type_, data) = database
+        .read_object(&oid)
+        .with_context(|| format!("CatFile: Could not read object {}", oid))?;
+    debug!(
+        "Object {} is a {} of size {}",
+        oid,
+        type_.as_string(),
+        data.len()
+    );
+
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    if cat_file.r#type {
+        writeln!(out, "{}", type_.as_string())?;
+    } else if cat_file.size {
+        writeln!(out, "{}", data.len())?;
+    } else if cat_file.pretty && type_ == Types::Tree {
+        write_tree(&mut out, &data, database.get_algorithm())?;
+    } else {
+        // blobs and commits are printed as is
+        out.write_all(&data)?;
+    }
+    out.flush()
+        .with_context(|| "CatFile: Could not flush output")?;
+
+    Ok(0)
+}
+
+/// Print a `Tree` the way `git cat-file -p` does, one
+/// `mode type oid\tname` line per entry.
+pub fn write_tree(out: &mut impl Write, data: &[u8], algorithm: HashAlgorithm) -> Result<()> {
+    let tree = Tree::parse(data, algorithm).with_context(|| "CatFile: Could not parse tree")?;
+    for (name, entry) in tree.sorted_entries() {
+        writeln!(
+            out,
+            "{:0>6} {} {}\t{}",
+            entry.get_mode(),
+            entry.get_type().as_string(),
+            entry.get_oid(),
+            name
+        )?;
     }
 
-    let mut buffer = Vec::new();
-    let mut file = File::options()
-        .read(true)
-        .write(false)
-        .open(cat_file.path)
-        .with_context(|| "CatFile: Either file {:?} exists or cannot be created")?;
-    file.read_to_end(&mut buffer).with_context(|| {
-        "CatFile: File could not be read in total or buffer could not be resized"
-    })?;
-    debug!("Read object: {:?}", buffer);
-    let mut d = ZlibDecoder::new(&buffer[..]);
-    let mut s = String::new();
-    d.read_to_string(&mut s)
-        .with_context(|| "CatFile: Zlib decoding issues")?;
-    debug!("Object: '{}'", s);
-    let size_space = s
-        .find(|c: char| c == ' ')
-        .with_context(|| "CatFile: No first space separating `type` and `len`")?;
-    let size_null = s
-        .find(|c: char| c == '\x00')
-        .with_context(|| "CatFile: No first null-byte marking end of `len`")?;
-
-    match Types::as_type(String::from(&s[0..size_space])) {
-        Types::Blob => {
-            let null = s
-                .find(|c: char| c == '\x00')
-                .with_context(|| "CatFile: Blob does not contain null-byte")?;
-            // Print blob
-            println!("'''");
-            println!("Type: Blob");
-            println!("Size: {}", &s[5..null]);
-            println!("Content:\n'''");
-            println!("{}", &s[null..]);
-            println!("'''");
+    Ok(())
+}
+
+/// Process the objects named on stdin, or every object with
+/// `--batch-all-objects`, printing one formatted line each.
+/// `--batch` additionally prints the raw content of each object.
+fn print_batch(cat_file: &CatFile, database: &Database, refs: &Refs) -> Result<()> {
+    trace!("Running batch mode");
+    let (format, with_content) = match (&cat_file.batch, &cat_file.batch_check) {
+        (Some(format), _) => (format, true),
+        (None, Some(format)) => (format, false),
+        (None, None) => bail!("CatFile: No batch mode given"),
+    };
+    let format = parse_format(format)?;
+    let split_rest = format
+        .iter()
+        .any(|part| matches!(part, FormatPart::Atom(Atom::Rest)));
+
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    if cat_file.batch_all_objects {
+        for oid in database.list_objects()? {
+            print_batch_object(&mut out, database, &format, &oid, "", with_content)?;
+            if !cat_file.buffer {
+                out.flush()?;
+            }
         }
-        Types::Tree => {
-            let mut prev = size_null;
-            // TODO Print tree
-            println!("'''");
-            println!("Type: Tree");
-            println!("Size: {}", &s[size_space..size_null]);
-            println!("Content:\n'''");
-            let _ = &s[..]
-                .match_indices(|c: char| c == '\x00')
-                .for_each(|(i, _)| {
-                    if size_null != i {
-                        println!("{}", i);
-                        println!("Mode and Name: {}", &s[prev..i]);
-                        println!("Hash: {}", &s[(i + 1)..(i + SHA1_OUTPUT_LEN)]);
-                        prev = i;
-                    }
-                });
-            println!("'''");
+    } else {
+        for line in io::stdin().lock().lines() {
+            let line = line.with_context(|| "CatFile: Failed to read from stdin")?;
+            // with %(rest) only the first word names the object
+            let (name, rest) = match split_rest {
+                true => line
+                    .trim_start()
+                    .split_once(char::is_whitespace)
+                    .map_or((line.trim(), ""), |(name, rest)| (name, rest.trim_start())),
+                false => (line.as_str(), ""),
+            };
+
+            let resolved = Revision::new(refs, database, name).and_then(|rev| rev.resolve(None));
+            match resolved {
+                Ok(oid) if database.contains(&oid) => {
+                    print_batch_object(&mut out, database, &format, &oid, rest, with_content)?
+                }
+                _ => writeln!(out, "{} missing", name)?,
+            }
+            if !cat_file.buffer {
+                out.flush()?;
+            }
         }
-        Types::Commit => {
-            // let mut prev = size_null + 1;
-            // TODO Print commit
-            println!("'''");
-            println!("Type: Commit");
-            println!("Size: {}", &s[size_space..size_null]);
-            println!("'''");
+    }
+    out.flush()
+        .with_context(|| "CatFile: Could not flush output")?;
+
+    Ok(())
+}
+
+/// Print the formatted line and possibly the content of the object `oid`.
+fn print_batch_object(
+    out: &mut impl Write,
+    database: &Database,
+    format: &[FormatPart],
+    oid: &ObjectId,
+    rest: &str,
+    with_content: bool,
+) -> Result<()> {
+    let (type_, size, data) = if with_content {
+        let (type_, data) = database.read_object(oid)?;
+        (type_, data.len(), Some(data))
+    } else {
+        let (type_, size) = database.read_header(oid)?;
+        (type_, size, None)
+    };
+
+    for part in format {
+        match part {
+            FormatPart::Literal(literal) => write!(out, "{}", literal)?,
+            FormatPart::Atom(Atom::ObjectName) => write!(out, "{}", oid)?,
+            FormatPart::Atom(Atom::ObjectType) => write!(out, "{}", type_.as_string())?,
+            FormatPart::Atom(Atom::ObjectSize) => write!(out, "{}", size)?,
+            FormatPart::Atom(Atom::ObjectSizeDisk) => write!(out, "{}", database.disk_size(oid)?)?,
+            FormatPart::Atom(Atom::DeltaBase) => match database.delta_base(oid)? {
+                Some(base) => write!(out, "{}", base)?,
+                // objects that are not deltas have the null oid as base
+                None => write!(out, "{}", database.get_algorithm().null_oid())?,
+            },
+            FormatPart::Atom(Atom::Rest) => write!(out, "{}", rest)?,
         }
     }
+    writeln!(out)?;
+
+    if let Some(data) = data {
+        out.write_all(&data)?;
+        writeln!(out)?;
+    }
 
     Ok(())
 }
+
+/// Split a batch format into literals and `%(atom)` placeholders.
+fn parse_format(format: &str) -> Result<Vec<FormatPart>> {
+    let mut parts = Vec::new();
+    let mut rest = format;
+    while let Some(start) = rest.find("%(") {
+        if start > 0 {
+            parts.push(FormatPart::Literal(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find(')')
+            .with_context(|| format!("CatFile: Unterminated atom in format {:?}", format))?;
+        let atom = match &rest[start + 2..start + end] {
+            "objectname" => Atom::ObjectName,
+            "objecttype" => Atom::ObjectType,
+            "objectsize" => Atom::ObjectSize,
+            "objectsize:disk" => Atom::ObjectSizeDisk,
+            "deltabase" => Atom::DeltaBase,
+            "rest" => Atom::Rest,
+            atom => bail!("CatFile: Unknown format element: {}", atom),
+        };
+        parts.push(FormatPart::Atom(atom));
+        rest = &rest[start + end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(FormatPart::Literal(rest.to_string()));
+    }
+
+    Ok(parts)
+}
@@ -12,6 +12,30 @@ pub mod commit;
 pub use commit::Commit;
 pub mod cat_file;
 pub use cat_file::CatFile;
+pub mod add;
+pub use add::Add;
+pub mod status;
+pub use status::Status;
+pub mod branch;
+pub use branch::Branch;
+pub mod rev_parse;
+pub use rev_parse::RevParse;
+pub mod repack;
+pub use repack::Repack;
+pub mod index_pack;
+pub use index_pack::IndexPack;
+pub mod verify_pack;
+pub use verify_pack::VerifyPack;
+pub mod fsck;
+pub use fsck::Fsck;
+pub mod reflog;
+pub use reflog::{Reflog, ReflogCommands};
+pub mod update_ref;
+pub use update_ref::UpdateRef;
+pub mod pack_refs;
+pub use pack_refs::PackRefs;
+pub mod tag;
+pub use tag::Tag;
 
 /// A fictional versioning CLI
 #[derive(Debug, Parser)] // requires `derive` feature
@@ -30,6 +54,18 @@ pub enum Commands {
     Init(Init),
     Commit(Commit),
     CatFile(CatFile),
+    Add(Add),
+    Status(Status),
+    Branch(Branch),
+    RevParse(RevParse),
+    Repack(Repack),
+    IndexPack(IndexPack),
+    VerifyPack(VerifyPack),
+    Fsck(Fsck),
+    Reflog(Reflog),
+    UpdateRef(UpdateRef),
+    PackRefs(PackRefs),
+    Tag(Tag),
 
     #[clap(external_subcommand)]
     External(Vec<OsString>),
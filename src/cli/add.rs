@@ -0,0 +1,11 @@
+use clap::Args;
+use std::path::PathBuf;
+
+/// Add file contents to the staging area.
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct Add {
+    /// Files or directories to add.
+    #[clap(value_parser, required = true)]
+    pub paths: Vec<PathBuf>,
+}
@@ -0,0 +1,34 @@
+use clap::Args;
+
+/// List, create, delete, rename or copy branches.
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct Branch {
+    /// Branch name and start point, or old and new name when renaming or copying.
+    #[clap(value_parser, max_values = 2)]
+    pub names: Vec<String>,
+
+    /// Show the oid and subject of each branch tip.
+    #[clap(short, long, value_parser)]
+    pub verbose: bool,
+
+    /// Delete a branch, it must be fully merged into HEAD.
+    #[clap(short, long, value_parser, conflicts_with_all = &["move", "copy"])]
+    pub delete: bool,
+
+    /// Delete a branch, even if it is not merged.
+    #[clap(short = 'D', value_parser, conflicts_with_all = &["move", "copy"])]
+    pub force_delete: bool,
+
+    /// Rename a branch and its reflog.
+    #[clap(short, long = "move", value_parser, conflicts_with = "copy")]
+    pub r#move: bool,
+
+    /// Copy a branch and its reflog.
+    #[clap(short, long, value_parser)]
+    pub copy: bool,
+
+    /// Overwrite an existing branch when creating, renaming or copying.
+    #[clap(short, long, value_parser)]
+    pub force: bool,
+}
@@ -1,11 +1,65 @@
-use clap::Args;
-use std::path::PathBuf;
+use clap::{ArgGroup, Args};
 
-/// Print some pretty information of a rit object.
+/// Format of `--batch` and `--batch-check` if none is given.
+pub const DEFAULT_BATCH_FORMAT: &str = "%(objectname) %(objecttype) %(objectsize)";
+
+/// Provide content, type or size information of a rit object.
 #[derive(Debug, Args)]
 #[clap(args_conflicts_with_subcommands = true)]
+#[clap(group(ArgGroup::new("mode").args(&["type", "size", "pretty", "exists", "batch", "batch-check"])))]
 pub struct CatFile {
-    /// Path to the rit object.
-    #[clap(value_parser)]
-    pub path: PathBuf,
+    /// Show the object type.
+    #[clap(short = 't', value_parser)]
+    pub r#type: bool,
+
+    /// Show the object size.
+    #[clap(short = 's', value_parser)]
+    pub size: bool,
+
+    /// Pretty-print the object content.
+    #[clap(short = 'p', value_parser)]
+    pub pretty: bool,
+
+    /// Exit with zero status if the object exists, non-zero otherwise.
+    #[clap(short = 'e', value_parser)]
+    pub exists: bool,
+
+    /// Print information and content of each object read from stdin.
+    #[clap(
+        long,
+        value_name = "format",
+        value_parser,
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = DEFAULT_BATCH_FORMAT
+    )]
+    pub batch: Option<String>,
+
+    /// Print information of each object read from stdin.
+    #[clap(
+        long,
+        value_name = "format",
+        value_parser,
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = DEFAULT_BATCH_FORMAT
+    )]
+    pub batch_check: Option<String>,
+
+    /// Process every object in the database instead of reading stdin.
+    #[clap(long, value_parser, requires = "mode")]
+    pub batch_all_objects: bool,
+
+    /// Only flush the output at the end instead of after every object.
+    #[clap(long, value_parser)]
+    pub buffer: bool,
+
+    /// Expected type followed by the object, or just the object with a mode.
+    #[clap(
+        value_parser,
+        required_unless_present_any = &["batch", "batch-check"],
+        conflicts_with_all = &["batch", "batch-check"],
+        max_values = 2
+    )]
+    pub args: Vec<String>,
 }
@@ -31,9 +31,9 @@ impl Commit {
     pub fn get_author(&self) -> Result<String> {
         trace!("Getting author's name");
         // check commit command
-        if self.author.is_some() {
+        if let Some(author) = &self.author {
             debug!("Got from command");
-            return Ok(self.author.as_ref().unwrap().clone());
+            return Ok(author.clone());
         }
 
         // check env var
@@ -55,9 +55,9 @@ impl Commit {
     pub fn get_email(&self) -> Result<String> {
         trace!("Getting author's email");
         // check commit command
-        if self.email.is_some() {
+        if let Some(email) = &self.email {
             debug!("Got from command");
-            return Ok(self.email.as_ref().unwrap().clone());
+            return Ok(email.clone());
         }
 
         // check env var
@@ -78,9 +78,9 @@ impl Commit {
         trace!("Getting commit message");
 
         // from command
-        if self.message.is_some() {
+        if let Some(message) = &self.message {
             debug!("Got from command");
-            return Ok(self.message.as_ref().unwrap().clone());
+            return Ok(message.clone());
         }
 
         // read from stdin
@@ -0,0 +1,18 @@
+use clap::Args;
+
+/// Verify the integrity and connectivity of all objects.
+///
+/// The exit code is a combination of 1 for corrupt objects, 2 for missing
+/// objects and 4 for reported unreachable objects. Dangling objects are
+/// reported but leave the exit code alone.
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct Fsck {
+    /// Report every object not reachable from a ref or reflog.
+    #[clap(long, value_parser)]
+    pub unreachable: bool,
+
+    /// Do not report unreachable objects no other object points to.
+    #[clap(long, value_parser, conflicts_with = "unreachable")]
+    pub no_dangling: bool,
+}
@@ -0,0 +1,34 @@
+use clap::Args;
+use std::path::PathBuf;
+
+use crate::HashAlgorithm;
+
+/// Build the index of a pack.
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct IndexPack {
+    /// Pack to index, its `.idx` is written next to it.
+    #[clap(
+        value_parser,
+        required_unless_present = "stdin",
+        conflicts_with = "stdin"
+    )]
+    pub pack: Option<PathBuf>,
+
+    /// Read the pack from stdin and store it in the repository.
+    #[clap(long, value_parser)]
+    pub stdin: bool,
+
+    /// Write the index to this file instead.
+    #[clap(
+        short,
+        value_name = "index-file",
+        value_parser,
+        conflicts_with = "stdin"
+    )]
+    pub output: Option<PathBuf>,
+
+    /// Hash algorithm of the pack, defaults to the one of the repository.
+    #[clap(long, value_name = "format", value_parser, conflicts_with = "stdin")]
+    pub object_format: Option<HashAlgorithm>,
+}
@@ -1,6 +1,8 @@
 use clap::Args;
 use std::path::PathBuf;
 
+use crate::HashAlgorithm;
+
 /// Initialize a rit repository.
 #[derive(Debug, Args)]
 #[clap(args_conflicts_with_subcommands = true)]
@@ -8,4 +10,8 @@ pub struct Init {
     /// Path where to initialize a rit repository.
     #[clap(value_parser)]
     pub path: Option<PathBuf>,
+
+    /// Hash algorithm for object ids, `sha1` or `sha256`.
+    #[clap(long, value_name = "format", value_parser, default_value = "sha1")]
+    pub object_format: HashAlgorithm,
 }
@@ -0,0 +1,18 @@
+use clap::Args;
+
+/// Move loose refs into `packed-refs`.
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct PackRefs {
+    /// Pack all refs, not only tags.
+    #[clap(long, value_parser)]
+    pub all: bool,
+
+    /// Remove the loose refs after packing them, the default.
+    #[clap(long, value_parser)]
+    pub prune: bool,
+
+    /// Keep the loose refs after packing them.
+    #[clap(long, value_parser, conflicts_with = "prune")]
+    pub no_prune: bool,
+}
@@ -0,0 +1,61 @@
+use clap::{Args, Subcommand};
+
+/// Show, expire or delete entries of the reflogs.
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct Reflog {
+    #[clap(subcommand)]
+    pub command: Option<ReflogCommands>,
+
+    /// Ref whose reflog to show, HEAD by default.
+    #[clap(value_parser)]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ReflogCommands {
+    Show(ReflogShow),
+    Expire(ReflogExpire),
+    Delete(ReflogDelete),
+}
+
+/// Show the reflog of a ref, newest entry first.
+#[derive(Debug, Args)]
+pub struct ReflogShow {
+    /// Ref whose reflog to show, HEAD by default.
+    #[clap(value_parser)]
+    pub name: Option<String>,
+}
+
+/// Remove reflog entries older than a given time.
+#[derive(Debug, Args)]
+pub struct ReflogExpire {
+    /// Remove entries older than this, e.g. `90.days.ago`, `now`, `all` or
+    /// `never`. Defaults to `gc.reflogExpire` or 90 days.
+    #[clap(long, value_name = "time", value_parser)]
+    pub expire: Option<String>,
+
+    /// Expire the reflogs of all refs.
+    #[clap(long, value_parser)]
+    pub all: bool,
+
+    /// Only print the entries that would be removed.
+    #[clap(short = 'n', long, value_parser)]
+    pub dry_run: bool,
+
+    /// Refs whose reflogs to expire.
+    #[clap(value_parser, required_unless_present = "all")]
+    pub refs: Vec<String>,
+}
+
+/// Remove single reflog entries.
+#[derive(Debug, Args)]
+pub struct ReflogDelete {
+    /// Only print the entries that would be removed.
+    #[clap(short = 'n', long, value_parser)]
+    pub dry_run: bool,
+
+    /// Entries to remove, e.g. `HEAD@{2}` or `master@{0}`.
+    #[clap(value_parser, required = true)]
+    pub entries: Vec<String>,
+}
@@ -0,0 +1,20 @@
+use clap::Args;
+
+use crate::pack::writer::{DEFAULT_DEPTH, DEFAULT_WINDOW};
+
+/// Pack all reachable objects into a single pack.
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct Repack {
+    /// Number of objects to compare each object against for deltas.
+    #[clap(long, value_parser, default_value_t = DEFAULT_WINDOW)]
+    pub window: usize,
+
+    /// Maximum length of a delta chain.
+    #[clap(long, value_parser, default_value_t = DEFAULT_DEPTH)]
+    pub depth: usize,
+
+    /// Write the pack to stdout instead of storing it.
+    #[clap(long, value_parser)]
+    pub stdout: bool,
+}
@@ -0,0 +1,25 @@
+use clap::Args;
+
+/// Turn revision expressions into object ids.
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct RevParse {
+    /// Revisions to resolve, e.g. `HEAD~3`, `master^2` or `HEAD:src/lib.rs`.
+    #[clap(value_parser, required = true)]
+    pub revisions: Vec<String>,
+
+    /// Require exactly one revision that resolves to an object.
+    #[clap(long, value_parser)]
+    pub verify: bool,
+
+    /// Abbreviate the object ids to the given length.
+    #[clap(
+        long,
+        value_name = "length",
+        value_parser,
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = "7"
+    )]
+    pub short: Option<usize>,
+}
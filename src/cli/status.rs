@@ -0,0 +1,25 @@
+use clap::Args;
+
+/// Show the working tree status.
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct Status {
+    /// Give the output in the short-format.
+    #[clap(short, long, value_parser)]
+    pub short: bool,
+
+    /// Give the output in a stable format for scripts, either `v1` or `v2`.
+    #[clap(
+        long,
+        value_name = "version",
+        value_parser = ["v1", "v2"],
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = "v1"
+    )]
+    pub porcelain: Option<String>,
+
+    /// Terminate entries with NUL instead of LF and do not quote paths.
+    #[clap(short = 'z', value_parser)]
+    pub null: bool,
+}
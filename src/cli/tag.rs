@@ -0,0 +1,36 @@
+use clap::Args;
+
+/// Create, list, delete or verify tags.
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct Tag {
+    /// Tag name and the object to tag, the patterns to list or the tags to
+    /// delete or verify.
+    #[clap(value_parser)]
+    pub args: Vec<String>,
+
+    /// List the tags matching the given patterns, e.g. `v1.*`.
+    #[clap(short, long, value_parser)]
+    pub list: bool,
+
+    /// Delete the given tags.
+    #[clap(short, long, value_parser, conflicts_with_all = &["list", "verify"])]
+    pub delete: bool,
+
+    /// Check that the given tags are well formed annotated tags. There is
+    /// no `-v`, it raises the verbosity.
+    #[clap(long, value_parser, conflicts_with = "list")]
+    pub verify: bool,
+
+    /// Create an annotated tag object, which needs a message.
+    #[clap(short, long, value_parser)]
+    pub annotate: bool,
+
+    /// Message of an annotated tag, implies `--annotate`.
+    #[clap(short, long, value_parser)]
+    pub message: Option<String>,
+
+    /// Replace an existing tag.
+    #[clap(short, long, value_parser)]
+    pub force: bool,
+}
@@ -0,0 +1,31 @@
+use clap::Args;
+
+/// Update, create or delete a ref, optionally checking its old value.
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct UpdateRef {
+    /// Full name of the ref, e.g. `refs/heads/master` or `HEAD`.
+    #[clap(value_parser, required_unless_present = "stdin")]
+    pub name: Option<String>,
+
+    /// New value, or with `-d` the value the ref must have.
+    #[clap(value_parser)]
+    pub new: Option<String>,
+
+    /// Value the ref must have before the update, zeros if it must not exist.
+    #[clap(value_parser)]
+    pub old: Option<String>,
+
+    /// Delete the ref.
+    #[clap(short, value_parser)]
+    pub delete: bool,
+
+    /// Read `update`, `create`, `delete` and `verify` commands from stdin and
+    /// apply them all or none.
+    #[clap(long, value_parser, conflicts_with_all = &["name", "delete"])]
+    pub stdin: bool,
+
+    /// Reason recorded in the reflog.
+    #[clap(short, value_name = "reason", value_parser)]
+    pub message: Option<String>,
+}
@@ -0,0 +1,15 @@
+use clap::Args;
+use std::path::PathBuf;
+
+/// Check packs against their checksums and indices.
+#[derive(Debug, Args)]
+#[clap(args_conflicts_with_subcommands = true)]
+pub struct VerifyPack {
+    /// The `.idx` or `.pack` files to verify.
+    #[clap(value_parser, required = true)]
+    pub packs: Vec<PathBuf>,
+
+    /// List every object with its delta chain and a chain histogram.
+    #[clap(short, long, value_parser)]
+    pub verbose: bool,
+}
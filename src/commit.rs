@@ -22,10 +22,14 @@ pub fn make_commit(commit: cli::Commit) -> Result<()> {
         bail!("Commit: {:?} is not a rit repository!", root_path);
     }
 
-    // get the current workspace.
-    let workspace =
-        Workspace::new(&root_path).with_context(|| "Commit: Could not load workspace!")?;
     let database = Database::new(&db_path).with_context(|| "Commit: Could not load database")?;
+
+    // load the staging area
+    let mut index = Index::new(git_path.join("index"), database.get_algorithm())
+        .with_context(|| "Commit: Could not create index")?;
+    index
+        .load()
+        .with_context(|| "Commit: Could not load index")?;
     let refs = Refs::new(git_path).with_context(|| "Commit: Could not load refs")?;
 
     // get commit message
@@ -33,65 +37,42 @@ pub fn make_commit(commit: cli::Commit) -> Result<()> {
         .with_context(|| "Commit: Failed to construct commit message")?;
     debug!("{}", message);
 
-    // collect entries for the tree
-    let mut entries: Vec<Entry> = Vec::new();
-
-    // iterate through all files in the workspace
-    // files with identical oid are skipped, this prevents unnecessary writes
-    // and makes diffing efficient
-    for path in workspace.get_list_files() {
-        debug!("Committing {:?} to database.", path);
-        let data = workspace
-            .read_file(path)
-            .with_context(|| "Commit: Could not read file in workspace")?;
-        let blob = &mut Blob::new(data);
-
-        // store blob and set its oid
-        database
-            .store(blob)
-            .with_context(|| "Commit: Failed storing blob")?;
-
-        let stat = workspace
-            .stat_file(path)
-            .with_context(|| "Commit: Could not get filestat")?;
-        let entry = Entry::new(path.to_path_buf(), blob.get_oid().unwrap(), stat);
-        entries.push(entry);
-    }
+    // collect entries for the tree from the index,
+    // their blobs were already stored when they were added
+    let entries = index
+        .get_entries()
+        .map(|entry| Entry::from_mode(entry.path.clone(), entry.oid, &entry.get_mode()))
+        .collect::<Result<Vec<Entry>>>()
+        .with_context(|| "Commit: Index contains an invalid entry")?;
 
     // store root tree
-    let root = &mut Tree::build(entries).with_context(|| "Commit: Could not build root tree")?;
-    // root.traverse(&|tree: &mut Tree| {
-    //     database
-    //         .store(tree)
-    //         .with_context(|| "Commit: Database failed to store the new tree")
-    //         .expect("Commit: Traversal of root tree failed");
-    // });
+    let tree = Tree::build(entries)
+        .with_context(|| "Commit: Could not build root tree")?
+        .write(&database)
+        .with_context(|| "Commit: Database failed to store the new tree")?;
 
     // get parent commit
     let parent = refs
         .read_head()
         .with_context(|| "Commit: Could not get parent")?;
 
+    // the reflog records the subject of the commit
+    let reason = match parent {
+        Some(_) => format!("commit: {}", message.get_subject()),
+        None => format!("commit (initial): {}", message.get_subject()),
+    };
+
     // generate commit
-    let commit = &mut database::Commit::new(
-        parent,
-        root.get_oid()
-            .with_context(|| "Commit: Tree should have oid set")?,
-        message,
-    );
+    let commit = &mut database::Commit::new(parent, tree, message);
 
     // store commit
-    database
+    let oid = database
         .store(commit)
         .with_context(|| "Commit: Failed to store commit")?;
 
     // update ref to new HEAD
-    refs.update_head(
-        commit
-            .get_oid()
-            .with_context(|| "Commit: Is stored, should have oid set")?,
-    )
-    .with_context(|| "Commit: Updating HEAD unsuccessful")?;
+    refs.update_head(oid, &reason)
+        .with_context(|| "Commit: Updating HEAD unsuccessful")?;
 
     info!("Commit: OK");
     Ok(())
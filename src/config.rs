@@ -0,0 +1,198 @@
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::fs::{read_to_string, write};
+use std::path::{Path, PathBuf};
+
+/// Name of the repository config file below `.git`.
+pub const CONFIG: &str = "config";
+
+/// A single `key = value` line of a `Config`.
+#[derive(Clone, Debug)]
+struct Variable {
+    /// Lowercase section, including the subsection, e.g. `branch.master`.
+    section: String,
+    /// Lowercase key.
+    key: String,
+    value: String,
+}
+
+/// The git-style INI config in `.git/config`.
+/// Sections and keys are case-insensitive, subsections are not.
+#[derive(Debug)]
+pub struct Config {
+    path: PathBuf,
+    variables: Vec<Variable>,
+}
+
+impl Config {
+    /// Load the config at `path`, a missing file is an empty config.
+    pub fn load(path: &Path) -> Result<Self> {
+        trace!("Loading config {:?}", path);
+        let mut config = Self {
+            path: path.to_path_buf(),
+            variables: Vec::new(),
+        };
+        if !path.exists() {
+            return Ok(config);
+        }
+
+        let content =
+            read_to_string(path).with_context(|| format!("Config: Could not read {:?}", path))?;
+        let mut section = None;
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header
+                    .strip_suffix(']')
+                    .with_context(|| format!("Config: Bad section in line {}", number + 1))?;
+                section = Some(Self::parse_section(header)?);
+                continue;
+            }
+
+            let section = section.clone().with_context(|| {
+                format!("Config: Variable outside section in line {}", number + 1)
+            })?;
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), Self::parse_value(value.trim())),
+                // a key without value is a boolean true
+                None => (line, String::from("true")),
+            };
+            config.variables.push(Variable {
+                section,
+                key: key.to_lowercase(),
+                value,
+            });
+        }
+        debug!("Config holds {} variables", config.variables.len());
+
+        Ok(config)
+    }
+
+    /// Get the last value of `name`, e.g. `core.fsync`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let (section, key) = Self::split_name(name)?;
+        self.variables
+            .iter()
+            .rev()
+            .find(|var| var.section == section && var.key == key)
+            .map(|var| var.value.as_str())
+    }
+
+    /// Get `name` as a boolean, `None` if it is not set.
+    pub fn get_bool(&self, name: &str) -> Result<Option<bool>> {
+        match self.get(name).map(|value| value.to_lowercase()) {
+            None => Ok(None),
+            Some(value) => match value.as_str() {
+                "true" | "yes" | "on" | "1" => Ok(Some(true)),
+                "false" | "no" | "off" | "0" | "" => Ok(Some(false)),
+                _ => bail!("Config: {} = {} is not a boolean", name, value),
+            },
+        }
+    }
+
+    /// Set `name` to `value`, replacing its last occurrence.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let (section, key) =
+            Self::split_name(name).with_context(|| format!("Config: Invalid key {}", name))?;
+        match self
+            .variables
+            .iter_mut()
+            .rev()
+            .find(|var| var.section == section && var.key == key)
+        {
+            Some(var) => var.value = value.to_string(),
+            None => {
+                // keep variables of a section together
+                let pos = self
+                    .variables
+                    .iter()
+                    .rposition(|var| var.section == section)
+                    .map_or(self.variables.len(), |pos| pos + 1);
+                self.variables.insert(
+                    pos,
+                    Variable {
+                        section,
+                        key,
+                        value: value.to_string(),
+                    },
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the config back to its file.
+    pub fn save(&self) -> Result<()> {
+        trace!("Saving config {:?}", self.path);
+        let mut content = String::new();
+        let mut current: Option<&str> = None;
+        for var in self.variables.iter() {
+            if current != Some(&var.section) {
+                match var.section.split_once('.') {
+                    Some((section, sub)) => {
+                        content.push_str(&format!("[{} \"{}\"]\n", section, sub))
+                    }
+                    None => content.push_str(&format!("[{}]\n", var.section)),
+                }
+                current = Some(&var.section);
+            }
+            content.push_str(&format!("\t{} = {}\n", var.key, var.value));
+        }
+
+        write(&self.path, content)
+            .with_context(|| format!("Config: Could not write {:?}", self.path))
+    }
+
+    /// Turn `section "sub"` into `section.sub`.
+    fn parse_section(header: &str) -> Result<String> {
+        match header.split_once(char::is_whitespace) {
+            Some((section, sub)) => {
+                let sub = sub
+                    .trim()
+                    .strip_prefix('"')
+                    .and_then(|sub| sub.strip_suffix('"'))
+                    .with_context(|| format!("Config: Bad subsection in [{}]", header))?;
+                Ok(format!("{}.{}", section.to_lowercase(), sub))
+            }
+            None => Ok(header.to_lowercase()),
+        }
+    }
+
+    /// Strip quotes and trailing comments from a value.
+    fn parse_value(value: &str) -> String {
+        let mut result = String::new();
+        let mut quoted = false;
+        let mut chars = value.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => quoted = !quoted,
+                '\\' => match chars.next() {
+                    Some('n') => result.push('\n'),
+                    Some('t') => result.push('\t'),
+                    Some(c) => result.push(c),
+                    None => (),
+                },
+                '#' | ';' if !quoted => break,
+                c => result.push(c),
+            }
+        }
+
+        result.trim_end().to_string()
+    }
+
+    /// Split `section.sub.key` into the lowercase section with its
+    /// subsection and the lowercase key.
+    fn split_name(name: &str) -> Option<(String, String)> {
+        let (section, key) = name.rsplit_once('.')?;
+        let section = match section.split_once('.') {
+            Some((section, sub)) => format!("{}.{}", section.to_lowercase(), sub),
+            None => section.to_lowercase(),
+        };
+
+        Some((section, key.to_lowercase()))
+    }
+}
@@ -1,18 +1,49 @@
 use anyhow::{bail, Context, Result};
+use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
 use log::*;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
-use ring::digest::{self, digest};
-use std::fs::{rename, File};
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{create_dir_all, metadata, read_dir, remove_dir, remove_file, rename, File};
 use std::io::prelude::*;
-use std::{fs::create_dir, path::PathBuf};
+use std::io::{BufRead, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use crate::{Config, HashAlgorithm, Types, CONFIG, MIN_PREFIX_LEN};
+
+/// Longest object header we accept, `commit 18446744073709551615\0` fits.
+const MAX_HEADER_LEN: usize = 32;
+
+/// Directory below the `Database` holding packs.
+pub const PACK_DIR: &str = "pack";
+
+/// Size of the chunks objects are streamed in.
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Config key enabling fsync of written objects and their directories.
+pub const FSYNC_KEY: &str = "core.fsync";
+
+/// Components of git's `core.fsync` list that cover object files.
+const FSYNC_OBJECT_COMPONENTS: [&str; 5] = ["loose-object", "pack", "objects", "committed", "all"];
+
+/// Minimum length of an abbreviated oid for display.
+pub const ABBREV_LEN: usize = 7;
 
 /// The `Database` is responsible for managing files in `.git/objects`.
 /// It will receive `Blobs` and store them.
 pub struct Database {
     /// `db_path` is the path to the database.
     db_path: PathBuf,
+
+    /// Hash algorithm of the repository.
+    algorithm: HashAlgorithm,
+
+    /// Packs in `objects/pack`, consulted when a loose object is missing.
+    packs: Vec<Pack>,
+
+    /// Flush written objects and their directories to disk.
+    fsync: bool,
 }
 
 impl Database {
@@ -25,95 +56,737 @@ impl Database {
             bail!("Database: {:?} does not exist or is malformed!", db_path);
         }
 
+        let (algorithm, fsync) = match db_path.parent() {
+            Some(git_path) => {
+                let config = Config::load(&git_path.join(CONFIG))
+                    .with_context(|| "Database: Could not load config")?;
+                let algorithm = HashAlgorithm::from_config(&config)
+                    .with_context(|| "Database: Could not determine object format")?;
+                (algorithm, Self::fsync_enabled(&config))
+            }
+            None => (HashAlgorithm::default(), false),
+        };
+        let packs = Self::load_packs(db_path, algorithm)
+            .with_context(|| "Database: Could not load packs")?;
+
         Ok(Self {
             db_path: db_path.to_owned(),
+            algorithm,
+            packs,
+            fsync,
         })
     }
 
+    /// Should written objects be flushed to disk according to `config`.
+    /// `core.fsync` is either a boolean or, as in git, a list of components.
+    fn fsync_enabled(config: &Config) -> bool {
+        match config.get_bool(FSYNC_KEY) {
+            Ok(enabled) => enabled.unwrap_or(false),
+            Err(_) => config.get(FSYNC_KEY).is_some_and(|value| {
+                value
+                    .split(',')
+                    .any(|component| FSYNC_OBJECT_COMPONENTS.contains(&component.trim()))
+            }),
+        }
+    }
+
+    /// Enable or disable flushing written objects to disk.
+    pub fn set_fsync(&mut self, fsync: bool) {
+        self.fsync = fsync;
+    }
+
+    /// Flush the file or directory at `path` to disk if fsync is enabled.
+    fn sync_path(&self, path: &Path) -> Result<()> {
+        if self.fsync {
+            trace!("Syncing {:?}", path);
+            File::open(path)
+                .and_then(|file| file.sync_all())
+                .with_context(|| format!("Database: Could not sync {:?}", path))?;
+        }
+
+        Ok(())
+    }
+
+    /// Open all packs in `objects/pack` that have an index.
+    fn load_packs(db_path: &Path, algorithm: HashAlgorithm) -> Result<Vec<Pack>> {
+        let pack_dir = db_path.join(PACK_DIR);
+        let mut packs = Vec::new();
+        if !pack_dir.is_dir() {
+            return Ok(packs);
+        }
+
+        let mut idx_paths = Vec::new();
+        for entry in read_dir(&pack_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "idx") {
+                idx_paths.push(path);
+            }
+        }
+        idx_paths.sort();
+        for idx_path in idx_paths {
+            packs.push(Pack::open(&idx_path, algorithm)?);
+        }
+        debug!("Loaded {} packs", packs.len());
+
+        Ok(packs)
+    }
+
+    /// Get the packs of the `Database`.
+    pub fn get_packs(&self) -> &[Pack] {
+        &self.packs
+    }
+
     /// Get `Database` path.
     pub fn get_db_path(&self) -> &PathBuf {
         &self.db_path
     }
 
-    /// Store a `Blob` in the `Database`.
-    pub fn store<T>(&self, object: &mut T) -> Result<()>
-    where
-        T: Object,
-    {
-        trace!("Storing Object.");
-        let type_ = object.get_type().as_string();
-        let data = object.get_data();
-        let len = data.len();
-        let mut content = format!("{} {}", type_, len).as_bytes().to_vec();
+    /// Calculate the oid of the raw `data` of an object of type `type_` with
+    /// the hash `algorithm`.
+    pub fn hash_data(algorithm: HashAlgorithm, type_: Types, data: &[u8]) -> ObjectId {
+        algorithm.digest_oid(&Self::serialize(type_, data))
+    }
+
+    /// Calculate the oid of the `size` bytes read from `reader` as an object
+    /// of type `type_` with the hash `algorithm`, without holding them in
+    /// memory.
+    pub fn hash_stream(
+        algorithm: HashAlgorithm,
+        type_: Types,
+        size: u64,
+        reader: &mut dyn Read,
+    ) -> Result<ObjectId> {
+        let mut hasher = algorithm.context();
+        hasher.update(format!("{} {}\0", type_.as_string(), size).as_bytes());
+
+        let mut buffer = vec![0u8; STREAM_CHUNK_SIZE];
+        let mut remaining = size;
+        loop {
+            let len = reader
+                .read(&mut buffer)
+                .with_context(|| "Database: Could not read object data")?;
+            if len == 0 {
+                break;
+            }
+            if len as u64 > remaining {
+                bail!("Database: Object data is longer than {} bytes", size);
+            }
+            remaining -= len as u64;
+            hasher.update(&buffer[..len]);
+        }
+        if remaining > 0 {
+            bail!("Database: Object data is shorter than {} bytes", size);
+        }
+
+        ObjectId::from_bytes(hasher.finish().as_ref(), algorithm)
+    }
+
+    /// Prefix `data` with the `type len\0` header of `type_`.
+    fn serialize(type_: Types, data: &[u8]) -> Vec<u8> {
+        let mut content = format!("{} {}", type_.as_string(), data.len())
+            .as_bytes()
+            .to_vec();
         content.push(b"\x00"[0]); // null terminate
         content.extend(data);
-        debug!("Content is: {:?}", content);
 
-        // calculate hash
-        // construct hash as valid utf-8 hex string
-        let hash = hex::encode(digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &content));
-        // set hash for blob
-        object.set_oid(hash.clone());
-        debug!("Object calculated hash: {}", hash);
-        self.write_object(hash, content)
-            .with_context(|| "Database: Could not store blob")?;
+        content
+    }
+
+    /// Expand the hex `prefix` of at least `MIN_PREFIX_LEN` characters into a
+    /// full oid, `None` if no object matches.
+    /// An ambiguous prefix is an error listing all candidates and their types.
+    pub fn prefix_match(&self, prefix: &str) -> Result<Option<ObjectId>> {
+        trace!("Matching prefix {}", prefix);
+        if prefix.len() < MIN_PREFIX_LEN {
+            bail!(
+                "Database: prefix {} is shorter than {} characters",
+                prefix,
+                MIN_PREFIX_LEN
+            );
+        }
+
+        let candidates = self.prefix_candidates(prefix)?;
+        match candidates.as_slice() {
+            [] => Ok(None),
+            [oid] => Ok(Some(*oid)),
+            _ => {
+                let mut message = format!(
+                    "Database: short object ID {} is ambiguous\nThe candidates are:",
+                    prefix
+                );
+                for oid in candidates.iter() {
+                    let type_ = match self.read_object(oid) {
+                        Ok((type_, _)) => type_.as_string(),
+                        Err(_) => String::from("unknown"),
+                    };
+                    message.push_str(&format!("\n  {} {}", self.short_oid(oid)?, type_));
+                }
+                bail!(message)
+            }
+        }
+    }
+
+    /// Return the shortest unique abbreviation of `oid`, but at least
+    /// `ABBREV_LEN` characters long.
+    pub fn short_oid(&self, oid: &ObjectId) -> Result<String> {
+        self.abbreviate(oid, ABBREV_LEN)
+    }
+
+    /// Return the shortest unique abbreviation of `oid` that is at least
+    /// `min_len` characters long.
+    pub fn abbreviate(&self, oid: &ObjectId, min_len: usize) -> Result<String> {
+        trace!("Abbreviating {}", oid);
+        let hex = oid.to_hex();
+        let len = self
+            .prefix_candidates(&hex[..2])?
+            .iter()
+            .filter(|other| *other != oid)
+            .map(|other| {
+                other
+                    .to_hex()
+                    .bytes()
+                    .zip(hex.bytes())
+                    .take_while(|(a, b)| a == b)
+                    .count()
+                    + 1
+            })
+            .max()
+            .unwrap_or(0)
+            .max(min_len)
+            .min(hex.len());
+
+        Ok(oid.abbrev(len))
+    }
+
+    /// Find all oids starting with the hex `prefix`, sorted, by scanning the
+    /// fan-out directory of the prefix and the pack indices.
+    fn prefix_candidates(&self, prefix: &str) -> Result<Vec<ObjectId>> {
+        let prefix = prefix.to_lowercase();
+        let mut oids = self.loose_candidates(&prefix)?;
+        for pack in self.packs.iter() {
+            oids.extend(pack.get_index().prefix_matches(&prefix));
+        }
+        oids.sort();
+        oids.dedup();
+        debug!("Prefix {} matches {:?}", prefix, oids);
+
+        Ok(oids)
+    }
+
+    /// Find the loose oids starting with the lowercase hex `prefix`.
+    fn loose_candidates(&self, prefix: &str) -> Result<Vec<ObjectId>> {
+        if prefix.len() < 2 || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("Database: {} is not a valid oid prefix", prefix);
+        }
+
+        let dirname = self.db_path.join(&prefix[0..2]);
+        let mut oids = Vec::new();
+        if dirname.is_dir() {
+            for entry in read_dir(&dirname).with_context(|| "Database: Could not list objects")? {
+                let entry = entry.with_context(|| "Database: Could not list objects")?;
+                let hex = format!("{}{}", &prefix[0..2], entry.file_name().to_string_lossy());
+                if hex.starts_with(prefix) && self.algorithm.is_oid(&hex) {
+                    oids.push(ObjectId::from_hex(&hex, self.algorithm)?);
+                }
+            }
+        }
+
+        Ok(oids)
+    }
+
+    /// Is the commit `ancestor` reachable from the commit `descendant`.
+    pub fn is_ancestor(&self, ancestor: &ObjectId, descendant: &ObjectId) -> Result<bool> {
+        trace!("Checking if {} is an ancestor of {}", ancestor, descendant);
+        let mut queue = vec![*descendant];
+        let mut seen = HashSet::new();
+        while let Some(oid) = queue.pop() {
+            if oid == *ancestor {
+                return Ok(true);
+            }
+            if !seen.insert(oid) {
+                continue;
+            }
+            match self.load(&oid)? {
+                ParsedObject::Commit(commit) => queue.extend(commit.get_parents().clone()),
+                _ => bail!("Database: {} is not a commit", oid),
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Flatten the `Tree` of the commit `oid` into a map from path to `Entry`.
+    /// No commit yields an empty map.
+    pub fn load_tree_list(&self, oid: Option<&ObjectId>) -> Result<BTreeMap<String, Entry>> {
+        trace!("Loading tree list of {:?}", oid);
+        let mut list = BTreeMap::new();
+        let oid = match oid {
+            Some(oid) => oid,
+            None => return Ok(list),
+        };
+
+        let tree = match self.load(oid)? {
+            ParsedObject::Commit(commit) => commit.get_tree(),
+            ParsedObject::Tree(_) => *oid,
+            _ => bail!("Database: {} is neither a commit nor a tree", oid),
+        };
+        self.build_tree_list(&tree, None, &mut list)?;
+
+        Ok(list)
+    }
+
+    /// Recursively add all entries of the tree `oid` below `prefix` to `list`.
+    fn build_tree_list(
+        &self,
+        oid: &ObjectId,
+        prefix: Option<&str>,
+        list: &mut BTreeMap<String, Entry>,
+    ) -> Result<()> {
+        let tree = match self.load(oid)? {
+            ParsedObject::Tree(tree) => tree,
+            _ => bail!("Database: {} is not a tree", oid),
+        };
+
+        for (name, entry) in tree.get_entries() {
+            let path = match prefix {
+                Some(prefix) => format!("{}/{}", prefix, name),
+                None => name.clone(),
+            };
+            if entry.is_tree() {
+                self.build_tree_list(&entry.get_oid(), Some(&path), list)?;
+            } else {
+                let entry = Entry::from_mode(path.clone(), entry.get_oid(), &entry.get_mode())?;
+                list.insert(path, entry);
+            }
+        }
 
         Ok(())
     }
 
-    /// Write object to `Database`.
-    fn write_object(&self, hash: String, content: Vec<u8>) -> Result<()> {
-        trace!("Writing blob to database");
-        // construct object path
-        let object_path = self.db_path.join(&hash[0..2]).join(&hash[2..]);
+    /// Read an object from the `Database` returning its type and its data with
+    /// the header stripped.
+    pub fn read_object(&self, oid: &ObjectId) -> Result<(Types, Vec<u8>)> {
+        trace!("Reading object {}", oid);
+        self.check_algorithm(oid)?;
+
+        let object_path = self.object_path(oid);
+        debug!("object_path is {:?}", object_path);
+        if !object_path.is_file() {
+            return self.read_packed(oid, |pack| pack.read_object(oid));
+        }
+        let mut buffer = Vec::new();
+        File::open(&object_path)
+            .with_context(|| format!("Database: Object {} does not exist", oid))?
+            .read_to_end(&mut buffer)
+            .with_context(|| "Database: Could not read object file")?;
+
+        // inflate content
+        let mut content = Vec::new();
+        ZlibDecoder::new(&buffer[..])
+            .read_to_end(&mut content)
+            .with_context(|| "Database: Zlib decoding issues")?;
+
+        let (type_, len, header_len) = Self::parse_header(&content)?;
+        let data = content.split_off(header_len);
+        if data.len() != len {
+            bail!(
+                "Database: Object {} has length {} but header says {}",
+                oid,
+                data.len(),
+                len
+            );
+        }
+        debug!("Read {} of length {}", type_.as_string(), len);
+
+        Ok((type_, data))
+    }
+
+    /// Read only the type and size of an object, without inflating all of it.
+    pub fn read_header(&self, oid: &ObjectId) -> Result<(Types, usize)> {
+        trace!("Reading header of object {}", oid);
+        self.check_algorithm(oid)?;
+
+        let object_path = self.object_path(oid);
+        if !object_path.is_file() {
+            return self.read_packed(oid, |pack| pack.read_header(oid));
+        }
+        let file = File::open(object_path)
+            .with_context(|| format!("Database: Object {} does not exist", oid))?;
+        let mut decoder = ZlibDecoder::new(BufReader::new(file));
+
+        // the header is short, inflate byte by byte until its null-byte
+        let mut header = Vec::new();
+        let mut byte = [0u8; 1];
+        while header.last() != Some(&b'\x00') {
+            if header.len() > MAX_HEADER_LEN {
+                bail!("Database: Header of {} is too long", oid);
+            }
+            decoder
+                .read_exact(&mut byte)
+                .with_context(|| "Database: Zlib decoding issues")?;
+            header.push(byte[0]);
+        }
+        let (type_, len, _) = Self::parse_header(&header)?;
+
+        Ok((type_, len))
+    }
+
+    /// Ask every pack for the object `oid` that is not stored loose.
+    fn read_packed<T, F>(&self, oid: &ObjectId, mut read: F) -> Result<T>
+    where
+        F: FnMut(&Pack) -> Result<Option<T>>,
+    {
+        for pack in self.packs.iter() {
+            if let Some(result) =
+                read(pack).with_context(|| format!("Database: Could not read {} from pack", oid))?
+            {
+                return Ok(result);
+            }
+        }
+
+        bail!("Database: Object {} does not exist", oid)
+    }
+
+    /// Size of the stored, compressed object `oid` on disk.
+    pub fn disk_size(&self, oid: &ObjectId) -> Result<u64> {
+        if let Ok(metadata) = metadata(self.object_path(oid)) {
+            return Ok(metadata.len());
+        }
+
+        self.packs
+            .iter()
+            .find_map(|pack| pack.disk_size(oid))
+            .with_context(|| format!("Database: Object {} does not exist", oid))
+    }
+
+    /// Oid of the object that `oid` is stored as a delta against, if any.
+    pub fn delta_base(&self, oid: &ObjectId) -> Result<Option<ObjectId>> {
+        if self.object_path(oid).is_file() {
+            return Ok(None);
+        }
+        for pack in self.packs.iter() {
+            if let Some(base) = pack.delta_base(oid)? {
+                return Ok(Some(base));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// List the oids of all objects in the `Database`, sorted.
+    pub fn list_objects(&self) -> Result<Vec<ObjectId>> {
+        trace!("Listing all objects");
+        let mut oids = Vec::new();
+        for dir in read_dir(&self.db_path).with_context(|| "Database: Could not list objects")? {
+            let dir = dir.with_context(|| "Database: Could not list objects")?;
+            let prefix = dir.file_name().to_string_lossy().to_string();
+            // only the fan-out directories hold loose objects
+            if prefix.len() != 2 || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+                continue;
+            }
+            oids.extend(self.loose_candidates(&prefix)?);
+        }
+        for pack in self.packs.iter() {
+            oids.extend(pack.get_index().get_oids().iter().copied());
+        }
+        oids.sort();
+        oids.dedup();
+
+        Ok(oids)
+    }
+
+    /// Parse an object header `type len\0` at the start of `content`,
+    /// returning the type, the length of the data and the length of the header.
+    fn parse_header(content: &[u8]) -> Result<(Types, usize, usize)> {
+        let space = content
+            .iter()
+            .position(|&b| b == b' ')
+            .with_context(|| "Database: No space separating `type` and `len`")?;
+        let null = content
+            .iter()
+            .position(|&b| b == b'\x00')
+            .with_context(|| "Database: No null-byte marking end of `len`")?;
+        if null < space {
+            bail!("Database: Malformed object header");
+        }
+        let type_: Types = std::str::from_utf8(&content[..space])
+            .with_context(|| "Database: Type is not valid utf-8")?
+            .parse()?;
+        let len: usize = std::str::from_utf8(&content[space + 1..null])
+            .with_context(|| "Database: Length is not valid utf-8")?
+            .parse()
+            .with_context(|| "Database: Length is not a number")?;
+
+        Ok((type_, len, null + 1))
+    }
+
+    /// Write a pack holding `objects` with its index to `objects/pack` and
+    /// return the hex checksum that names it.
+    pub fn write_pack(
+        &self,
+        objects: &[PackObject],
+        window: usize,
+        depth: usize,
+    ) -> Result<String> {
+        trace!("Writing pack of {} objects", objects.len());
+        self.create_pack(|out| pack::writer::pack_objects(self, objects, window, depth, out))
+    }
+
+    /// Store the pack read from `input` in `objects/pack`, resolving its deltas
+    /// to build the index, and return the hex checksum that names it.
+    pub fn index_pack<R: BufRead>(&self, input: R) -> Result<String> {
+        trace!("Indexing incoming pack");
+        self.create_pack(|mut out| {
+            // deltas are resolved against the copy written to disk
+            pack::indexer::Indexer::read(input, Some(&mut out), self.algorithm)?
+                .resolve(out.get_mut())
+        })
+    }
+
+    /// Let `write` fill a temporary pack, then move it with its index into
+    /// `objects/pack`.
+    fn create_pack<F>(&self, write: F) -> Result<String>
+    where
+        F: FnOnce(BufWriter<File>) -> Result<(Vec<u8>, Vec<PackEntry>)>,
+    {
+        let pack_dir = self.db_path.join(PACK_DIR);
+        create_dir_all(&pack_dir).with_context(|| "Database: Failed to create pack directory")?;
+
+        let temp_name = pack_dir.join(Self::generate_temp_name());
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&temp_name)
+            .with_context(|| "Database: Could not create pack file")?;
+        let (checksum, entries) = match write(BufWriter::new(file)) {
+            Ok(result) => result,
+            Err(err) => {
+                remove_file(&temp_name).ok();
+                return Err(err);
+            }
+        };
+
+        let name = hex::encode(&checksum);
+        let pack_path = pack_dir.join(format!("pack-{}.pack", name));
+        let idx_path = pack_path.with_extension("idx");
+        let temp_idx = pack_dir.join(Self::generate_temp_name());
+        File::options()
+            .write(true)
+            .create_new(true)
+            .open(&temp_idx)
+            .with_context(|| "Database: Could not create pack index")?
+            .write_all(&pack::writer::write_index(
+                &entries,
+                &checksum,
+                self.algorithm,
+            ))
+            .with_context(|| "Database: Could not write pack index")?;
+
+        self.sync_path(&temp_name)?;
+        self.sync_path(&temp_idx)?;
+
+        // the index goes last, packs without one are ignored
+        rename(temp_name, &pack_path).with_context(|| "Database: Could not rename pack")?;
+        rename(temp_idx, &idx_path).with_context(|| "Database: Could not rename pack index")?;
+        self.sync_path(&pack_dir)?;
+        debug!("Wrote {:?}", pack_path);
+
+        Ok(name)
+    }
+
+    /// Remove loose objects and other packs which are fully contained in the
+    /// pack named `name`.
+    pub fn prune_packed(&self, name: &str) -> Result<()> {
+        trace!("Pruning objects contained in pack-{}", name);
+        let idx_path = self
+            .db_path
+            .join(PACK_DIR)
+            .join(format!("pack-{}.idx", name));
+        let pack = Pack::open(&idx_path, self.algorithm)?;
+
+        for dir in read_dir(&self.db_path).with_context(|| "Database: Could not list objects")? {
+            let dir = dir.with_context(|| "Database: Could not list objects")?;
+            let prefix = dir.file_name().to_string_lossy().to_string();
+            if prefix.len() != 2 || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+                continue;
+            }
+            for oid in self.loose_candidates(&prefix)? {
+                if pack.contains(&oid) {
+                    remove_file(self.object_path(&oid))
+                        .with_context(|| format!("Database: Could not remove {}", oid))?;
+                }
+            }
+            // only succeeds once the directory is empty
+            remove_dir(dir.path()).ok();
+        }
+
+        for old in self.packs.iter() {
+            let old_path = old.get_pack_path();
+            if old_path == pack.get_pack_path() {
+                continue;
+            }
+            if old
+                .get_index()
+                .get_oids()
+                .iter()
+                .all(|oid| pack.contains(oid))
+            {
+                debug!("Removing redundant {:?}", old_path);
+                remove_file(old_path.with_extension("idx"))
+                    .with_context(|| format!("Database: Could not remove {:?}", old_path))?;
+                remove_file(old_path)
+                    .with_context(|| format!("Database: Could not remove {:?}", old_path))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Refuse oids of another hash algorithm than the `Database` uses.
+    fn check_algorithm(&self, oid: &ObjectId) -> Result<()> {
+        if oid.get_algorithm() != self.algorithm {
+            bail!(
+                "Database: {} is a {} oid, but the repository uses {}",
+                oid,
+                oid.get_algorithm().name(),
+                self.algorithm.name()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Path of the object file for `oid`.
+    fn object_path(&self, oid: &ObjectId) -> PathBuf {
+        let hex = oid.to_hex();
+        self.db_path.join(&hex[0..2]).join(&hex[2..])
+    }
+
+    /// Hash and compress the `type_` header and the `size` bytes of `reader`
+    /// into `file` in a single pass, returning the oid.
+    fn deflate_stream(
+        &self,
+        file: &mut File,
+        type_: Types,
+        size: u64,
+        reader: &mut dyn Read,
+    ) -> Result<ObjectId> {
+        let header = format!("{} {}\0", type_.as_string(), size);
+        let mut hasher = self.algorithm.context();
+        let mut encoder = ZlibEncoder::new(BufWriter::new(file), Compression::fast());
+        hasher.update(header.as_bytes());
+        encoder
+            .write_all(header.as_bytes())
+            .with_context(|| "Database: Unable to encode content")?;
+
+        let mut buffer = vec![0u8; STREAM_CHUNK_SIZE];
+        let mut remaining = size;
+        loop {
+            let len = reader
+                .read(&mut buffer)
+                .with_context(|| "Database: Could not read object data")?;
+            if len == 0 {
+                break;
+            }
+            // the file might change while we read it
+            if len as u64 > remaining {
+                bail!("Database: Object data is longer than {} bytes", size);
+            }
+            remaining -= len as u64;
+            hasher.update(&buffer[..len]);
+            encoder
+                .write_all(&buffer[..len])
+                .with_context(|| "Database: Unable to encode content")?;
+        }
+        if remaining > 0 {
+            bail!("Database: Object data is shorter than {} bytes", size);
+        }
+
+        encoder
+            .finish()
+            .with_context(|| "Database: Could not finish encoding")?
+            .flush()
+            .with_context(|| "Database: Could not write compressed data to file")?;
+        debug!("Written compressed content to temp_file.");
+
+        ObjectId::from_bytes(hasher.finish().as_ref(), self.algorithm)
+    }
+
+    /// Move the finished temp file `temp_name` to the path of `oid`.
+    /// The rename is atomic, so concurrent writers of the same object only
+    /// ever see a complete file.
+    fn move_object(&self, oid: &ObjectId, temp_name: &Path) -> Result<()> {
+        let object_path = self.object_path(oid);
         let dirname = object_path.parent().unwrap();
-        let temp_name = dirname.join(Self::generate_temp_name());
         debug!("object_path is {:?}", object_path);
-        debug!("dirname is {:?}", dirname);
-        debug!("temp_name is {:?}", temp_name);
 
-        // do not write duplicates
+        // do not write duplicates, but make sure the existing copy matches
         if object_path.exists() {
-            debug!("{:?} does already exist, skipping...", object_path);
-            return Ok(());
-        }
-
-        // if dirname does not exist create it
-        if !dirname.exists() {
-            create_dir(dirname).with_context(|| "Database: Failed to create directory")?;
-        }
-
-        {
-            // open temp_file, ensuring it does not exists already
-            let mut file = File::options()
-                .read(true)
-                .write(true)
-                .create_new(true)
-                .open(&temp_name)
-                .with_context(|| "Database: Could not create file")?;
-            // create encoder
-            let mut compressed = ZlibEncoder::new(Vec::new(), Compression::fast());
-            // compress content
-            compressed
-                .write_all(&content)
-                .with_context(|| "Database: Unable to encode content")?;
-            // finish compression
-            let compressed = compressed
-                .finish()
-                .with_context(|| "Database: Could not finish encoding")?;
-            // write data to file
-            file.write_all(&compressed)
-                .with_context(|| "Database: Could not write compressed data to file")?;
-            debug!("Written compressed content to temp_file.");
-        } // file is closed here.
+            match Self::same_content(&object_path, temp_name) {
+                Ok(true) => {
+                    debug!("{:?} does already exist, skipping...", object_path);
+                    remove_file(temp_name)
+                        .with_context(|| "Database: Could not remove temp file")?;
+                    return Ok(());
+                }
+                Ok(false) => {
+                    remove_file(temp_name).ok();
+                    bail!("Database: Hash collision on {}", oid);
+                }
+                Err(err) => warn!("Replacing corrupt object {}: {:#}", oid, err),
+            }
+        }
+
+        // another process may create the directory at the same time
+        let created = !dirname.is_dir();
+        create_dir_all(dirname).with_context(|| "Database: Failed to create directory")?;
 
         debug!("Rename temp_file to object_path");
-        rename(temp_name, object_path)
+        rename(temp_name, &object_path)
             .with_context(|| "Database: Renaming of temp_name to object_path failed")?;
 
+        self.sync_path(dirname)?;
+        if created {
+            self.sync_path(&self.db_path)?;
+        }
+
         Ok(())
     }
 
+    /// Compare the inflated content of the loose object files `existing`
+    /// and `written`. Fails if `existing` cannot be inflated.
+    fn same_content(existing: &Path, written: &Path) -> Result<bool> {
+        let open = |path: &Path| -> Result<ZlibDecoder<BufReader<File>>> {
+            let file =
+                File::open(path).with_context(|| format!("Database: Could not open {:?}", path))?;
+            Ok(ZlibDecoder::new(BufReader::new(file)))
+        };
+        let mut existing = open(existing)?;
+        let mut written = open(written)?;
+
+        let mut left = Vec::with_capacity(STREAM_CHUNK_SIZE);
+        let mut right = Vec::with_capacity(STREAM_CHUNK_SIZE);
+        loop {
+            left.clear();
+            right.clear();
+            (&mut existing)
+                .take(STREAM_CHUNK_SIZE as u64)
+                .read_to_end(&mut left)
+                .with_context(|| "Database: Could not inflate existing object")?;
+            (&mut written)
+                .take(STREAM_CHUNK_SIZE as u64)
+                .read_to_end(&mut right)
+                .with_context(|| "Database: Could not inflate written object")?;
+            if left != right {
+                return Ok(false);
+            }
+            if left.is_empty() {
+                return Ok(true);
+            }
+        }
+    }
+
     /// Generate temporay name.
     fn generate_temp_name() -> String {
         let rand: String = thread_rng()
@@ -125,10 +798,75 @@ impl Database {
     }
 }
 
+impl ObjectStore for Database {
+    fn get_algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    fn read(&self, oid: &ObjectId) -> Result<(Types, Vec<u8>)> {
+        self.read_object(oid)
+    }
+
+    fn write(&self, type_: Types, data: &[u8]) -> Result<ObjectId> {
+        self.write_stream(type_, data.len() as u64, &mut &data[..])
+    }
+
+    fn write_stream(&self, type_: Types, size: u64, reader: &mut dyn Read) -> Result<ObjectId> {
+        trace!("Storing {} of size {}", type_.as_string(), size);
+        // the oid is only known at the end, so the temp file starts out
+        // outside of the fan-out directories
+        let temp_name = self.db_path.join(Self::generate_temp_name());
+        let mut file = File::options()
+            .write(true)
+            .create_new(true)
+            .open(&temp_name)
+            .with_context(|| "Database: Could not create file")?;
+        let written = self
+            .deflate_stream(&mut file, type_, size, reader)
+            .and_then(|oid| {
+                if self.fsync {
+                    file.sync_all()
+                        .with_context(|| "Database: Could not sync temp file")?;
+                }
+                Ok(oid)
+            });
+        drop(file);
+        let oid = match written {
+            Ok(oid) => oid,
+            Err(err) => {
+                remove_file(&temp_name).ok();
+                return Err(err);
+            }
+        };
+        debug!("Object calculated hash: {}", oid);
+        self.move_object(&oid, &temp_name)
+            .with_context(|| format!("Database: Could not store {}", oid))?;
+
+        Ok(oid)
+    }
+
+    fn contains(&self, oid: &ObjectId) -> bool {
+        oid.get_algorithm() == self.algorithm
+            && (self.object_path(oid).is_file() || self.packs.iter().any(|p| p.contains(oid)))
+    }
+
+    fn iter(&self) -> Result<Box<dyn Iterator<Item = ObjectId> + '_>> {
+        Ok(Box::new(self.list_objects()?.into_iter()))
+    }
+}
+
 pub mod object;
-pub use object::Object;
+pub use object::{Object, ParsedObject};
+pub mod store;
+pub use store::ObjectStore;
+pub mod memory;
+pub use memory::MemoryStore;
+pub mod object_id;
+pub use object_id::ObjectId;
 pub mod commit;
 pub use commit::Commit;
+pub mod tag;
+pub use tag::Tag;
 pub mod blob;
 pub use blob::Blob;
 pub mod tree;
@@ -139,3 +877,88 @@ pub mod author;
 pub use author::Author;
 pub mod message;
 pub use message::Message;
+pub mod pack;
+pub use pack::{
+    writer::{PackEntry, PackObject},
+    Pack,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestDir;
+
+    /// Create an empty `Database` in `dir`.
+    fn database(dir: &TestDir) -> Database {
+        let db_path = dir.path().join("objects");
+        create_dir_all(&db_path).unwrap();
+        Database::new(&db_path).unwrap()
+    }
+
+    /// Store `content` deflated as the loose object `oid`, header included.
+    fn write_raw(database: &Database, oid: &ObjectId, content: &[u8]) {
+        let path = database.object_path(oid);
+        create_dir_all(path.parent().unwrap()).unwrap();
+        let mut encoder = ZlibEncoder::new(File::create(path).unwrap(), Compression::default());
+        encoder.write_all(content).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    #[test]
+    fn load_round_trip() {
+        let dir = TestDir::new();
+        let database = database(&dir);
+        let blob = &mut Blob::new(b"some content\n".to_vec());
+        let oid = database.store(blob).unwrap();
+
+        assert!(database.object_path(&oid).is_file());
+        assert_eq!(database.read_header(&oid).unwrap(), (Types::Blob, 13));
+        match database.load(&oid).unwrap() {
+            ParsedObject::Blob(loaded) => {
+                assert_eq!(loaded.get_oid(), Some(oid));
+                assert_eq!(loaded.get_data(), blob.get_data());
+            }
+            _ => panic!("expected a blob"),
+        }
+    }
+
+    #[test]
+    fn header_is_parsed() {
+        assert_eq!(
+            Database::parse_header(b"commit 12\0data").unwrap(),
+            (Types::Commit, 12, 10)
+        );
+    }
+
+    #[test]
+    fn bad_header_is_rejected() {
+        for header in [
+            &b"blob 5"[..],
+            b"blob5\0",
+            b"blob \0",
+            b"blob x\0",
+            b"blob -1\0",
+            b"\0blob 5 ",
+            b"bolb 5\0",
+            b"Blob 5\0",
+        ] {
+            assert!(Database::parse_header(header).is_err(), "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn corrupt_object_is_rejected() {
+        let dir = TestDir::new();
+        let database = database(&dir);
+        let oid = Database::hash_data(HashAlgorithm::Sha1, Types::Blob, b"hello");
+
+        // length in the header does not match the data
+        write_raw(&database, &oid, b"blob 4\0hello");
+        assert!(database.read_object(&oid).is_err());
+        assert!(database.load(&oid).is_err());
+
+        // unknown type
+        write_raw(&database, &oid, b"blub 5\0hello");
+        assert!(database.read_object(&oid).is_err());
+    }
+}
@@ -1,5 +1,6 @@
 use std::fmt::Display;
 
+use anyhow::{bail, Context, Result};
 use chrono::{
     offset::{Offset, TimeZone},
     Local,
@@ -37,6 +38,27 @@ impl Author {
 
         Self { name, email, time }
     }
+
+    /// Parse an `Author` from a header value of the form
+    /// `name <email> timestamp timezone`.
+    pub fn parse(s: &str) -> Result<Self> {
+        trace!("Parsing author {}", s);
+        let open = s
+            .find('<')
+            .with_context(|| "Author: No `<` opening the email")?;
+        let close = s
+            .find('>')
+            .with_context(|| "Author: No `>` closing the email")?;
+        if close < open {
+            bail!("Author: Malformed email in {}", s);
+        }
+
+        Ok(Self {
+            name: s[..open].trim_end().to_string(),
+            email: s[open + 1..close].to_string(),
+            time: s[close + 1..].trim_start().to_string(),
+        })
+    }
 }
 
 impl Display for Author {
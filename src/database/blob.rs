@@ -8,7 +8,7 @@ pub struct Blob {
     type_: Types,
 
     /// Unique oid of `Blob`.
-    oid: Option<String>,
+    oid: Option<ObjectId>,
 
     /// Data contained in the `Blob`.
     data: Vec<u8>,
@@ -25,6 +25,13 @@ impl Blob {
             data,
         }
     }
+
+    /// Parse a `Blob` from the data of a stored object.
+    /// This is the inverse of `get_data`.
+    pub fn parse(data: Vec<u8>) -> Self {
+        trace!("Parsing blob");
+        Self::new(data)
+    }
 }
 
 impl Object for Blob {
@@ -38,13 +45,49 @@ impl Object for Blob {
         self.data.to_owned()
     }
 
-    fn set_oid(&mut self, hash: String) {
+    fn set_oid(&mut self, hash: ObjectId) {
         trace!("Setting oid of blob");
         self.oid = Some(hash);
     }
 
-    fn get_oid(&self) -> Option<String> {
+    fn get_oid(&self) -> Option<ObjectId> {
         trace!("Getting oid of blob");
-        self.oid.clone()
+        self.oid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let store = MemoryStore::new(HashAlgorithm::Sha1);
+        let blob = &mut Blob::new(b"hello\n".to_vec());
+        let oid = store.store(blob).unwrap();
+        // same oid as `git hash-object`
+        assert_eq!(oid.to_hex(), "ce013625030ba8dba906f756967f9e9ca394464a");
+        assert_eq!(blob.get_oid(), Some(oid));
+
+        let loaded = match store.load(&oid).unwrap() {
+            ParsedObject::Blob(blob) => blob,
+            _ => panic!("expected a blob"),
+        };
+        assert_eq!(loaded.get_oid(), Some(oid));
+        assert_eq!(loaded.get_data(), blob.get_data());
+    }
+
+    #[test]
+    fn round_trip_binary() {
+        let store = MemoryStore::new(HashAlgorithm::Sha256);
+        let data: Vec<u8> = (0..=255).collect();
+        let oid = store.store(&mut Blob::new(data.clone())).unwrap();
+
+        let loaded = store.load(&oid).unwrap();
+        assert_eq!(loaded.get_type(), Types::Blob);
+        match loaded {
+            ParsedObject::Blob(blob) => assert_eq!(blob.get_data(), data),
+            _ => panic!("expected a blob"),
+        }
     }
 }
@@ -1,44 +1,166 @@
 use crate::*;
+use anyhow::{Context, Result};
 use log::*;
 use std::fmt::Display;
 
 /// The `Commit` `Object` is another implementation of the established `Blob` and
 /// `Tree` pattern.
 pub struct Commit {
-    /// The `Tree` id the `Commit` belongs to.
-    oid: String,
+    /// Unique oid of `Commit`.
+    oid: Option<ObjectId>,
 
     /// Type.
     type_: Types,
 
-    /// Possible parent of `Commit`.
-    parent: Option<String>,
+    /// The `Tree` id the `Commit` belongs to.
+    tree: ObjectId,
+
+    /// Parents of `Commit`, empty for a root commit.
+    parents: Vec<ObjectId>,
 
     /// The `Message` attached to this commit.
     message: Message,
 
-    /// Data field
-    data: Vec<u8>,
+    /// The committer, which is the author for commits we create.
+    committer: Author,
+
+    /// Other headers following the committer, e.g. `encoding` or `gpgsig`,
+    /// with the lines of multi-line values joined by newlines.
+    extra_headers: Vec<(String, String)>,
+
+    /// Does the stored message end in a newline, as ours always do.
+    trailing_newline: bool,
 }
 
 impl Commit {
     /// Create a new `Commit`.
-    pub fn new(parent: Option<String>, tree: String, message: Message) -> Self {
+    pub fn new(parent: Option<ObjectId>, tree: ObjectId, message: Message) -> Self {
         trace!("Creating Commit");
+        let committer = message.get_author();
         Self {
-            oid: tree,
+            oid: None,
             type_: Types::Commit,
-            parent,
+            tree,
+            parents: parent.into_iter().collect(),
             message,
-            data: Vec::new(),
+            committer,
+            extra_headers: Vec::new(),
+            trailing_newline: true,
         }
     }
+
+    /// Parse a `Commit` from the data of a stored object.
+    /// This is the inverse of `get_data`, oids are hashed with `algorithm`.
+    pub fn parse(data: &[u8], algorithm: HashAlgorithm) -> Result<Self> {
+        trace!("Parsing commit");
+        let s = std::str::from_utf8(data).with_context(|| "Commit: Data is not valid utf-8")?;
+        let (headers, body) = s
+            .split_once("\n\n")
+            .with_context(|| "Commit: No empty line separating headers and message")?;
+
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        let mut extra_headers: Vec<(String, String)> = Vec::new();
+        let mut continues = false;
+        for line in headers.split('\n') {
+            // continuation lines of multi-line headers (e.g. `gpgsig`)
+            if let Some(continuation) = line.strip_prefix(' ') {
+                let (_, value) = extra_headers
+                    .last_mut()
+                    .filter(|_| continues)
+                    .with_context(|| "Commit: Continuation line without header")?;
+                value.push('\n');
+                value.push_str(continuation);
+                continue;
+            }
+            continues = false;
+            let (key, value) = line
+                .split_once(' ')
+                .with_context(|| format!("Commit: Malformed header line {:?}", line))?;
+            match key {
+                "tree" => {
+                    tree = Some(
+                        ObjectId::from_hex(value, algorithm)
+                            .with_context(|| "Commit: Invalid tree")?,
+                    )
+                }
+                "parent" => parents.push(
+                    ObjectId::from_hex(value, algorithm)
+                        .with_context(|| "Commit: Invalid parent")?,
+                ),
+                "author" => {
+                    author = Some(Author::parse(value).with_context(|| "Commit: Invalid author")?)
+                }
+                "committer" => {
+                    committer =
+                        Some(Author::parse(value).with_context(|| "Commit: Invalid committer")?)
+                }
+                _ => {
+                    debug!("Keeping commit header {}", key);
+                    extra_headers.push((key.to_string(), value.to_string()));
+                    continues = true;
+                }
+            }
+        }
+
+        let tree = tree.with_context(|| "Commit: Missing tree header")?;
+        let author = author.with_context(|| "Commit: Missing author header")?;
+        let committer = committer.with_context(|| "Commit: Missing committer header")?;
+        let trailing_newline = body.ends_with('\n');
+        let message = body.strip_suffix('\n').unwrap_or(body).to_string();
+
+        Ok(Self {
+            oid: None,
+            type_: Types::Commit,
+            tree,
+            parents,
+            message: Message::new(author, message),
+            committer,
+            extra_headers,
+            trailing_newline,
+        })
+    }
+
+    /// Get the oid of the root `Tree`.
+    pub fn get_tree(&self) -> ObjectId {
+        self.tree
+    }
+
+    /// Get the first parent, if any.
+    pub fn get_parent(&self) -> Option<ObjectId> {
+        self.parents.first().copied()
+    }
+
+    /// Get all parents.
+    pub fn get_parents(&self) -> &Vec<ObjectId> {
+        &self.parents
+    }
+
+    /// Get the `Message`.
+    pub fn get_message(&self) -> &Message {
+        &self.message
+    }
+
+    /// Get the committer.
+    pub fn get_committer(&self) -> &Author {
+        &self.committer
+    }
+
+    /// Get the value of the first extra header `key`, e.g. `gpgsig`.
+    pub fn get_header(&self, key: &str) -> Option<&str> {
+        self.extra_headers
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
 }
 
 impl Display for Commit {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f)?;
-        write!(f, "{:?}", self.data)?;
+        write!(f, "{}", String::from_utf8_lossy(&self.get_data()))?;
         writeln!(f)?;
 
         Ok(())
@@ -54,26 +176,110 @@ impl Object for Commit {
     fn get_data(&self) -> Vec<u8> {
         trace!("Getting data of commit");
         let mut s = String::new();
-        s.push_str(&format!("tree {}\n", self.oid));
-        if let Some(parent) = &self.parent {
+        s.push_str(&format!("tree {}\n", self.tree));
+        for parent in self.parents.iter() {
             debug!("Parent of commit is: {parent}");
             s.push_str(&format!("parent {}\n", parent));
         }
         s.push_str(&format!("author {}\n", self.message.get_author()));
-        s.push_str(&format!("committer {}\n", self.message.get_author()));
+        s.push_str(&format!("committer {}\n", self.committer));
+        for (key, value) in self.extra_headers.iter() {
+            s.push_str(&format!("{} {}\n", key, value.replace('\n', "\n ")));
+        }
         s.push('\n');
-        s.push_str(&format!("{}\n", self.message.get_message()));
+        s.push_str(&self.message.get_message());
+        if self.trailing_newline {
+            s.push('\n');
+        }
 
         s.as_bytes().to_vec()
     }
 
-    fn set_oid(&mut self, hash: String) {
-        trace!("Setting oid of blob");
-        self.oid = hash;
+    fn set_oid(&mut self, hash: ObjectId) {
+        trace!("Setting oid of commit");
+        self.oid = Some(hash);
+    }
+
+    fn get_oid(&self) -> Option<ObjectId> {
+        trace!("Getting oid of commit");
+        self.oid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let store = MemoryStore::new(HashAlgorithm::Sha1);
+        let mut tree = Tree::new();
+        let tree = tree.write(&store).unwrap();
+        let author = Author::parse("A U Thor <author@example.com> 1234567890 +0200").unwrap();
+
+        let root = &mut Commit::new(None, tree, Message::new(author.clone(), "root".into()));
+        let root = store.store(root).unwrap();
+        let message = Message::new(author, "subject\n\nbody line".into());
+        let commit = &mut Commit::new(Some(root), tree, message);
+        let oid = store.store(commit).unwrap();
+
+        let loaded = match store.load(&oid).unwrap() {
+            ParsedObject::Commit(commit) => commit,
+            _ => panic!("expected a commit"),
+        };
+        assert_eq!(loaded.get_oid(), Some(oid));
+        assert_eq!(loaded.get_data(), commit.get_data());
+        assert_eq!(loaded.get_tree(), tree);
+        assert_eq!(loaded.get_parents(), &vec![root]);
+        assert_eq!(loaded.get_message().get_message(), "subject\n\nbody line");
+        assert_eq!(loaded.get_message().get_subject(), "subject");
+        assert_eq!(loaded.get_message().get_email(), "author@example.com");
+        assert_eq!(
+            loaded.get_committer().to_string(),
+            "A U Thor <author@example.com> 1234567890 +0200"
+        );
+    }
+
+    #[test]
+    fn extra_headers_round_trip() {
+        let data = "tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+                    author A <a@x> 0 +0000\n\
+                    committer A <a@x> 0 +0000\n\
+                    encoding ISO-8859-1\n\
+                    mergetag object 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n \
+                    type tree\n \
+                    tag v1\n \n \
+                    message\n\
+                    gpgsig -----BEGIN PGP SIGNATURE-----\n \n \
+                    abc=\n \
+                    -----END PGP SIGNATURE-----\n\
+                    \n\
+                    subject\n\nbody\n";
+        let commit = Commit::parse(data.as_bytes(), HashAlgorithm::Sha1).unwrap();
+        assert_eq!(commit.get_data(), data.as_bytes());
+        assert_eq!(commit.get_header("encoding"), Some("ISO-8859-1"));
+        assert_eq!(
+            commit.get_header("gpgsig"),
+            Some("-----BEGIN PGP SIGNATURE-----\n\nabc=\n-----END PGP SIGNATURE-----")
+        );
+        assert_eq!(commit.get_message().get_message(), "subject\n\nbody");
+
+        // messages without a final newline are kept as they are
+        let data = &data.as_bytes()[..data.len() - 1];
+        let commit = Commit::parse(data, HashAlgorithm::Sha1).unwrap();
+        assert_eq!(commit.get_data(), data);
+    }
+
+    #[test]
+    fn continuation_without_header_is_rejected() {
+        let data = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n \
+                     stray\nauthor A <a@x> 0 +0000\ncommitter A <a@x> 0 +0000\n\nmsg\n";
+        assert!(Commit::parse(data, HashAlgorithm::Sha1).is_err());
     }
 
-    fn get_oid(&self) -> Option<String> {
-        trace!("Getting oid of blob");
-        Some(self.oid.clone())
+    #[test]
+    fn missing_tree_is_rejected() {
+        let data = b"author A <a@x> 0 +0000\ncommitter A <a@x> 0 +0000\n\nmsg\n";
+        assert!(Commit::parse(data, HashAlgorithm::Sha1).is_err());
     }
 }
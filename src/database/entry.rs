@@ -1,10 +1,23 @@
+use anyhow::{bail, Result};
+use log::*;
 use std::cmp::Ord;
 use std::path::PathBuf;
 
-use crate::FileStat;
+use crate::database::tree::{GITLINK_MODE, TREE_MODE};
+use crate::{FileStat, ObjectId};
 
 const REGULAR_MODE: &str = "100644";
-const EXECUTABLE_MODE: &str = "100744";
+const EXECUTABLE_MODE: &str = "100755";
+const SYMLINK_MODE: &str = "120000";
+
+/// File type bits of a mode and the types a tree can hold.
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFGITLINK: u32 = 0o160000;
+/// Owner execute bit, which makes a regular file executable.
+const S_IXUSR: u32 = 0o100;
 
 /// `Entry` serves to package up information that `Tree` needs to about its
 /// contents:
@@ -17,7 +30,7 @@ pub struct Entry {
     name: String,
 
     /// Object id (hash)
-    oid: String,
+    oid: ObjectId,
 
     /// File mode
     stat: &'static str,
@@ -25,7 +38,7 @@ pub struct Entry {
 
 impl Entry {
     /// Create a new `Entry`.
-    pub fn new(name: PathBuf, oid: String, stat: FileStat) -> Self {
+    pub fn new(name: PathBuf, oid: ObjectId, stat: FileStat) -> Self {
         Self {
             name: name
                 .into_os_string()
@@ -40,14 +53,37 @@ impl Entry {
         }
     }
 
+    /// Create an `Entry` from a mode as it is found in a stored `Tree`.
+    /// Legacy modes written by old versions of git, e.g. `100664`, are
+    /// normalized by their file type like git does.
+    pub fn from_mode(name: String, oid: ObjectId, mode: &str) -> Result<Self> {
+        if mode.is_empty() || mode.len() > 6 || !mode.bytes().all(|b| (b'0'..=b'7').contains(&b)) {
+            bail!("Entry: Malformed mode {:?} for {}", mode, name);
+        }
+        let bits = u32::from_str_radix(mode, 8)?;
+        let stat = match bits & S_IFMT {
+            S_IFREG if bits & S_IXUSR != 0 => EXECUTABLE_MODE,
+            S_IFREG => REGULAR_MODE,
+            S_IFLNK => SYMLINK_MODE,
+            S_IFDIR => TREE_MODE,
+            S_IFGITLINK => GITLINK_MODE,
+            _ => bail!("Entry: Unknown mode {} for {}", mode, name),
+        };
+        if stat != mode {
+            debug!("Normalized mode {} of {} to {}", mode, name, stat);
+        }
+
+        Ok(Self { name, oid, stat })
+    }
+
     /// Get the name of the `Entry`.
     pub fn get_name(&self) -> String {
         self.name.clone()
     }
 
     /// Get the oid of the `Entry`.
-    pub fn get_oid(&self) -> String {
-        self.oid.clone()
+    pub fn get_oid(&self) -> ObjectId {
+        self.oid
     }
 
     /// Get File mode
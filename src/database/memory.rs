@@ -0,0 +1,70 @@
+use crate::*;
+use anyhow::{bail, Result};
+use log::*;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// An `ObjectStore` that keeps all objects in memory, for building objects
+/// without a repository on disk.
+#[derive(Default)]
+pub struct MemoryStore {
+    /// Hash algorithm of the oids.
+    algorithm: HashAlgorithm,
+
+    /// Type and data of every object.
+    objects: RefCell<BTreeMap<ObjectId, (Types, Vec<u8>)>>,
+}
+
+impl MemoryStore {
+    /// Create an empty `MemoryStore` hashing objects with `algorithm`.
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        trace!("Creating memory store");
+        Self {
+            algorithm,
+            objects: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// Number of objects in the store.
+    pub fn len(&self) -> usize {
+        self.objects.borrow().len()
+    }
+
+    /// Is the store empty.
+    pub fn is_empty(&self) -> bool {
+        self.objects.borrow().is_empty()
+    }
+}
+
+impl ObjectStore for MemoryStore {
+    fn get_algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    fn read(&self, oid: &ObjectId) -> Result<(Types, Vec<u8>)> {
+        match self.objects.borrow().get(oid) {
+            Some(object) => Ok(object.clone()),
+            None => bail!("MemoryStore: Object {} does not exist", oid),
+        }
+    }
+
+    fn write(&self, type_: Types, data: &[u8]) -> Result<ObjectId> {
+        let oid = Database::hash_data(self.algorithm, type_, data);
+        debug!("Storing {} {} in memory", type_.as_string(), oid);
+        self.objects
+            .borrow_mut()
+            .entry(oid)
+            .or_insert_with(|| (type_, data.to_vec()));
+
+        Ok(oid)
+    }
+
+    fn contains(&self, oid: &ObjectId) -> bool {
+        self.objects.borrow().contains_key(oid)
+    }
+
+    fn iter(&self) -> Result<Box<dyn Iterator<Item = ObjectId> + '_>> {
+        let oids: Vec<ObjectId> = self.objects.borrow().keys().copied().collect();
+        Ok(Box::new(oids.into_iter()))
+    }
+}
@@ -14,6 +14,11 @@ pub struct Message {
 }
 
 impl Message {
+    /// Create a new `Message` from an `Author` and the message text.
+    pub fn new(author: Author, message: String) -> Self {
+        Self { author, message }
+    }
+
     /// Get author's name.
     pub fn get_name(&self) -> String {
         self.author.name.clone()
@@ -29,6 +34,18 @@ impl Message {
         self.message.clone()
     }
 
+    /// Get the subject, the first paragraph of the message joined into one
+    /// line.
+    pub fn get_subject(&self) -> String {
+        self.message
+            .trim_start()
+            .lines()
+            .take_while(|line| !line.trim().is_empty())
+            .map(|line| line.trim())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+
     /// Get author.
     pub fn get_author(&self) -> Author {
         self.author.clone()
@@ -8,8 +8,49 @@ pub trait Object {
     fn get_data(&self) -> Vec<u8>;
 
     /// Set the `Object` id.
-    fn set_oid(&mut self, hash: String);
+    fn set_oid(&mut self, hash: ObjectId);
 
     /// Get the `Object` id.
-    fn get_oid(&self) -> Option<String>;
+    fn get_oid(&self) -> Option<ObjectId>;
+}
+
+/// An `Object` loaded back from the `Database`.
+pub enum ParsedObject {
+    Blob(Blob),
+    Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
+}
+
+impl ParsedObject {
+    /// Parse the data of an object of type `type_`, oids are hashed with
+    /// `algorithm`.
+    pub fn parse(type_: Types, data: Vec<u8>, algorithm: HashAlgorithm) -> anyhow::Result<Self> {
+        Ok(match type_ {
+            Types::Blob => Self::Blob(Blob::parse(data)),
+            Types::Tree => Self::Tree(Tree::parse(&data, algorithm)?),
+            Types::Commit => Self::Commit(Commit::parse(&data, algorithm)?),
+            Types::Tag => Self::Tag(Tag::parse(&data, algorithm)?),
+        })
+    }
+
+    /// Return the type of the parsed object.
+    pub fn get_type(&self) -> Types {
+        match self {
+            Self::Blob(_) => Types::Blob,
+            Self::Tree(_) => Types::Tree,
+            Self::Commit(_) => Types::Commit,
+            Self::Tag(_) => Types::Tag,
+        }
+    }
+
+    /// Set the oid of the parsed object.
+    pub fn set_oid(&mut self, hash: ObjectId) {
+        match self {
+            Self::Blob(b) => b.set_oid(hash),
+            Self::Tree(t) => t.set_oid(hash),
+            Self::Commit(c) => c.set_oid(hash),
+            Self::Tag(t) => t.set_oid(hash),
+        }
+    }
 }
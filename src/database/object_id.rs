@@ -0,0 +1,130 @@
+use anyhow::{bail, Context, Error, Result};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::HashAlgorithm;
+
+/// Length of the longest raw hash we support, that of SHA-256.
+const MAX_HASH_LEN: usize = 32;
+
+/// A validated object id, the raw hash of an object together with the
+/// `HashAlgorithm` that produced it.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjectId {
+    /// Hash algorithm the oid was computed with.
+    algorithm: HashAlgorithm,
+
+    /// Raw hash, padded with zeros beyond the output length of `algorithm`.
+    bytes: [u8; MAX_HASH_LEN],
+}
+
+impl ObjectId {
+    /// Create an `ObjectId` from a raw hash of `algorithm`.
+    pub fn from_bytes(bytes: &[u8], algorithm: HashAlgorithm) -> Result<Self> {
+        if bytes.len() != algorithm.output_len() {
+            bail!(
+                "ObjectId: {} bytes are not a {} hash",
+                bytes.len(),
+                algorithm.name()
+            );
+        }
+        let mut oid = Self::null(algorithm);
+        oid.bytes[..bytes.len()].copy_from_slice(bytes);
+
+        Ok(oid)
+    }
+
+    /// Parse a full hex oid of `algorithm`.
+    pub fn from_hex(hex: &str, algorithm: HashAlgorithm) -> Result<Self> {
+        if !algorithm.is_oid(hex) {
+            bail!(
+                "ObjectId: {:?} is not a {} object id",
+                hex,
+                algorithm.name()
+            );
+        }
+        let bytes = hex::decode(hex).with_context(|| "ObjectId: Could not decode hex")?;
+
+        Self::from_bytes(&bytes, algorithm)
+    }
+
+    /// The oid consisting of zeros only, standing for no object.
+    pub fn null(algorithm: HashAlgorithm) -> Self {
+        Self {
+            algorithm,
+            bytes: [0; MAX_HASH_LEN],
+        }
+    }
+
+    /// Is this the null oid.
+    pub fn is_null(&self) -> bool {
+        self.bytes.iter().all(|&b| b == 0)
+    }
+
+    /// Get the hash algorithm of the oid.
+    pub fn get_algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Get the raw hash.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.algorithm.output_len()]
+    }
+
+    /// Get the full hex form.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.as_bytes())
+    }
+
+    /// Get the first `len` characters of the hex form.
+    pub fn abbrev(&self, len: usize) -> String {
+        let mut hex = self.to_hex();
+        hex.truncate(len);
+        hex
+    }
+
+    /// Does the hex form start with the hex `prefix`, in any case.
+    pub fn starts_with(&self, prefix: &str) -> bool {
+        self.to_hex().starts_with(&prefix.to_lowercase())
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = Error;
+
+    /// Parse a full hex oid, the algorithm is determined by its length.
+    fn from_str(s: &str) -> Result<Self> {
+        match HashAlgorithm::from_hex_len(s.len()) {
+            Some(algorithm) => Self::from_hex(s, algorithm),
+            None => bail!("ObjectId: {:?} is not an object id", s),
+        }
+    }
+}
+
+impl Ord for ObjectId {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_bytes()
+            .cmp(other.as_bytes())
+            .then_with(|| self.algorithm.name().cmp(other.algorithm.name()))
+    }
+}
+
+impl PartialOrd for ObjectId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // honour width and alignment, e.g. in `{:>40}`
+        f.pad(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjectId({})", self.to_hex())
+    }
+}
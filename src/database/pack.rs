@@ -0,0 +1,445 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::ZlibDecoder;
+use log::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::{HashAlgorithm, ObjectId, Types};
+
+/// Every pack starts with this signature.
+pub const PACK_SIGNATURE: &[u8; 4] = b"PACK";
+pub const PACK_VERSION: u32 = 2;
+/// Length of the pack header: signature, version and object count.
+pub const PACK_HEADER_LEN: u64 = 12;
+
+/// Type numbers of the entries in a pack.
+pub const OBJ_COMMIT: u8 = 1;
+pub const OBJ_TREE: u8 = 2;
+pub const OBJ_BLOB: u8 = 3;
+pub const OBJ_TAG: u8 = 4;
+pub const OBJ_OFS_DELTA: u8 = 6;
+pub const OBJ_REF_DELTA: u8 = 7;
+
+/// Longest delta chain we follow before assuming the pack is corrupt.
+const MAX_DELTA_DEPTH: usize = 10_000;
+/// Upper bound of bytes kept in the base cache.
+const BASE_CACHE_LIMIT: usize = 96 * 1024 * 1024;
+
+/// What a delta entry is based on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeltaBase {
+    /// The entry at this offset of the same pack.
+    Offset(u64),
+    /// The object with this oid.
+    Oid(ObjectId),
+}
+
+/// The header of an entry in a pack.
+#[derive(Debug)]
+pub struct EntryHeader {
+    /// One of the `OBJ_*` type numbers.
+    pub kind: u8,
+    /// Size of the inflated data, which is the delta itself for deltas.
+    pub size: usize,
+    pub base: Option<DeltaBase>,
+    /// Offset of the compressed data following the header.
+    pub data_offset: u64,
+}
+
+/// Reconstructed objects that served as delta bases, keyed by offset.
+#[derive(Default)]
+struct BaseCache {
+    objects: HashMap<u64, (Types, Vec<u8>)>,
+    size: usize,
+}
+
+impl BaseCache {
+    fn get(&self, offset: u64) -> Option<(Types, Vec<u8>)> {
+        self.objects.get(&offset).cloned()
+    }
+
+    /// Remember an object, dropping everything once the limit is reached.
+    fn insert(&mut self, offset: u64, type_: Types, data: &[u8]) {
+        if data.len() > BASE_CACHE_LIMIT || self.objects.contains_key(&offset) {
+            return;
+        }
+        if self.size + data.len() > BASE_CACHE_LIMIT {
+            self.objects.clear();
+            self.size = 0;
+        }
+        self.size += data.len();
+        self.objects.insert(offset, (type_, data.to_vec()));
+    }
+}
+
+/// A `.pack` in `objects/pack` together with its `.idx`.
+pub struct Pack {
+    pack_path: PathBuf,
+    algorithm: HashAlgorithm,
+    index: PackIndex,
+    file: RefCell<File>,
+    /// Length of the pack file.
+    len: u64,
+    cache: RefCell<BaseCache>,
+}
+
+impl Pack {
+    /// Open the pack belonging to the index at `idx_path`, holding objects
+    /// hashed with `algorithm`.
+    pub fn open(idx_path: &Path, algorithm: HashAlgorithm) -> Result<Self> {
+        trace!("Opening pack of {:?}", idx_path);
+        let pack_path = idx_path.with_extension("pack");
+        let index = PackIndex::load(idx_path, algorithm)?;
+        let mut file = File::open(&pack_path)
+            .with_context(|| format!("Pack: Could not open {:?}", pack_path))?;
+        let len = file
+            .metadata()
+            .with_context(|| format!("Pack: Could not stat {:?}", pack_path))?
+            .len();
+
+        let mut header = [0u8; PACK_HEADER_LEN as usize];
+        file.read_exact(&mut header)
+            .with_context(|| format!("Pack: {:?} is truncated", pack_path))?;
+        if &header[0..4] != PACK_SIGNATURE {
+            bail!("Pack: {:?} has no pack signature", pack_path);
+        }
+        let version = u32::from_be_bytes(header[4..8].try_into().unwrap());
+        if version != PACK_VERSION && version != 3 {
+            bail!(
+                "Pack: Version {} of {:?} is not supported",
+                version,
+                pack_path
+            );
+        }
+        let count = u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize;
+        if count != index.len() {
+            bail!(
+                "Pack: {:?} holds {} objects but its index {}",
+                pack_path,
+                count,
+                index.len()
+            );
+        }
+        debug!("Pack {:?} holds {} objects", pack_path, count);
+
+        Ok(Self {
+            pack_path,
+            algorithm,
+            index,
+            file: RefCell::new(file),
+            len,
+            cache: RefCell::new(BaseCache::default()),
+        })
+    }
+
+    /// Path of the `.pack` file.
+    pub fn get_pack_path(&self) -> &PathBuf {
+        &self.pack_path
+    }
+
+    /// Hash algorithm of the objects in this pack.
+    pub fn get_algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// The index of this pack.
+    pub fn get_index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    /// Does the pack hold the object `oid`.
+    pub fn contains(&self, oid: &ObjectId) -> bool {
+        self.index.find(oid).is_some()
+    }
+
+    /// Read the object `oid`, `None` if it is not in this pack.
+    pub fn read_object(&self, oid: &ObjectId) -> Result<Option<(Types, Vec<u8>)>> {
+        match self.index.find(oid) {
+            Some(offset) => self.read_at(offset).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Read type and size of the object `oid` without reconstructing it,
+    /// `None` if it is not in this pack.
+    pub fn read_header(&self, oid: &ObjectId) -> Result<Option<(Types, usize)>> {
+        let offset = match self.index.find(oid) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+
+        // the size comes from the outermost delta, the type from the base
+        let header = self.read_entry_header(offset)?;
+        let size = match header.base {
+            Some(_) => {
+                let start = self.inflate(header.data_offset, header.size.min(20), false)?;
+                delta::sizes(&start)?.1
+            }
+            None => header.size,
+        };
+        let mut header = header;
+        let mut depth = 0;
+        while let Some(base) = &header.base {
+            depth += 1;
+            if depth > MAX_DELTA_DEPTH {
+                bail!("Pack: Delta chain of {} is too long", oid);
+            }
+            let offset = self.base_offset(base)?;
+            header = self.read_entry_header(offset)?;
+        }
+
+        Ok(Some((pack_type(header.kind)?, size)))
+    }
+
+    /// Size of the packed entry of `oid`, `None` if it is not in this pack.
+    pub fn disk_size(&self, oid: &ObjectId) -> Option<u64> {
+        let offset = self.index.find(oid)?;
+        let end = self
+            .index
+            .next_offset(offset)
+            .unwrap_or(self.len - self.algorithm.output_len() as u64);
+
+        Some(end - offset)
+    }
+
+    /// Oid of the object `oid` is a delta against.
+    pub fn delta_base(&self, oid: &ObjectId) -> Result<Option<ObjectId>> {
+        let offset = match self.index.find(oid) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+
+        Ok(match self.read_entry_header(offset)?.base {
+            Some(DeltaBase::Offset(base)) => Some(
+                self.index
+                    .oid_at(base)
+                    .with_context(|| format!("Pack: No object at offset {}", base))?,
+            ),
+            Some(DeltaBase::Oid(base)) => Some(base),
+            None => None,
+        })
+    }
+
+    /// Check the trailing checksum of the pack against its content and
+    /// against the checksum recorded in its index.
+    pub fn verify_checksum(&self) -> Result<()> {
+        trace!("Verifying checksum of {:?}", self.pack_path);
+        let checksum_len = self.algorithm.output_len();
+        if self.len < PACK_HEADER_LEN + checksum_len as u64 {
+            bail!("Pack: {:?} is truncated", self.pack_path);
+        }
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(0))
+            .with_context(|| "Pack: Could not seek")?;
+        let mut reader = BufReader::new(&mut *file);
+
+        let mut hasher = self.algorithm.context();
+        let mut content = (&mut reader).take(self.len - checksum_len as u64);
+        let mut buffer = [0u8; 8192];
+        loop {
+            let len = content
+                .read(&mut buffer)
+                .with_context(|| "Pack: Could not read pack")?;
+            if len == 0 {
+                break;
+            }
+            hasher.update(&buffer[..len]);
+        }
+        let mut trailer = vec![0u8; checksum_len];
+        reader
+            .read_exact(&mut trailer)
+            .with_context(|| "Pack: Could not read checksum")?;
+
+        if hasher.finish().as_ref() != trailer.as_slice() {
+            bail!("Pack: Checksum of {:?} does not match", self.pack_path);
+        }
+        if self.index.get_pack_checksum() != trailer {
+            bail!(
+                "Pack: Index checksum of {:?} does not match",
+                self.pack_path
+            );
+        }
+
+        Ok(())
+    }
+
+    /// CRC32 of the raw, packed entry of `oid`.
+    pub fn entry_crc32(&self, oid: &ObjectId) -> Result<u32> {
+        let offset = self
+            .index
+            .find(oid)
+            .with_context(|| format!("Pack: {} is not in the pack", oid))?;
+        let len = self.disk_size(oid).unwrap_or(0);
+
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(offset))
+            .with_context(|| "Pack: Could not seek")?;
+        let mut raw = Vec::new();
+        (&mut *file)
+            .take(len)
+            .read_to_end(&mut raw)
+            .with_context(|| format!("Pack: Could not read entry of {}", oid))?;
+
+        Ok(crc32fast::hash(&raw))
+    }
+
+    /// Reconstruct the object whose entry starts at `offset`.
+    pub fn read_at(&self, offset: u64) -> Result<(Types, Vec<u8>)> {
+        trace!("Reading pack entry at {}", offset);
+        // walk down the delta chain until we hit a base or a cached object
+        let mut deltas = Vec::new();
+        let mut current = offset;
+        let (type_, mut data) = loop {
+            if let Some(cached) = self.cache.borrow().get(current) {
+                break cached;
+            }
+            if deltas.len() > MAX_DELTA_DEPTH {
+                bail!("Pack: Delta chain at {} is too long", offset);
+            }
+
+            let header = self.read_entry_header(current)?;
+            let data = self.inflate(header.data_offset, header.size, true)?;
+            match &header.base {
+                Some(base) => {
+                    let base = self.base_offset(base)?;
+                    deltas.push((base, data));
+                    current = base;
+                }
+                None => break (pack_type(header.kind)?, data),
+            }
+        };
+
+        // apply the deltas from the base upwards, caching every base
+        for (base, delta) in deltas.iter().rev() {
+            self.cache.borrow_mut().insert(*base, type_, &data);
+            data = delta::apply(&data, delta)
+                .with_context(|| format!("Pack: Could not apply delta at {}", offset))?;
+        }
+
+        Ok((type_, data))
+    }
+
+    /// Parse the header of the entry starting at `offset`.
+    pub fn read_entry_header(&self, offset: u64) -> Result<EntryHeader> {
+        if offset < PACK_HEADER_LEN || offset >= self.len {
+            bail!("Pack: Offset {} is out of bounds", offset);
+        }
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(offset))
+            .with_context(|| "Pack: Could not seek")?;
+        let mut reader = BufReader::new(&mut *file);
+        let mut next = || -> Result<u8> {
+            let mut byte = [0u8; 1];
+            reader
+                .read_exact(&mut byte)
+                .with_context(|| format!("Pack: Entry at {} is truncated", offset))?;
+            Ok(byte[0])
+        };
+
+        // type and size: 3 type bits, then the size in little-endian 7-bit groups
+        let mut consumed = 1;
+        let mut byte = next()?;
+        let kind = (byte >> 4) & 0x7;
+        let mut size = (byte & 0xf) as usize;
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            if shift > 60 {
+                bail!("Pack: Size of entry at {} is too large", offset);
+            }
+            byte = next()?;
+            consumed += 1;
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+        }
+
+        let base = match kind {
+            OBJ_OFS_DELTA => {
+                // big-endian 7-bit groups, each continuation adds one
+                byte = next()?;
+                consumed += 1;
+                let mut distance = (byte & 0x7f) as u64;
+                while byte & 0x80 != 0 {
+                    if distance >= 1 << 56 {
+                        bail!("Pack: Delta offset at {} is too large", offset);
+                    }
+                    byte = next()?;
+                    consumed += 1;
+                    distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+                }
+                if distance == 0 || distance > offset {
+                    bail!("Pack: Delta base of entry at {} is out of bounds", offset);
+                }
+                Some(DeltaBase::Offset(offset - distance))
+            }
+            OBJ_REF_DELTA => {
+                let mut oid = vec![0u8; self.algorithm.output_len()];
+                for b in oid.iter_mut() {
+                    *b = next()?;
+                }
+                consumed += oid.len() as u64;
+                Some(DeltaBase::Oid(ObjectId::from_bytes(&oid, self.algorithm)?))
+            }
+            OBJ_COMMIT | OBJ_TREE | OBJ_BLOB | OBJ_TAG => None,
+            kind => bail!("Pack: Entry at {} has invalid type {}", offset, kind),
+        };
+
+        Ok(EntryHeader {
+            kind,
+            size,
+            base,
+            data_offset: offset + consumed,
+        })
+    }
+
+    /// Inflate `size` bytes of the compressed data at `offset`.
+    /// With `exact` the data must not be longer than `size`.
+    fn inflate(&self, offset: u64, size: usize, exact: bool) -> Result<Vec<u8>> {
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(offset))
+            .with_context(|| "Pack: Could not seek")?;
+        let mut decoder = ZlibDecoder::new(BufReader::new(&mut *file));
+        // the size is untrusted, only reserve what the pack could hold
+        let mut data = Vec::with_capacity(size.min(self.len.saturating_sub(offset) as usize));
+        (&mut decoder)
+            .take(size as u64)
+            .read_to_end(&mut data)
+            .with_context(|| format!("Pack: Zlib decoding issues at {}", offset))?;
+        if data.len() != size || (exact && decoder.read(&mut [0u8; 1])? != 0) {
+            bail!("Pack: Entry data at {} does not have size {}", offset, size);
+        }
+
+        Ok(data)
+    }
+
+    /// Offset of the entry a delta is based on.
+    fn base_offset(&self, base: &DeltaBase) -> Result<u64> {
+        match base {
+            DeltaBase::Offset(offset) => Ok(*offset),
+            DeltaBase::Oid(oid) => self
+                .index
+                .find(oid)
+                .with_context(|| format!("Pack: Delta base {} is not in the pack", oid)),
+        }
+    }
+}
+
+/// Map the type number of a non-delta entry to its `Types`.
+pub fn pack_type(kind: u8) -> Result<Types> {
+    match kind {
+        OBJ_COMMIT => Ok(Types::Commit),
+        OBJ_TREE => Ok(Types::Tree),
+        OBJ_BLOB => Ok(Types::Blob),
+        OBJ_TAG => Ok(Types::Tag),
+        kind => bail!("Pack: Objects of type {} are not supported", kind),
+    }
+}
+
+pub mod index;
+pub use index::PackIndex;
+pub mod delta;
+pub mod writer;
+pub use writer::PackWriter;
+pub mod indexer;
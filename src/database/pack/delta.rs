@@ -0,0 +1,360 @@
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::collections::HashMap;
+
+/// Copy instructions with a size of zero copy this many bytes.
+const MAX_COPY_SIZE: usize = 0x10000;
+/// Largest size a single copy instruction can encode.
+const MAX_COPY_CHUNK: usize = 0xff_ffff;
+/// Largest number of bytes a single insert instruction can carry.
+const MAX_INSERT: usize = 0x7f;
+/// Length of the blocks of the base that are indexed to find matches.
+const BLOCK_LEN: usize = 16;
+/// Candidate positions kept per block hash.
+const MAX_CANDIDATES: usize = 8;
+
+/// Read a little-endian base-128 size from `delta` at `pos`, advancing `pos`.
+pub fn read_size(delta: &[u8], pos: &mut usize) -> Result<usize> {
+    let mut size = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *delta
+            .get(*pos)
+            .with_context(|| "Delta: Size is truncated")?;
+        *pos += 1;
+        if shift > 63 {
+            bail!("Delta: Size is too large");
+        }
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
+
+/// Append `size` in little-endian base-128 to `out`.
+pub fn write_size(out: &mut Vec<u8>, mut size: usize) {
+    while size >= 0x80 {
+        out.push((size & 0x7f) as u8 | 0x80);
+        size >>= 7;
+    }
+    out.push(size as u8);
+}
+
+/// Return the sizes of the base and the result stored at the start of `delta`.
+pub fn sizes(delta: &[u8]) -> Result<(usize, usize)> {
+    let mut pos = 0;
+    let base_size = read_size(delta, &mut pos)?;
+    let result_size = read_size(delta, &mut pos)?;
+
+    Ok((base_size, result_size))
+}
+
+/// Reconstruct an object by applying the instructions of `delta` to `base`.
+pub fn apply(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    trace!("Applying delta of {} bytes", delta.len());
+    let mut pos = 0;
+    let base_size = read_size(delta, &mut pos)?;
+    if base_size != base.len() {
+        bail!(
+            "Delta: Base has size {} but delta expects {}",
+            base.len(),
+            base_size
+        );
+    }
+    let result_size = read_size(delta, &mut pos)?;
+
+    // the size is untrusted, reserve no more than base and delta could give
+    let mut result = Vec::with_capacity(result_size.min(base.len() + delta.len()));
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        if op & 0x80 != 0 {
+            // copy from base, the low bits select which offset and size bytes follow
+            let mut args = [0usize; 7];
+            for (bit, arg) in args.iter_mut().enumerate() {
+                if op & (1 << bit) != 0 {
+                    *arg = *delta
+                        .get(pos)
+                        .with_context(|| "Delta: Copy instruction is truncated")?
+                        as usize;
+                    pos += 1;
+                }
+            }
+            let offset = args[0] | args[1] << 8 | args[2] << 16 | args[3] << 24;
+            let size = match args[4] | args[5] << 8 | args[6] << 16 {
+                0 => MAX_COPY_SIZE,
+                size => size,
+            };
+            let chunk = base
+                .get(offset..offset + size)
+                .with_context(|| "Delta: Copy instruction is out of bounds")?;
+            result.extend_from_slice(chunk);
+        } else if op != 0 {
+            // insert the next `op` bytes of the delta
+            let chunk = delta
+                .get(pos..pos + op as usize)
+                .with_context(|| "Delta: Insert instruction is truncated")?;
+            result.extend_from_slice(chunk);
+            pos += op as usize;
+        } else {
+            bail!("Delta: Unexpected instruction 0");
+        }
+    }
+
+    if result.len() != result_size {
+        bail!(
+            "Delta: Result has size {} but delta expects {}",
+            result.len(),
+            result_size
+        );
+    }
+
+    Ok(result)
+}
+
+/// Create a delta that turns `base` into `target`.
+/// Blocks of `base` are indexed by hash and every match found in `target` is
+/// extended as far as possible and emitted as a copy, the rest is inserted.
+pub fn create(base: &[u8], target: &[u8]) -> Vec<u8> {
+    trace!(
+        "Creating delta of {} against {} bytes",
+        target.len(),
+        base.len()
+    );
+    let mut delta = Vec::new();
+    write_size(&mut delta, base.len());
+    write_size(&mut delta, target.len());
+
+    let mut blocks: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for start in (0..base.len().saturating_sub(BLOCK_LEN - 1)).step_by(BLOCK_LEN) {
+        let candidates = blocks.entry(&base[start..start + BLOCK_LEN]).or_default();
+        if candidates.len() < MAX_CANDIDATES {
+            candidates.push(start);
+        }
+    }
+
+    let mut pending = 0;
+    let mut pos = 0;
+    while pos < target.len() {
+        let best = match target.get(pos..pos + BLOCK_LEN) {
+            Some(block) => blocks
+                .get(block)
+                .into_iter()
+                .flatten()
+                .map(|&start| {
+                    let len = base[start..]
+                        .iter()
+                        .zip(&target[pos..])
+                        .take_while(|(a, b)| a == b)
+                        .count();
+                    (len, start)
+                })
+                .max_by_key(|&(len, start)| (len, std::cmp::Reverse(start))),
+            None => None,
+        };
+
+        match best {
+            Some((len, start)) => {
+                // grow the match backwards into the bytes not yet emitted
+                let mut start = start;
+                let mut from = pos;
+                let mut len = len;
+                while from > pending && start > 0 && base[start - 1] == target[from - 1] {
+                    start -= 1;
+                    from -= 1;
+                    len += 1;
+                }
+                write_insert(&mut delta, &target[pending..from]);
+                write_copy(&mut delta, start, len);
+                pos = from + len;
+                pending = pos;
+            }
+            None => pos += 1,
+        }
+    }
+    write_insert(&mut delta, &target[pending..]);
+    debug!("Delta has {} bytes", delta.len());
+
+    delta
+}
+
+/// Append insert instructions carrying `data`.
+fn write_insert(delta: &mut Vec<u8>, data: &[u8]) {
+    for chunk in data.chunks(MAX_INSERT) {
+        delta.push(chunk.len() as u8);
+        delta.extend_from_slice(chunk);
+    }
+}
+
+/// Append copy instructions for `len` bytes of the base at `offset`.
+fn write_copy(delta: &mut Vec<u8>, mut offset: usize, mut len: usize) {
+    while len > 0 {
+        let size = len.min(MAX_COPY_CHUNK);
+        let mut op = 0x80u8;
+        let mut args = Vec::new();
+        for (bit, value) in [
+            (0, offset),
+            (1, offset >> 8),
+            (2, offset >> 16),
+            (3, offset >> 24),
+            (4, size),
+            (5, size >> 8),
+            (6, size >> 16),
+        ] {
+            if value & 0xff != 0 {
+                op |= 1 << bit;
+                args.push(value as u8);
+            }
+        }
+        delta.push(op);
+        delta.extend(args);
+
+        offset += size;
+        len -= size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random bytes.
+    fn noise(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    fn round_trip(base: &[u8], target: &[u8]) -> Vec<u8> {
+        let delta = create(base, target);
+        assert_eq!(sizes(&delta).unwrap(), (base.len(), target.len()));
+        assert_eq!(apply(base, &delta).unwrap(), target);
+
+        delta
+    }
+
+    #[test]
+    fn sizes_round_trip() {
+        for size in [0, 1, 0x7f, 0x80, 0x3fff, 0x4000, usize::MAX >> 1] {
+            let mut out = Vec::new();
+            write_size(&mut out, size);
+            let mut pos = 0;
+            assert_eq!(read_size(&out, &mut pos).unwrap(), size);
+            assert_eq!(pos, out.len());
+        }
+    }
+
+    #[test]
+    fn create_apply_round_trips() {
+        let base = noise(4096, 1);
+        round_trip(&base, &base);
+        round_trip(&base, b"");
+        round_trip(b"", &base);
+        round_trip(b"", b"");
+        round_trip(&base, &noise(1000, 2));
+
+        // an edit in the middle, an insert at the front and a cut at the end
+        let mut edited = base.clone();
+        edited[2000..2010].copy_from_slice(b"0123456789");
+        let delta = round_trip(&base, &edited);
+        assert!(delta.len() < 100, "delta has {} bytes", delta.len());
+
+        let mut prefixed = noise(300, 3);
+        prefixed.extend_from_slice(&base[..3000]);
+        let delta = round_trip(&base, &prefixed);
+        assert!(delta.len() < 400, "delta has {} bytes", delta.len());
+
+        // blocks moved around
+        let mut moved = base[2048..].to_vec();
+        moved.extend_from_slice(&base[..2048]);
+        round_trip(&base, &moved);
+    }
+
+    #[test]
+    fn copies_with_large_offsets() {
+        // matches beyond 16 MiB need all four offset bytes
+        let mut base = vec![0u8; 0x0100_0000];
+        base.extend(noise(4096, 4));
+        let target = base[0x0100_0000 + 10..].to_vec();
+        let delta = round_trip(&base, &target);
+        assert!(delta.len() < 32, "delta has {} bytes", delta.len());
+    }
+
+    #[test]
+    fn copies_longer_than_one_instruction() {
+        // copies are split at 0xffffff bytes
+        let base: Vec<u8> = (0..0x0100_0100).map(|i| (i % 251) as u8).collect();
+        let mut delta = Vec::new();
+        write_size(&mut delta, base.len());
+        write_size(&mut delta, base.len() - 1);
+        write_copy(&mut delta, 1, base.len() - 1);
+        // two copies of at most 8 bytes each after the sizes
+        assert!(delta.len() <= 8 + 16, "delta has {} bytes", delta.len());
+        assert_eq!(apply(&base, &delta).unwrap(), &base[1..]);
+    }
+
+    #[test]
+    fn copy_encoding() {
+        let mut delta = Vec::new();
+        write_copy(&mut delta, 0x0102_0304, 0x10);
+        assert_eq!(delta, [0x80 | 0x0f | 0x10, 0x04, 0x03, 0x02, 0x01, 0x10]);
+
+        // zero bytes are left out, offset 0 needs no argument at all
+        let mut delta = Vec::new();
+        write_copy(&mut delta, 0x0100_0000, 0x0100);
+        assert_eq!(delta, [0x80 | 0x08 | 0x20, 0x01, 0x01]);
+    }
+
+    #[test]
+    fn copy_of_zero_size_copies_64k() {
+        let base = noise(0x10000 + 5, 6);
+        let mut delta = Vec::new();
+        write_size(&mut delta, base.len());
+        write_size(&mut delta, 0x10000);
+        // offset 5, no size bytes
+        delta.extend([0x80 | 0x01, 0x05]);
+        assert_eq!(apply(&base, &delta).unwrap(), &base[5..]);
+    }
+
+    #[test]
+    fn corrupt_deltas_are_rejected() {
+        let base = noise(4096, 7);
+        let mut target = base.clone();
+        target.extend(noise(200, 8));
+        let delta = create(&base, &target);
+
+        // truncated at every point
+        for len in 0..delta.len() {
+            assert!(apply(&base, &delta[..len]).is_err(), "truncated to {}", len);
+        }
+        // wrong base
+        assert!(apply(&base[1..], &delta).is_err());
+
+        let header = |base_size: usize, result_size: usize| {
+            let mut delta = Vec::new();
+            write_size(&mut delta, base_size);
+            write_size(&mut delta, result_size);
+            delta
+        };
+        // copy beyond the end of the base
+        let mut out_of_bounds = header(base.len(), 16);
+        out_of_bounds.extend([0x80 | 0x02 | 0x10, 0x10, 0x10]);
+        assert!(apply(&base, &out_of_bounds).is_err());
+        // the reserved instruction 0
+        let mut reserved = header(base.len(), 1);
+        reserved.extend([0x00, 0x01, b'x']);
+        assert!(apply(&base, &reserved).is_err());
+        // result shorter than announced
+        let mut short = header(base.len(), 5);
+        short.extend([0x01, b'x']);
+        assert!(apply(&base, &short).is_err());
+        // size that does not terminate
+        assert!(sizes(&[0xff; 16]).is_err());
+    }
+}
@@ -0,0 +1,371 @@
+use crate::{HashAlgorithm, ObjectId};
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Every version 2 index starts with this magic number.
+pub const IDX_SIGNATURE: &[u8; 4] = b"\xfftOc";
+pub const IDX_VERSION: u32 = 2;
+/// Offsets with this bit set point into the table of 64-bit offsets.
+pub const LARGE_OFFSET: u32 = 0x8000_0000;
+/// Number of entries in the fan-out table, one per leading byte.
+pub const FANOUT_SIZE: usize = 256;
+
+/// The version 2 `.idx` belonging to a pack.
+/// It maps every oid in the pack to the offset of its entry.
+pub struct PackIndex {
+    /// `fanout[b]` is the number of oids whose first byte is at most `b`.
+    fanout: Vec<u32>,
+    /// Sorted oids of all objects in the pack.
+    oids: Vec<ObjectId>,
+    /// CRC32 of the packed data of every object.
+    crcs: Vec<u32>,
+    /// Offset of every object in the pack.
+    offsets: Vec<u64>,
+    /// Offsets in ascending order with the position of their oid.
+    by_offset: Vec<(u64, usize)>,
+    /// Checksum of the pack this index belongs to.
+    pack_checksum: Vec<u8>,
+}
+
+impl PackIndex {
+    /// Load the index at `path` of a pack with oids hashed by `algorithm`.
+    pub fn load(path: &Path, algorithm: HashAlgorithm) -> Result<Self> {
+        trace!("Loading pack index {:?}", path);
+        let mut data = Vec::new();
+        File::open(path)
+            .with_context(|| format!("PackIndex: Could not open {:?}", path))?
+            .read_to_end(&mut data)
+            .with_context(|| format!("PackIndex: Could not read {:?}", path))?;
+
+        Self::parse(&data, algorithm).with_context(|| format!("PackIndex: {:?} is malformed", path))
+    }
+
+    /// Parse the content of an index file.
+    pub fn parse(data: &[u8], algorithm: HashAlgorithm) -> Result<Self> {
+        let hash_len = algorithm.output_len();
+        let header_len = 8 + 4 * FANOUT_SIZE;
+        if data.len() < header_len + 2 * hash_len {
+            bail!("PackIndex: Index is truncated");
+        }
+        if &data[0..4] != IDX_SIGNATURE {
+            bail!("PackIndex: Signature does not match");
+        }
+        let version = read_u32(data, 4);
+        if version != IDX_VERSION {
+            bail!("PackIndex: Version {} is not supported", version);
+        }
+
+        let fanout: Vec<u32> = (0..FANOUT_SIZE)
+            .map(|i| read_u32(data, 8 + 4 * i))
+            .collect();
+        if fanout.windows(2).any(|w| w[0] > w[1]) {
+            bail!("PackIndex: Fan-out table is not monotonic");
+        }
+        let count = fanout[FANOUT_SIZE - 1] as usize;
+
+        let oids_start = header_len;
+        let crcs_start = oids_start + count * hash_len;
+        let offsets_start = crcs_start + 4 * count;
+        let large_start = offsets_start + 4 * count;
+        if data.len() < large_start + 2 * hash_len {
+            bail!("PackIndex: Index is truncated");
+        }
+
+        let oids = (0..count)
+            .map(|i| {
+                let start = oids_start + i * hash_len;
+                ObjectId::from_bytes(&data[start..start + hash_len], algorithm)
+            })
+            .collect::<Result<Vec<ObjectId>>>()?;
+        if oids.windows(2).any(|w| w[0] >= w[1]) {
+            bail!("PackIndex: Oids are not sorted");
+        }
+        let crcs = (0..count)
+            .map(|i| read_u32(data, crcs_start + 4 * i))
+            .collect();
+
+        let large_count = (data.len() - large_start - 2 * hash_len) / 8;
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            let offset = read_u32(data, offsets_start + 4 * i);
+            if offset & LARGE_OFFSET == 0 {
+                offsets.push(offset as u64);
+                continue;
+            }
+            let large = (offset & !LARGE_OFFSET) as usize;
+            if large >= large_count {
+                bail!("PackIndex: 64-bit offset {} is out of bounds", large);
+            }
+            let pos = large_start + 8 * large;
+            offsets.push((read_u32(data, pos) as u64) << 32 | read_u32(data, pos + 4) as u64);
+        }
+
+        let mut by_offset: Vec<(u64, usize)> = offsets.iter().copied().zip(0..).collect();
+        by_offset.sort_unstable();
+
+        let checksum_start = data.len() - 2 * hash_len;
+        let pack_checksum = data[checksum_start..checksum_start + hash_len].to_vec();
+        debug!("Index holds {} objects", count);
+
+        Ok(Self {
+            fanout,
+            oids,
+            crcs,
+            offsets,
+            by_offset,
+            pack_checksum,
+        })
+    }
+
+    /// Check the trailing checksum of the index file at `path`.
+    pub fn verify_checksum(path: &Path, algorithm: HashAlgorithm) -> Result<()> {
+        let hash_len = algorithm.output_len();
+        let mut data = Vec::new();
+        File::open(path)
+            .with_context(|| format!("PackIndex: Could not open {:?}", path))?
+            .read_to_end(&mut data)
+            .with_context(|| format!("PackIndex: Could not read {:?}", path))?;
+        if data.len() < hash_len {
+            bail!("PackIndex: {:?} is truncated", path);
+        }
+
+        let (content, checksum) = data.split_at(data.len() - hash_len);
+        if algorithm.digest(content) != checksum {
+            bail!("PackIndex: Checksum of {:?} does not match", path);
+        }
+
+        Ok(())
+    }
+
+    /// Oids of all objects in the order they are stored in the pack.
+    pub fn oids_by_offset(&self) -> Vec<(u64, ObjectId)> {
+        self.by_offset
+            .iter()
+            .map(|(offset, pos)| (*offset, self.oids[*pos]))
+            .collect()
+    }
+
+    /// Number of objects in the pack.
+    pub fn len(&self) -> usize {
+        self.oids.len()
+    }
+
+    /// Is the pack empty.
+    pub fn is_empty(&self) -> bool {
+        self.oids.is_empty()
+    }
+
+    /// Sorted oids of all objects in the pack.
+    pub fn get_oids(&self) -> &[ObjectId] {
+        &self.oids
+    }
+
+    /// Checksum of the pack this index belongs to.
+    pub fn get_pack_checksum(&self) -> &[u8] {
+        &self.pack_checksum
+    }
+
+    /// Offset of the object `oid` in the pack.
+    pub fn find(&self, oid: &ObjectId) -> Option<u64> {
+        let range = self.fanout_range(oid.as_bytes()[0]);
+        let pos = self.oids[range.clone()].binary_search(oid).ok()?;
+
+        Some(self.offsets[range.start + pos])
+    }
+
+    /// CRC32 of the packed data of the object `oid`.
+    pub fn crc32(&self, oid: &ObjectId) -> Option<u32> {
+        let pos = self.oids.binary_search(oid).ok()?;
+        Some(self.crcs[pos])
+    }
+
+    /// All oids starting with the hex `prefix` of at least two characters.
+    pub fn prefix_matches(&self, prefix: &str) -> Vec<ObjectId> {
+        let first = match prefix.get(0..2).map(|first| u8::from_str_radix(first, 16)) {
+            Some(Ok(first)) => first,
+            _ => return Vec::new(),
+        };
+        self.oids[self.fanout_range(first)]
+            .iter()
+            .filter(|oid| oid.starts_with(prefix))
+            .copied()
+            .collect()
+    }
+
+    /// Oid of the object stored at `offset`.
+    pub fn oid_at(&self, offset: u64) -> Option<ObjectId> {
+        let pos = self
+            .by_offset
+            .binary_search_by_key(&offset, |(offset, _)| *offset)
+            .ok()?;
+        Some(self.oids[self.by_offset[pos].1])
+    }
+
+    /// Offset of the entry following the one at `offset`, if any.
+    pub fn next_offset(&self, offset: u64) -> Option<u64> {
+        let pos = self
+            .by_offset
+            .partition_point(|(other, _)| *other <= offset);
+        self.by_offset.get(pos).map(|(offset, _)| *offset)
+    }
+
+    /// Range of `oids` starting with the byte `first`.
+    fn fanout_range(&self, first: u8) -> std::ops::Range<usize> {
+        let first = first as usize;
+        let start = match first {
+            0 => 0,
+            _ => self.fanout[first - 1] as usize,
+        };
+
+        start..self.fanout[first] as usize
+    }
+}
+
+/// Read a big-endian `u32` at `pos`.
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pack::writer::{write_index, PackEntry};
+    use crate::TestDir;
+
+    /// An oid made of `first` followed by `rest`.
+    fn oid(algorithm: HashAlgorithm, first: u8, rest: u8) -> ObjectId {
+        let mut bytes = vec![rest; algorithm.output_len()];
+        bytes[0] = first;
+        ObjectId::from_bytes(&bytes, algorithm).unwrap()
+    }
+
+    fn entries(algorithm: HashAlgorithm) -> Vec<PackEntry> {
+        [
+            (0x00, 1, 12),
+            (0xff, 2, 0x7fff_ffff),
+            (0x42, 3, 0x8000_0000),
+            (0x42, 4, 0x1_2345_6789),
+            (0x10, 5, 200),
+            (0x42, 0, u64::MAX >> 1),
+        ]
+        .into_iter()
+        .map(|(first, rest, offset)| PackEntry {
+            oid: oid(algorithm, first, rest),
+            offset,
+            crc32: offset as u32 ^ 0xdead_beef,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn write_parse_round_trip() {
+        for algorithm in [HashAlgorithm::Sha1, HashAlgorithm::Sha256] {
+            let entries = entries(algorithm);
+            let pack_checksum = algorithm.digest(b"pack");
+            let data = write_index(&entries, &pack_checksum, algorithm);
+            let index = PackIndex::parse(&data, algorithm).unwrap();
+
+            assert_eq!(index.len(), entries.len());
+            assert_eq!(index.get_pack_checksum(), pack_checksum);
+            assert!(index.get_oids().windows(2).all(|w| w[0] < w[1]));
+            for entry in entries.iter() {
+                assert_eq!(index.find(&entry.oid), Some(entry.offset));
+                assert_eq!(index.crc32(&entry.oid), Some(entry.crc32));
+                assert_eq!(index.oid_at(entry.offset), Some(entry.oid));
+            }
+            assert_eq!(index.find(&oid(algorithm, 0x42, 9)), None);
+            assert_eq!(index.find(&oid(algorithm, 0x43, 3)), None);
+            assert_eq!(index.prefix_matches("42").len(), 3);
+            assert_eq!(index.prefix_matches("ff").len(), 1);
+
+            let offsets: Vec<u64> = index.oids_by_offset().iter().map(|(o, _)| *o).collect();
+            assert_eq!(
+                offsets,
+                [
+                    12,
+                    200,
+                    0x7fff_ffff,
+                    0x8000_0000,
+                    0x1_2345_6789,
+                    u64::MAX >> 1
+                ]
+            );
+            assert_eq!(index.next_offset(200), Some(0x7fff_ffff));
+            assert_eq!(index.next_offset(u64::MAX >> 1), None);
+        }
+    }
+
+    #[test]
+    fn large_offsets_use_the_64_bit_table() {
+        let algorithm = HashAlgorithm::Sha1;
+        let data = write_index(&entries(algorithm), &[0; 20], algorithm);
+        // three offsets need 64 bits
+        let count = 6;
+        let tables = 8 + 4 * FANOUT_SIZE + count * (20 + 4 + 4);
+        assert_eq!(data.len(), tables + 3 * 8 + 2 * 20);
+    }
+
+    #[test]
+    fn empty_index_round_trips() {
+        let algorithm = HashAlgorithm::Sha1;
+        let data = write_index(&[], &[0; 20], algorithm);
+        let index = PackIndex::parse(&data, algorithm).unwrap();
+        assert!(index.is_empty());
+        assert_eq!(index.find(&oid(algorithm, 0, 0)), None);
+    }
+
+    #[test]
+    fn corrupt_index_is_rejected() {
+        let algorithm = HashAlgorithm::Sha1;
+        let data = write_index(&entries(algorithm), &[0; 20], algorithm);
+
+        for len in [0, 8, 8 + 4 * FANOUT_SIZE, data.len() - 3 * 8 - 2 * 20 - 1] {
+            assert!(
+                PackIndex::parse(&data[..len], algorithm).is_err(),
+                "{}",
+                len
+            );
+        }
+
+        let mut signature = data.clone();
+        signature[0] = b'P';
+        assert!(PackIndex::parse(&signature, algorithm).is_err());
+
+        let mut version = data.clone();
+        version[7] = 3;
+        assert!(PackIndex::parse(&version, algorithm).is_err());
+
+        // a fan-out entry larger than the next one
+        let mut fanout = data.clone();
+        fanout[8 + 4 * 0x20 + 3] = 0xff;
+        assert!(PackIndex::parse(&fanout, algorithm).is_err());
+
+        // a 64-bit offset pointing past the table
+        let offsets_start = 8 + 4 * FANOUT_SIZE + 6 * (20 + 4);
+        let mut large = data.clone();
+        let pos = (0..6)
+            .map(|i| offsets_start + 4 * i)
+            .find(|&pos| data[pos] & 0x80 != 0)
+            .unwrap();
+        large[pos..pos + 4].copy_from_slice(&(LARGE_OFFSET | 7).to_be_bytes());
+        assert!(PackIndex::parse(&large, algorithm).is_err());
+    }
+
+    #[test]
+    fn checksum_is_verified() {
+        let dir = TestDir::new();
+        let algorithm = HashAlgorithm::Sha1;
+        let path = dir.path().join("pack.idx");
+        let mut data = write_index(&entries(algorithm), &[0; 20], algorithm);
+        std::fs::write(&path, &data).unwrap();
+        PackIndex::verify_checksum(&path, algorithm).unwrap();
+        assert_eq!(PackIndex::load(&path, algorithm).unwrap().len(), 6);
+
+        data[100] ^= 1;
+        std::fs::write(&path, &data).unwrap();
+        assert!(PackIndex::verify_checksum(&path, algorithm).is_err());
+    }
+}
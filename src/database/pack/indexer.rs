@@ -0,0 +1,334 @@
+use anyhow::{bail, Context, Result};
+use flate2::bufread::ZlibDecoder;
+use log::*;
+use ring::digest;
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::rc::Rc;
+
+use super::writer::PackEntry;
+use super::*;
+use crate::{Database, HashAlgorithm, ObjectId, Types};
+
+/// Wraps the pack being read, hashing every consumed byte, computing the
+/// CRC32 of the current entry and optionally copying the pack to `copy`.
+struct PackStream<R: BufRead, W: Write> {
+    inner: R,
+    algorithm: HashAlgorithm,
+    copy: Option<W>,
+    hasher: digest::Context,
+    crc: crc32fast::Hasher,
+    offset: u64,
+    error: Option<std::io::Error>,
+}
+
+impl<R: BufRead, W: Write> Read for PackStream<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+
+        Ok(len)
+    }
+}
+
+impl<R: BufRead, W: Write> BufRead for PackStream<R, W> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Ok(buf) = self.inner.fill_buf() {
+            let buf = &buf[..amt];
+            self.hasher.update(buf);
+            self.crc.update(buf);
+            if let Some(copy) = self.copy.as_mut() {
+                if let Err(err) = copy.write_all(buf) {
+                    self.error = Some(err);
+                }
+            }
+        }
+        self.offset += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+/// An entry read from the pack, its data is read again when it is needed.
+struct RawEntry {
+    offset: u64,
+    kind: u8,
+    /// Size of the inflated data, which is the delta itself for deltas.
+    size: usize,
+    base: Option<DeltaBase>,
+    /// Offset of the compressed data following the header.
+    data_offset: u64,
+    crc32: u32,
+    /// Oid of a non-delta entry, hashed while it is read.
+    oid: Option<ObjectId>,
+}
+
+/// A pack that was read and checked, but whose deltas are not resolved yet.
+pub struct Indexer {
+    algorithm: HashAlgorithm,
+    checksum: Vec<u8>,
+    entries: Vec<RawEntry>,
+}
+
+impl Indexer {
+    /// Read the pack from `input`, hashing every non-delta entry with
+    /// `algorithm` on the fly.
+    /// The pack is copied to `copy` while it is read, no inflated data is
+    /// kept in memory.
+    pub fn read<R, W>(input: R, copy: Option<W>, algorithm: HashAlgorithm) -> Result<Self>
+    where
+        R: BufRead,
+        W: Write,
+    {
+        trace!("Indexing pack");
+        let mut stream = PackStream {
+            inner: input,
+            algorithm,
+            copy,
+            hasher: algorithm.context(),
+            crc: crc32fast::Hasher::new(),
+            offset: 0,
+            error: None,
+        };
+
+        let mut header = [0u8; PACK_HEADER_LEN as usize];
+        stream
+            .read_exact(&mut header)
+            .with_context(|| "Indexer: Pack header is truncated")?;
+        if &header[0..4] != PACK_SIGNATURE {
+            bail!("Indexer: Input is not a pack");
+        }
+        let version = u32::from_be_bytes(header[4..8].try_into().unwrap());
+        if version != PACK_VERSION && version != 3 {
+            bail!("Indexer: Pack version {} is not supported", version);
+        }
+        let count = u32::from_be_bytes(header[8..12].try_into().unwrap());
+        debug!("Pack holds {} objects", count);
+
+        // the count is untrusted, so the entries are not preallocated
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            entries.push(read_entry(&mut stream)?);
+        }
+
+        let checksum = stream.hasher.clone().finish().as_ref().to_vec();
+        let mut trailer = vec![0u8; algorithm.output_len()];
+        stream
+            .read_exact(&mut trailer)
+            .with_context(|| "Indexer: Pack checksum is missing")?;
+        if trailer[..] != checksum[..] {
+            bail!("Indexer: Pack checksum does not match its content");
+        }
+        if !stream.fill_buf()?.is_empty() {
+            bail!("Indexer: Pack has trailing garbage");
+        }
+        if let Some(copy) = stream.copy.as_mut() {
+            copy.flush()
+                .with_context(|| "Indexer: Could not write pack")?;
+        }
+
+        Ok(Self {
+            algorithm,
+            checksum,
+            entries,
+        })
+    }
+
+    /// Compute the oid of every delta, re-reading the deltas and their bases
+    /// from `pack`, which holds the pack that was read.
+    /// Returns the checksum of the pack and the entries for its `.idx`.
+    pub fn resolve<P: Read + Seek>(self, pack: &mut P) -> Result<(Vec<u8>, Vec<PackEntry>)> {
+        trace!("Resolving deltas of {} entries", self.entries.len());
+        let entries = &self.entries;
+        let position: HashMap<u64, usize> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.offset, i))
+            .collect();
+
+        // deltas waiting for their base, by base offset and by base oid
+        let mut by_offset: HashMap<u64, Vec<usize>> = HashMap::new();
+        let mut by_oid: HashMap<ObjectId, Vec<usize>> = HashMap::new();
+        let mut roots = Vec::new();
+        for (i, entry) in entries.iter().enumerate() {
+            match &entry.base {
+                Some(DeltaBase::Offset(base)) => {
+                    if !position.contains_key(base) {
+                        bail!("Indexer: No entry at delta base offset {}", base);
+                    }
+                    by_offset.entry(*base).or_default().push(i);
+                }
+                Some(DeltaBase::Oid(base)) => by_oid.entry(*base).or_default().push(i),
+                None => roots.push(i),
+            }
+        }
+
+        let mut oids: Vec<Option<ObjectId>> = entries.iter().map(|entry| entry.oid).collect();
+        let mut children = |i: usize, oid: &ObjectId| {
+            let mut children = by_offset.remove(&entries[i].offset).unwrap_or_default();
+            children.extend(by_oid.remove(oid).unwrap_or_default());
+            children
+        };
+        // walk from every base to the deltas depending on it, only the bases
+        // of the current chain are kept in memory
+        let mut stack: Vec<(usize, Types, Rc<Vec<u8>>)> = Vec::new();
+        for root in roots {
+            let oid = oids[root].unwrap();
+            let pending = children(root, &oid);
+            if pending.is_empty() {
+                continue;
+            }
+            let type_ = pack_type(entries[root].kind)?;
+            let data = Rc::new(read_data(pack, &entries[root])?);
+            stack.extend(pending.into_iter().map(|i| (i, type_, data.clone())));
+
+            while let Some((i, type_, base)) = stack.pop() {
+                let delta = read_data(pack, &entries[i])?;
+                let data = delta::apply(&base, &delta).with_context(|| {
+                    format!("Indexer: Could not apply delta at {}", entries[i].offset)
+                })?;
+                let oid = Database::hash_data(self.algorithm, type_, &data);
+                oids[i] = Some(oid);
+
+                let pending = children(i, &oid);
+                if !pending.is_empty() {
+                    let data = Rc::new(data);
+                    stack.extend(pending.into_iter().map(|i| (i, type_, data.clone())));
+                }
+            }
+        }
+
+        let unresolved = oids.iter().filter(|oid| oid.is_none()).count();
+        if unresolved > 0 {
+            bail!("Indexer: Pack has {} unresolved deltas", unresolved);
+        }
+
+        let entries = entries
+            .iter()
+            .zip(oids)
+            .map(|(entry, oid)| PackEntry {
+                oid: oid.unwrap(),
+                offset: entry.offset,
+                crc32: entry.crc32,
+            })
+            .collect();
+        Ok((self.checksum, entries))
+    }
+}
+
+/// Read the next entry of the pack from `stream`.
+fn read_entry<R: BufRead, W: Write>(stream: &mut PackStream<R, W>) -> Result<RawEntry> {
+    let offset = stream.offset;
+    let hash_len = stream.algorithm.output_len();
+    stream.crc = crc32fast::Hasher::new();
+    let mut next = || -> Result<u8> {
+        let mut byte = [0u8; 1];
+        stream
+            .read_exact(&mut byte)
+            .with_context(|| format!("Indexer: Entry at {} is truncated", offset))?;
+        Ok(byte[0])
+    };
+
+    let mut byte = next()?;
+    let kind = (byte >> 4) & 0x7;
+    let mut size = (byte & 0xf) as usize;
+    let mut shift = 4;
+    while byte & 0x80 != 0 {
+        if shift > 60 {
+            bail!("Indexer: Size of entry at {} is too large", offset);
+        }
+        byte = next()?;
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+    }
+
+    let base = match kind {
+        OBJ_OFS_DELTA => {
+            byte = next()?;
+            let mut distance = (byte & 0x7f) as u64;
+            while byte & 0x80 != 0 {
+                if distance >= 1 << 56 {
+                    bail!("Indexer: Delta offset at {} is too large", offset);
+                }
+                byte = next()?;
+                distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+            }
+            if distance == 0 || distance > offset {
+                bail!(
+                    "Indexer: Delta base of entry at {} is out of bounds",
+                    offset
+                );
+            }
+            Some(DeltaBase::Offset(offset - distance))
+        }
+        OBJ_REF_DELTA => {
+            let mut oid = vec![0u8; hash_len];
+            for b in oid.iter_mut() {
+                *b = next()?;
+            }
+            Some(DeltaBase::Oid(ObjectId::from_bytes(
+                &oid,
+                stream.algorithm,
+            )?))
+        }
+        OBJ_COMMIT | OBJ_TREE | OBJ_BLOB | OBJ_TAG => None,
+        kind => bail!("Indexer: Entry at {} has invalid type {}", offset, kind),
+    };
+
+    // objects are hashed as they are inflated, deltas only checked
+    let data_offset = stream.offset;
+    let algorithm = stream.algorithm;
+    let mut decoder = ZlibDecoder::new(&mut *stream);
+    let oid = match base {
+        Some(_) => {
+            let len = io::copy(&mut (&mut decoder).take(size as u64 + 1), &mut io::sink())
+                .with_context(|| format!("Indexer: Zlib decoding issues at {}", offset))?;
+            if len != size as u64 {
+                bail!("Indexer: Entry at {} does not have size {}", offset, size);
+            }
+            None
+        }
+        None => Some(
+            Database::hash_stream(algorithm, pack_type(kind)?, size as u64, &mut decoder)
+                .with_context(|| format!("Indexer: Could not inflate entry at {}", offset))?,
+        ),
+    };
+
+    Ok(RawEntry {
+        offset,
+        kind,
+        size,
+        base,
+        data_offset,
+        crc32: stream.crc.clone().finalize(),
+        oid,
+    })
+}
+
+/// Inflate the data of `entry` from `pack`.
+fn read_data<P: Read + Seek>(pack: &mut P, entry: &RawEntry) -> Result<Vec<u8>> {
+    pack.seek(SeekFrom::Start(entry.data_offset))
+        .with_context(|| "Indexer: Could not seek in pack")?;
+    let mut data = Vec::new();
+    ZlibDecoder::new(BufReader::new(pack))
+        .take(entry.size as u64)
+        .read_to_end(&mut data)
+        .with_context(|| format!("Indexer: Zlib decoding issues at {}", entry.offset))?;
+    if data.len() != entry.size {
+        bail!(
+            "Indexer: Entry at {} does not have size {}",
+            entry.offset,
+            entry.size
+        );
+    }
+
+    Ok(data)
+}
@@ -0,0 +1,283 @@
+use anyhow::{Context, Result};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use log::*;
+use ring::digest;
+use std::collections::VecDeque;
+use std::io::Write;
+
+use super::index::{FANOUT_SIZE, IDX_SIGNATURE, IDX_VERSION, LARGE_OFFSET};
+use super::*;
+use crate::{Database, HashAlgorithm, ObjectId, ObjectStore, Types};
+
+/// Objects compared against each other when searching for deltas.
+pub const DEFAULT_WINDOW: usize = 10;
+/// Longest delta chain created.
+pub const DEFAULT_DEPTH: usize = 50;
+/// Objects smaller than this are never stored as deltas.
+const MIN_DELTA_SIZE: usize = 50;
+
+/// Where an object is stored in a pack, as recorded in its `.idx`.
+#[derive(Clone, Debug)]
+pub struct PackEntry {
+    pub oid: ObjectId,
+    pub offset: u64,
+    pub crc32: u32,
+}
+
+/// An object to be packed.
+#[derive(Clone, Debug)]
+pub struct PackObject {
+    pub oid: ObjectId,
+    pub type_: Types,
+    pub size: usize,
+    /// Hash of the path the object was found at, see `name_hash`.
+    pub name_hash: u32,
+}
+
+/// Writes the entries of a pack to `out`, keeping track of their offsets
+/// and the running checksum.
+pub struct PackWriter<W: Write> {
+    out: W,
+    hasher: digest::Context,
+    offset: u64,
+    entries: Vec<PackEntry>,
+}
+
+impl<W: Write> PackWriter<W> {
+    /// Start a pack holding `count` objects, checksummed with `algorithm`.
+    pub fn new(out: W, count: u32, algorithm: HashAlgorithm) -> Result<Self> {
+        let mut writer = Self {
+            out,
+            hasher: algorithm.context(),
+            offset: 0,
+            entries: Vec::new(),
+        };
+        let mut header = PACK_SIGNATURE.to_vec();
+        header.extend(PACK_VERSION.to_be_bytes());
+        header.extend(count.to_be_bytes());
+        writer.write(&header)?;
+
+        Ok(writer)
+    }
+
+    /// Offset the next entry will be written at.
+    pub fn get_offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Write the object `oid` of pack type `kind` with its inflated `data`.
+    /// Deltas have to pass the offset of their base, which has to be written
+    /// already.
+    pub fn write_entry(
+        &mut self,
+        oid: &ObjectId,
+        kind: u8,
+        base: Option<u64>,
+        data: &[u8],
+    ) -> Result<u64> {
+        let offset = self.offset;
+        let mut entry = encode_entry_header(kind, data.len());
+        if let Some(base) = base {
+            entry.extend(encode_offset(offset - base));
+        }
+
+        let mut compressed = ZlibEncoder::new(entry, Compression::default());
+        compressed
+            .write_all(data)
+            .with_context(|| "PackWriter: Unable to encode content")?;
+        let entry = compressed
+            .finish()
+            .with_context(|| "PackWriter: Could not finish encoding")?;
+
+        self.write(&entry)?;
+        self.entries.push(PackEntry {
+            oid: *oid,
+            offset,
+            crc32: crc32fast::hash(&entry),
+        });
+
+        Ok(offset)
+    }
+
+    /// Write the trailing checksum, returning it and the entries written.
+    pub fn finish(mut self) -> Result<(Vec<u8>, Vec<PackEntry>)> {
+        let checksum = self.hasher.clone().finish().as_ref().to_vec();
+        self.out
+            .write_all(&checksum)
+            .with_context(|| "PackWriter: Could not write checksum")?;
+        self.out
+            .flush()
+            .with_context(|| "PackWriter: Could not flush pack")?;
+
+        Ok((checksum, self.entries))
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.out
+            .write_all(data)
+            .with_context(|| "PackWriter: Could not write pack")?;
+        self.hasher.update(data);
+        self.offset += data.len() as u64;
+
+        Ok(())
+    }
+}
+
+/// Pack `objects` into `out`, storing objects as deltas against similar
+/// objects among the previous `window` ones, with chains of at most `depth`.
+pub fn pack_objects<W: Write>(
+    database: &Database,
+    objects: &[PackObject],
+    window: usize,
+    depth: usize,
+    out: W,
+) -> Result<(Vec<u8>, Vec<PackEntry>)> {
+    trace!("Packing {} objects", objects.len());
+    // similar objects end up next to each other, larger ones first so
+    // deltas mostly remove data
+    let mut order: Vec<&PackObject> = objects.iter().collect();
+    order.sort_by_key(|object| {
+        (
+            pack_kind(object.type_),
+            object.name_hash,
+            std::cmp::Reverse(object.size),
+        )
+    });
+
+    let mut writer = PackWriter::new(out, order.len() as u32, database.get_algorithm())?;
+    // objects in the window: offset, delta depth, kind and data
+    let mut candidates: VecDeque<(u64, usize, u8, Vec<u8>)> = VecDeque::new();
+    for object in order {
+        let (type_, data) = database.read_object(&object.oid)?;
+        let kind = pack_kind(type_);
+
+        // find the smallest delta against a base in the window
+        let mut best: Option<(u64, usize, Vec<u8>)> = None;
+        if data.len() >= MIN_DELTA_SIZE {
+            for (offset, base_depth, base_kind, base) in candidates.iter() {
+                if *base_kind != kind || *base_depth >= depth {
+                    continue;
+                }
+                // a delta only pays off if it is at most half the size
+                let max_size = best
+                    .as_ref()
+                    .map_or(data.len() / 2, |(_, _, delta)| delta.len());
+                if base.len().abs_diff(data.len()) >= max_size {
+                    continue;
+                }
+                let delta = delta::create(base, &data);
+                if delta.len() < max_size {
+                    best = Some((*offset, *base_depth + 1, delta));
+                }
+            }
+        }
+
+        let (offset, entry_depth) = match best {
+            Some((base, entry_depth, delta)) => (
+                writer.write_entry(&object.oid, OBJ_OFS_DELTA, Some(base), &delta)?,
+                entry_depth,
+            ),
+            None => (writer.write_entry(&object.oid, kind, None, &data)?, 0),
+        };
+
+        if window > 0 {
+            if candidates.len() >= window {
+                candidates.pop_front();
+            }
+            candidates.push_back((offset, entry_depth, kind, data));
+        }
+    }
+
+    writer.finish()
+}
+
+/// Serialize a version 2 `.idx` for the pack with `checksum` holding `entries`,
+/// checksummed with `algorithm`.
+pub fn write_index(entries: &[PackEntry], checksum: &[u8], algorithm: HashAlgorithm) -> Vec<u8> {
+    trace!("Writing index of {} objects", entries.len());
+    let mut entries = entries.to_vec();
+    entries.sort_by_key(|entry| entry.oid);
+
+    let mut data = IDX_SIGNATURE.to_vec();
+    data.extend(IDX_VERSION.to_be_bytes());
+    let mut fanout = [0u32; FANOUT_SIZE];
+    for entry in entries.iter() {
+        fanout[entry.oid.as_bytes()[0] as usize] += 1;
+    }
+    let mut total = 0;
+    for count in fanout {
+        total += count;
+        data.extend(total.to_be_bytes());
+    }
+
+    for entry in entries.iter() {
+        data.extend(entry.oid.as_bytes());
+    }
+    for entry in entries.iter() {
+        data.extend(entry.crc32.to_be_bytes());
+    }
+    let mut large = Vec::new();
+    for entry in entries.iter() {
+        if entry.offset < LARGE_OFFSET as u64 {
+            data.extend((entry.offset as u32).to_be_bytes());
+        } else {
+            data.extend((LARGE_OFFSET | large.len() as u32).to_be_bytes());
+            large.push(entry.offset);
+        }
+    }
+    for offset in large {
+        data.extend(offset.to_be_bytes());
+    }
+
+    data.extend(checksum);
+    let idx_checksum = algorithm.digest(&data);
+    data.extend(idx_checksum);
+
+    data
+}
+
+/// Hash of the path of an object, so that objects with similar names sort
+/// next to each other. Mostly determined by the last characters of the path.
+pub fn name_hash(name: &str) -> u32 {
+    name.bytes()
+        .filter(|c| !c.is_ascii_whitespace())
+        .fold(0u32, |hash, c| (hash >> 2).wrapping_add((c as u32) << 24))
+}
+
+/// Map `Types` to the type number of a pack entry.
+pub fn pack_kind(type_: Types) -> u8 {
+    match type_ {
+        Types::Commit => OBJ_COMMIT,
+        Types::Tree => OBJ_TREE,
+        Types::Blob => OBJ_BLOB,
+        Types::Tag => OBJ_TAG,
+    }
+}
+
+/// Encode the type and size header of a pack entry.
+fn encode_entry_header(kind: u8, size: usize) -> Vec<u8> {
+    let mut header = vec![(kind << 4) | (size & 0xf) as u8];
+    let mut size = size >> 4;
+    while size > 0 {
+        *header.last_mut().unwrap() |= 0x80;
+        header.push((size & 0x7f) as u8);
+        size >>= 7;
+    }
+
+    header
+}
+
+/// Encode the distance to the base of an `OBJ_OFS_DELTA`.
+fn encode_offset(mut distance: u64) -> Vec<u8> {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance > 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+
+    bytes
+}
@@ -0,0 +1,71 @@
+use crate::*;
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::io::Read;
+
+/// An `ObjectStore` holds objects addressed by their `ObjectId`.
+/// The `Database` keeps them on disk, a `MemoryStore` keeps them in memory.
+pub trait ObjectStore {
+    /// Get the hash algorithm of the oids in the store.
+    fn get_algorithm(&self) -> HashAlgorithm;
+
+    /// Read the object `oid`, returning its type and its data.
+    fn read(&self, oid: &ObjectId) -> Result<(Types, Vec<u8>)>;
+
+    /// Store `data` as an object of type `type_` and return its oid.
+    /// Storing an object that already exists does nothing.
+    fn write(&self, type_: Types, data: &[u8]) -> Result<ObjectId>;
+
+    /// Store the `size` bytes read from `reader` as an object of type `type_`
+    /// and return its oid.
+    /// The default buffers the data, stores on disk stream it instead.
+    fn write_stream(&self, type_: Types, size: u64, reader: &mut dyn Read) -> Result<ObjectId> {
+        let mut data = Vec::new();
+        reader
+            .take(size + 1)
+            .read_to_end(&mut data)
+            .with_context(|| "ObjectStore: Could not read object data")?;
+        if data.len() as u64 != size {
+            bail!(
+                "ObjectStore: Expected {} bytes but read {}",
+                size,
+                data.len()
+            );
+        }
+
+        self.write(type_, &data)
+    }
+
+    /// Does the object `oid` exist.
+    fn contains(&self, oid: &ObjectId) -> bool;
+
+    /// Iterate over the oids of all objects in the store, sorted.
+    fn iter(&self) -> Result<Box<dyn Iterator<Item = ObjectId> + '_>>;
+
+    /// Store an `Object` and set its oid.
+    fn store(&self, object: &mut dyn Object) -> Result<ObjectId> {
+        trace!("Storing {}", object.get_type().as_string());
+        let oid = self.write(object.get_type(), &object.get_data())?;
+        object.set_oid(oid);
+
+        Ok(oid)
+    }
+
+    /// Calculate the oid of an `Object` without storing it.
+    fn hash_object(&self, object: &dyn Object) -> ObjectId {
+        Database::hash_data(self.get_algorithm(), object.get_type(), &object.get_data())
+    }
+
+    /// Load an object and parse it according to its type.
+    fn load(&self, oid: &ObjectId) -> Result<ParsedObject> {
+        trace!("Loading object {}", oid);
+        let (type_, data) = self
+            .read(oid)
+            .with_context(|| format!("ObjectStore: Could not read object {}", oid))?;
+        let mut object = ParsedObject::parse(type_, data, self.get_algorithm())
+            .with_context(|| format!("ObjectStore: Could not parse object {}", oid))?;
+        object.set_oid(*oid);
+
+        Ok(object)
+    }
+}
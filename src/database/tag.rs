@@ -0,0 +1,179 @@
+use crate::*;
+use anyhow::{Context, Result};
+use log::*;
+use std::fmt::Display;
+
+/// The `Tag` `Object` gives another object a name, a tagger and a message,
+/// e.g. to mark a release.
+pub struct Tag {
+    /// Unique oid of `Tag`.
+    oid: Option<ObjectId>,
+
+    /// Type.
+    type_: Types,
+
+    /// The tagged object.
+    object: ObjectId,
+
+    /// Type of the tagged object.
+    object_type: Types,
+
+    /// Name of the tag.
+    tag: String,
+
+    /// Who created the tag, missing in some very old tags.
+    tagger: Option<Author>,
+
+    /// The tag message.
+    message: String,
+}
+
+impl Tag {
+    /// Create a new `Tag` named `tag` for the object `object` of type
+    /// `object_type`.
+    pub fn new(
+        object: ObjectId,
+        object_type: Types,
+        tag: String,
+        tagger: Author,
+        message: String,
+    ) -> Self {
+        trace!("Creating Tag {}", tag);
+        Self {
+            oid: None,
+            type_: Types::Tag,
+            object,
+            object_type,
+            tag,
+            tagger: Some(tagger),
+            message,
+        }
+    }
+
+    /// Parse a `Tag` from the data of a stored object.
+    /// This is the inverse of `get_data`, oids are hashed with `algorithm`.
+    pub fn parse(data: &[u8], algorithm: HashAlgorithm) -> Result<Self> {
+        trace!("Parsing tag");
+        let s = std::str::from_utf8(data).with_context(|| "Tag: Data is not valid utf-8")?;
+        // a tag without message may end right after its headers
+        let (headers, body) = s
+            .split_once("\n\n")
+            .unwrap_or_else(|| (s.strip_suffix('\n').unwrap_or(s), ""));
+
+        let mut object = None;
+        let mut object_type = None;
+        let mut tag = None;
+        let mut tagger = None;
+        for line in headers.lines() {
+            let (key, value) = line
+                .split_once(' ')
+                .with_context(|| format!("Tag: Malformed header line {:?}", line))?;
+            match key {
+                "object" => {
+                    object = Some(
+                        ObjectId::from_hex(value, algorithm)
+                            .with_context(|| "Tag: Invalid object")?,
+                    )
+                }
+                "type" => object_type = Some(value.parse::<Types>()?),
+                "tag" => tag = Some(value.to_string()),
+                "tagger" => {
+                    tagger = Some(Author::parse(value).with_context(|| "Tag: Invalid tagger")?)
+                }
+                _ => debug!("Skipping unknown tag header {}", key),
+            }
+        }
+
+        let object = object.with_context(|| "Tag: Missing object header")?;
+        let object_type = object_type.with_context(|| "Tag: Missing type header")?;
+        let tag = tag.with_context(|| "Tag: Missing tag header")?;
+        let message = body.strip_suffix('\n').unwrap_or(body).to_string();
+
+        Ok(Self {
+            oid: None,
+            type_: Types::Tag,
+            object,
+            object_type,
+            tag,
+            tagger,
+            message,
+        })
+    }
+
+    /// Get the oid of the tagged object.
+    pub fn get_object(&self) -> ObjectId {
+        self.object
+    }
+
+    /// Get the type of the tagged object.
+    pub fn get_object_type(&self) -> Types {
+        self.object_type
+    }
+
+    /// Get the name of the tag.
+    pub fn get_tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Get the tagger.
+    pub fn get_tagger(&self) -> Option<&Author> {
+        self.tagger.as_ref()
+    }
+
+    /// Get the message.
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+
+    /// Get the subject, the first paragraph of the message joined into one
+    /// line.
+    pub fn get_subject(&self) -> String {
+        self.message
+            .trim_start()
+            .lines()
+            .take_while(|line| !line.trim().is_empty())
+            .map(|line| line.trim())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+}
+
+impl Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.get_data()))
+    }
+}
+
+impl Object for Tag {
+    fn get_type(&self) -> Types {
+        trace!("Getting type, should be tag");
+        self.type_
+    }
+
+    fn get_data(&self) -> Vec<u8> {
+        trace!("Getting data of tag");
+        let mut s = String::new();
+        s.push_str(&format!("object {}\n", self.object));
+        s.push_str(&format!("type {}\n", self.object_type.as_string()));
+        s.push_str(&format!("tag {}\n", self.tag));
+        if let Some(tagger) = &self.tagger {
+            s.push_str(&format!("tagger {}\n", tagger));
+        }
+        s.push('\n');
+        if !self.message.is_empty() {
+            s.push_str(&format!("{}\n", self.message));
+        }
+
+        s.as_bytes().to_vec()
+    }
+
+    fn set_oid(&mut self, hash: ObjectId) {
+        trace!("Setting oid of tag");
+        self.oid = Some(hash);
+    }
+
+    fn get_oid(&self) -> Option<ObjectId> {
+        trace!("Getting oid of tag");
+        self.oid
+    }
+}
@@ -1,10 +1,12 @@
 use crate::*;
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use log::*;
 use std::collections::BTreeMap;
 use std::path::Path;
 
-const TREE_MODE: &str = "004000";
+pub const TREE_MODE: &str = "40000";
+/// Mode of a submodule, which points to a `Commit`.
+pub const GITLINK_MODE: &str = "160000";
 
 /// This is a generic `Tree` type.
 /// It can either be a `Entry` or a `Tree` itself.
@@ -22,17 +24,26 @@ impl TreeEntry {
         }
     }
 
-    pub fn get_oid(&self) -> String {
+    pub fn get_oid(&self) -> ObjectId {
         match self {
             TreeEntry::Entry(e) => e.get_oid(),
             TreeEntry::Tree(t) => t.get_oid().expect("Tree should have an oid!"),
         }
     }
 
+    /// Get the type of the object this entry points to.
+    pub fn get_type(&self) -> Types {
+        match self.get_mode().as_str() {
+            TREE_MODE => Types::Tree,
+            GITLINK_MODE => Types::Commit,
+            _ => Types::Blob,
+        }
+    }
+
     pub fn is_tree(&self) -> bool {
         match self {
             TreeEntry::Entry(e) => e.get_mode() == TREE_MODE,
-            _ => false,
+            TreeEntry::Tree(_) => true,
         }
     }
 }
@@ -41,18 +52,102 @@ impl TreeEntry {
 /// `TreeEntry` types.
 #[derive(Clone)]
 pub struct Tree {
+    /// Unique oid of `Tree`, set once it is stored.
+    oid: Option<ObjectId>,
+
     entries: BTreeMap<String, TreeEntry>,
 }
 
+impl Default for Tree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Tree {
     /// Create a new `Tree` from an `Entry` vector.
     pub fn new() -> Self {
         trace!("Creating a new tree");
         Self {
+            oid: None,
             entries: BTreeMap::new(),
         }
     }
 
+    /// Parse a `Tree` from the data of a stored object.
+    /// This is the inverse of `get_data`, every subtree is kept as an `Entry`
+    /// with `TREE_MODE`. Oids are hashed with `algorithm`.
+    pub fn parse(data: &[u8], algorithm: HashAlgorithm) -> Result<Self> {
+        trace!("Parsing tree");
+        let mut tree = Tree::new();
+        for (mode, name, oid) in Self::parse_entries(data, algorithm)? {
+            let entry = Entry::from_mode(name.clone(), oid, &mode)
+                .with_context(|| "Tree: Could not parse entry")?;
+            tree.entries.insert(name, TreeEntry::Entry(entry));
+        }
+
+        Ok(tree)
+    }
+
+    /// Split the data of a stored tree into `(mode, name, oid)` triples in the
+    /// order they are stored in, without validating them.
+    pub fn parse_entries(
+        data: &[u8],
+        algorithm: HashAlgorithm,
+    ) -> Result<Vec<(String, String, ObjectId)>> {
+        let oid_len = algorithm.output_len();
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let space = data[pos..]
+                .iter()
+                .position(|&b| b == b' ')
+                .with_context(|| "Tree: Entry has no space after mode")?;
+            let mode = std::str::from_utf8(&data[pos..pos + space])
+                .with_context(|| "Tree: Mode is not valid utf-8")?
+                .to_string();
+            pos += space + 1;
+
+            let null = data[pos..]
+                .iter()
+                .position(|&b| b == b'\x00')
+                .with_context(|| "Tree: Entry has no null-byte after name")?;
+            let name = String::from_utf8(data[pos..pos + null].to_vec())
+                .with_context(|| "Tree: Name is not valid utf-8")?;
+            pos += null + 1;
+
+            if pos + oid_len > data.len() {
+                bail!("Tree: Entry {} is truncated", name);
+            }
+            let oid = ObjectId::from_bytes(&data[pos..pos + oid_len], algorithm)?;
+            pos += oid_len;
+
+            entries.push((mode, name, oid));
+        }
+
+        Ok(entries)
+    }
+
+    /// Return the entries in the order they are stored in.
+    /// git sorts subtrees as if their name had a trailing `/`.
+    pub fn sorted_entries(&self) -> Vec<(&String, &TreeEntry)> {
+        let mut entries: Vec<(&String, &TreeEntry)> = self.entries.iter().collect();
+        entries.sort_by_cached_key(|(name, entry)| {
+            let mut key = name.as_bytes().to_vec();
+            if entry.is_tree() {
+                key.push(b'/');
+            }
+            key
+        });
+
+        entries
+    }
+
+    /// Return the entries of this `Tree` sorted by name.
+    pub fn get_entries(&self) -> &BTreeMap<String, TreeEntry> {
+        &self.entries
+    }
+
     /// Build a `Tree` from a list of `Entry`.
     pub fn build(entries: Vec<Entry>) -> Result<Tree> {
         trace!("Building a tree from entries");
@@ -99,21 +194,37 @@ impl Tree {
         }
     }
 
-    /// Traverse this `Tree` and apply a function to each `Entry`.
-    pub fn traverse<F>(&self, f: &F)
+    /// Store this `Tree` and all its subtrees in `store`, returning the oid
+    /// of this `Tree`.
+    pub fn write(&mut self, store: &dyn ObjectStore) -> Result<ObjectId> {
+        trace!("Writing tree");
+        self.traverse(&mut |tree: &mut Tree| {
+            store
+                .store(tree)
+                .map(|_| ())
+                .with_context(|| "Tree: Could not store subtree")
+        })?;
+
+        self.oid
+            .with_context(|| "Tree: Is stored, should have oid set")
+    }
+
+    /// Traverse this `Tree` and apply a function to each subtree.
+    /// The first error returned by `f` aborts the traversal.
+    pub fn traverse<F>(&mut self, f: &mut F) -> Result<()>
     where
-        F: Fn(&Tree) -> (),
+        F: FnMut(&mut Tree) -> Result<()>,
     {
         trace!("Traversing root tree");
         // Do a postorder traversal(visit all children first, then
         // process `self`
-        for (_name, entry) in self.entries.clone() {
+        for entry in self.entries.values_mut() {
             if let TreeEntry::Tree(tree) = entry {
-                tree.traverse(f);
+                tree.traverse(f)?;
             }
         }
 
-        f(self);
+        f(self)
     }
 }
 
@@ -126,11 +237,10 @@ impl Object for Tree {
     fn get_data(&self) -> Vec<u8> {
         trace!("Getting data of tree");
         let mut data = Vec::new();
-        for (name, entry) in self.entries.iter() {
+        for (name, entry) in self.sorted_entries() {
             let mut pre = format!("{} {}", entry.get_mode(), name).as_bytes().to_vec();
             pre.push(b"\x00"[0]);
-            let oid = hex::decode(entry.get_oid()).expect("Tree: Could not decode hex");
-            pre.extend(oid);
+            pre.extend(entry.get_oid().as_bytes());
             data.extend(pre);
         }
 
@@ -138,13 +248,120 @@ impl Object for Tree {
         data
     }
 
-    fn set_oid(&mut self, hash: String) {
+    fn set_oid(&mut self, hash: ObjectId) {
         trace!("Setting oid of tree");
-        unimplemented!();
+        self.oid = Some(hash);
     }
 
-    fn get_oid(&self) -> Option<String> {
+    fn get_oid(&self) -> Option<ObjectId> {
         trace!("Getting oid of tree");
-        unimplemented!();
+        self.oid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blob(store: &MemoryStore, data: &str) -> ObjectId {
+        store.store(&mut Blob::new(data.into())).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let store = MemoryStore::new(HashAlgorithm::Sha1);
+        let a = blob(&store, "a\n");
+        let b = blob(&store, "b\n");
+        let entries = vec![
+            Entry::from_mode("a.txt".into(), a, "100644").unwrap(),
+            Entry::from_mode("dir/b.txt".into(), b, "100644").unwrap(),
+            Entry::from_mode("run.sh".into(), a, "100755").unwrap(),
+        ];
+        let mut tree = Tree::build(entries).unwrap();
+        let oid = tree.write(&store).unwrap();
+
+        let loaded = match store.load(&oid).unwrap() {
+            ParsedObject::Tree(tree) => tree,
+            _ => panic!("expected a tree"),
+        };
+        assert_eq!(loaded.get_oid(), Some(oid));
+        assert_eq!(loaded.get_data(), tree.get_data());
+
+        let entries = loaded.get_entries();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries["a.txt"].get_mode(), "100644");
+        assert_eq!(entries["a.txt"].get_oid(), a);
+        assert_eq!(entries["run.sh"].get_mode(), "100755");
+        assert!(entries["dir"].is_tree());
+
+        // the subtree was stored before its parent
+        let subtree = match store.load(&entries["dir"].get_oid()).unwrap() {
+            ParsedObject::Tree(tree) => tree,
+            _ => panic!("expected a tree"),
+        };
+        assert_eq!(subtree.get_entries()["b.txt"].get_oid(), b);
+    }
+
+    #[test]
+    fn subtrees_sort_with_trailing_slash() {
+        let store = MemoryStore::new(HashAlgorithm::Sha1);
+        let oid = blob(&store, "x");
+        let entries = vec![
+            Entry::from_mode("foo.c".into(), oid, "100644").unwrap(),
+            Entry::from_mode("foo/bar".into(), oid, "100644").unwrap(),
+        ];
+        let mut tree = Tree::build(entries).unwrap();
+        let root = tree.write(&store).unwrap();
+
+        let (_, data) = store.read(&root).unwrap();
+        let names: Vec<String> = Tree::parse_entries(&data, HashAlgorithm::Sha1)
+            .unwrap()
+            .into_iter()
+            .map(|(_, name, _)| name)
+            .collect();
+        assert_eq!(names, ["foo.c", "foo"]);
+    }
+
+    #[test]
+    fn legacy_modes_are_normalized() {
+        let store = MemoryStore::new(HashAlgorithm::Sha1);
+        let oid = blob(&store, "x");
+        let mut data = Vec::new();
+        for (mode, name) in [
+            ("100664", "a"),
+            ("100775", "b"),
+            ("040000", "c"),
+            ("120777", "d"),
+        ] {
+            data.extend(format!("{} {}\0", mode, name).as_bytes());
+            data.extend(oid.as_bytes());
+        }
+
+        let tree = Tree::parse(&data, HashAlgorithm::Sha1).unwrap();
+        let entries = tree.get_entries();
+        assert_eq!(entries["a"].get_mode(), "100644");
+        assert_eq!(entries["b"].get_mode(), "100755");
+        assert_eq!(entries["c"].get_mode(), "40000");
+        assert!(entries["c"].is_tree());
+        assert_eq!(entries["d"].get_mode(), "120000");
+    }
+
+    #[test]
+    fn malformed_modes_are_rejected() {
+        let oid = Database::hash_data(HashAlgorithm::Sha1, Types::Blob, b"x");
+        for mode in [
+            "", "10064x", "1000644", "-100644", "+100644", "30000", "644",
+        ] {
+            let mut data = format!("{} a\0", mode).into_bytes();
+            data.extend(oid.as_bytes());
+            assert!(Tree::parse(&data, HashAlgorithm::Sha1).is_err(), "{}", mode);
+        }
+    }
+
+    #[test]
+    fn truncated_entry_is_rejected() {
+        let mut data = b"100644 a\0".to_vec();
+        data.extend_from_slice(&[0; 10]);
+        assert!(Tree::parse(&data, HashAlgorithm::Sha1).is_err());
     }
 }
@@ -1,2 +1,4 @@
 pub static GIT_AUTHOR_NAME: &str = "GIT_AUTHOR_NAME";
 pub static GIT_AUTHOR_EMAIL: &str = "GIT_AUTHOR_EMAIL";
+pub static GIT_COMMITTER_NAME: &str = "GIT_COMMITTER_NAME";
+pub static GIT_COMMITTER_EMAIL: &str = "GIT_COMMITTER_EMAIL";
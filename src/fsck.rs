@@ -0,0 +1,281 @@
+use crate::pack::PackIndex;
+use crate::tree::TreeEntry;
+use crate::*;
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::collections::{BTreeMap, HashSet};
+use std::env::current_dir;
+
+/// Exit code bit for corrupt objects or packs.
+pub const FSCK_CORRUPT: i32 = 1;
+/// Exit code bit for objects that are referenced but do not exist.
+pub const FSCK_MISSING: i32 = 2;
+/// Exit code bit for reported unreachable objects.
+pub const FSCK_UNREACHABLE: i32 = 4;
+
+/// Re-hash and validate every object, then walk everything reachable from
+/// HEAD, the refs and the reflogs. Returns the combined `FSCK_*` exit code.
+pub fn check_objects(fsck: cli::Fsck) -> Result<i32> {
+    trace!("Checking objects");
+    debug!("Got arguments: {:?}", fsck);
+
+    // get current directory
+    let root_path =
+        current_dir().with_context(|| "Fsck: Could not get the current working directory!")?;
+    let git_path = root_path.join(".git");
+    let db_path = git_path.join("objects");
+
+    // check if this is actually a repository.
+    if !is_repo(&root_path)? {
+        bail!("Fsck: {:?} is not a rit repository!", root_path);
+    }
+
+    let database = Database::new(&db_path).with_context(|| "Fsck: Could not load database")?;
+    let refs = Refs::new(git_path).with_context(|| "Fsck: Could not load refs")?;
+    let algorithm = database.get_algorithm();
+    let mut code = 0;
+
+    for pack in database.get_packs() {
+        let idx_path = pack.get_pack_path().with_extension("idx");
+        if let Err(err) =
+            PackIndex::verify_checksum(&idx_path, algorithm).and(pack.verify_checksum())
+        {
+            eprintln!("error: {:#}", err);
+            code |= FSCK_CORRUPT;
+        }
+    }
+
+    // every valid object with the objects it points to
+    let present: HashSet<ObjectId> = database.list_objects()?.into_iter().collect();
+    let mut objects: BTreeMap<ObjectId, (Types, Vec<(Types, ObjectId)>)> = BTreeMap::new();
+    for oid in present.iter() {
+        let (type_, data) = match database.read_object(oid) {
+            Ok(object) => object,
+            Err(err) => {
+                eprintln!("error: {}: object corrupt or missing: {:#}", oid, err);
+                code |= FSCK_CORRUPT;
+                continue;
+            }
+        };
+        if Database::hash_data(algorithm, type_, &data) != *oid {
+            eprintln!("error: hash mismatch for {}", oid);
+            code |= FSCK_CORRUPT;
+            continue;
+        }
+        match check_object(type_, &data, algorithm) {
+            Ok(links) => {
+                objects.insert(*oid, (type_, links));
+            }
+            Err(err) => {
+                eprintln!("error in {} {}: {:#}", type_.as_string(), oid, err);
+                code |= FSCK_CORRUPT;
+            }
+        }
+    }
+    debug!("Checked {} objects", objects.len());
+
+    let mut referenced = HashSet::new();
+    let mut missing = BTreeMap::new();
+    for (oid, (type_, links)) in objects.iter() {
+        for (link_type, link) in links {
+            referenced.insert(*link);
+            if !present.contains(link) {
+                eprintln!(
+                    "broken link from {:>6} {}\n              to {:>6} {}",
+                    type_.as_string(),
+                    oid,
+                    link_type.as_string(),
+                    link
+                );
+                missing.insert(*link, *link_type);
+            }
+        }
+    }
+
+    let mut roots = Vec::new();
+    let mut pointers = Vec::new();
+    if let Some(oid) = refs.read_head()? {
+        pointers.push((HEAD.to_string(), oid));
+    }
+    for name in refs.list_all()? {
+        match refs.read_ref(&name) {
+            Ok(Some(oid)) => pointers.push((name, oid)),
+            Ok(None) => (),
+            Err(err) => {
+                eprintln!("error: {}: {:#}", name, err);
+                code |= FSCK_CORRUPT;
+            }
+        }
+    }
+    for name in refs.list_reflogs()? {
+        let entries = match refs.reflog_entries(&name) {
+            Ok(entries) => entries,
+            Err(err) => {
+                eprintln!("error: {}: {:#}", name, err);
+                code |= FSCK_CORRUPT;
+                continue;
+            }
+        };
+        for entry in entries {
+            for oid in [entry.old, entry.new] {
+                if !oid.is_null() {
+                    pointers.push((format!("{}@{{reflog}}", name), oid));
+                }
+            }
+        }
+    }
+    for (name, oid) in pointers {
+        if present.contains(&oid) {
+            roots.push(oid);
+        } else {
+            eprintln!("error: {}: invalid sha1 pointer {}", name, oid);
+            code |= FSCK_MISSING;
+        }
+    }
+
+    for (oid, type_) in missing.iter() {
+        println!("missing {} {}", type_.as_string(), oid);
+        code |= FSCK_MISSING;
+    }
+
+    // walk everything reachable from the roots
+    let mut reachable = HashSet::new();
+    while let Some(oid) = roots.pop() {
+        if !reachable.insert(oid) {
+            continue;
+        }
+        if let Some((_, links)) = objects.get(&oid) {
+            roots.extend(links.iter().map(|(_, link)| *link));
+        }
+    }
+
+    for (oid, (type_, _)) in objects.iter() {
+        if reachable.contains(oid) {
+            continue;
+        }
+        if fsck.unreachable {
+            println!("unreachable {} {}", type_.as_string(), oid);
+            code |= FSCK_UNREACHABLE;
+        } else if !fsck.no_dangling && !referenced.contains(oid) {
+            // dangling objects are normal after a reset or amend, they are
+            // reported but do not fail the check
+            println!("dangling {} {}", type_.as_string(), oid);
+        }
+    }
+
+    Ok(code)
+}
+
+/// Validate the `data` of an object of type `type_` and return the objects
+/// it points to.
+fn check_object(
+    type_: Types,
+    data: &[u8],
+    algorithm: HashAlgorithm,
+) -> Result<Vec<(Types, ObjectId)>> {
+    match type_ {
+        Types::Blob => Ok(Vec::new()),
+        Types::Tree => check_tree(data, algorithm),
+        Types::Commit => check_commit(data, algorithm),
+        Types::Tag => check_tag(data, algorithm),
+    }
+}
+
+/// Check the modes, names and order of the entries of a tree.
+fn check_tree(data: &[u8], algorithm: HashAlgorithm) -> Result<Vec<(Types, ObjectId)>> {
+    let mut links = Vec::new();
+    let mut names = HashSet::new();
+    let mut previous: Option<Vec<u8>> = None;
+    for (mode, name, oid) in Tree::parse_entries(data, algorithm)? {
+        let entry = TreeEntry::Entry(Entry::from_mode(name.clone(), oid, &mode)?);
+        if name.is_empty() || name.contains('/') || [".", "..", ".git"].contains(&name.as_str()) {
+            bail!("Fsck: Tree contains invalid name {:?}", name);
+        }
+        if !names.insert(name.clone()) {
+            bail!("Fsck: Tree contains duplicate entry {:?}", name);
+        }
+
+        // subtrees sort as if their name had a trailing `/`
+        let mut key = name.as_bytes().to_vec();
+        if entry.is_tree() {
+            key.push(b'/');
+        }
+        if previous.as_ref().is_some_and(|previous| *previous > key) {
+            bail!("Fsck: Tree is not properly sorted at {:?}", name);
+        }
+        previous = Some(key);
+
+        // submodule commits live in another repository
+        if entry.get_type() != Types::Commit {
+            links.push((entry.get_type(), oid));
+        }
+    }
+
+    Ok(links)
+}
+
+/// Check the order and format of the headers of a commit.
+fn check_commit(data: &[u8], algorithm: HashAlgorithm) -> Result<Vec<(Types, ObjectId)>> {
+    // validates the author and committer lines
+    Commit::parse(data, algorithm)?;
+    let text = std::str::from_utf8(data)?;
+    let mut lines = text.lines();
+    let mut links = Vec::new();
+
+    let tree = lines
+        .next()
+        .and_then(|line| line.strip_prefix("tree "))
+        .with_context(|| "Fsck: Commit does not start with a tree header")?;
+    links.push((Types::Tree, check_oid(tree, algorithm)?));
+
+    let mut line = lines.next();
+    while let Some(parent) = line.and_then(|line| line.strip_prefix("parent ")) {
+        links.push((Types::Commit, check_oid(parent, algorithm)?));
+        line = lines.next();
+    }
+    if !line.is_some_and(|line| line.starts_with("author ")) {
+        bail!("Fsck: Commit has no author header after its parents");
+    }
+    if !lines
+        .next()
+        .is_some_and(|line| line.starts_with("committer "))
+    {
+        bail!("Fsck: Commit has no committer header after its author");
+    }
+
+    Ok(links)
+}
+
+/// Check the order and format of the headers of a tag.
+fn check_tag(data: &[u8], algorithm: HashAlgorithm) -> Result<Vec<(Types, ObjectId)>> {
+    // validates the type and tagger lines
+    let tag = Tag::parse(data, algorithm)?;
+    let text = std::str::from_utf8(data)?;
+    let mut lines = text.lines();
+
+    let object = lines
+        .next()
+        .and_then(|line| line.strip_prefix("object "))
+        .with_context(|| "Fsck: Tag does not start with an object header")?;
+    let object = check_oid(object, algorithm)?;
+    if !lines.next().is_some_and(|line| line.starts_with("type ")) {
+        bail!("Fsck: Tag has no type header after its object");
+    }
+    if !lines.next().is_some_and(|line| line.starts_with("tag ")) {
+        bail!("Fsck: Tag has no tag header after its type");
+    }
+    if !is_valid_ref_name(&format!("{}/{}", REFS_TAGS, tag.get_tag())) {
+        bail!("Fsck: Tag has invalid name {:?}", tag.get_tag());
+    }
+
+    Ok(vec![(tag.get_object_type(), object)])
+}
+
+/// Check that `oid` is a full, lowercase hex object id and parse it.
+fn check_oid(oid: &str, algorithm: HashAlgorithm) -> Result<ObjectId> {
+    if oid.bytes().any(|b| b.is_ascii_uppercase()) {
+        bail!("Fsck: Invalid object id {:?}", oid);
+    }
+
+    ObjectId::from_hex(oid, algorithm).with_context(|| format!("Fsck: Invalid object id {:?}", oid))
+}
@@ -0,0 +1,104 @@
+use anyhow::{bail, Error, Result};
+use ring::digest::{self, Algorithm};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::{Config, ObjectId};
+
+/// Config key recording the object format of a repository.
+pub const OBJECT_FORMAT_KEY: &str = "extensions.objectformat";
+
+/// Hash function used to compute object ids and checksums of a repository.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// Read the object format of the repository at `git_path` from its
+    /// config, repositories without `extensions.objectFormat` use SHA-1.
+    pub fn detect(git_path: &Path) -> Result<Self> {
+        Self::from_config(&Config::load(&git_path.join(crate::CONFIG))?)
+    }
+
+    /// Read the object format from a loaded `config`.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        match config.get(OBJECT_FORMAT_KEY) {
+            Some(format) => format.parse(),
+            None => Ok(Self::Sha1),
+        }
+    }
+
+    /// Name of the object format as used in the config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+        }
+    }
+
+    /// Length of a raw hash in bytes.
+    pub fn output_len(&self) -> usize {
+        self.algorithm().output_len
+    }
+
+    /// Length of a hash in hex.
+    pub fn hex_len(&self) -> usize {
+        2 * self.output_len()
+    }
+
+    /// Find the algorithm whose hex oids have length `len`.
+    pub fn from_hex_len(len: usize) -> Option<Self> {
+        [Self::Sha1, Self::Sha256]
+            .into_iter()
+            .find(|algorithm| algorithm.hex_len() == len)
+    }
+
+    /// The oid consisting of zeros only, standing for no object.
+    pub fn null_oid(&self) -> ObjectId {
+        ObjectId::null(*self)
+    }
+
+    /// Is `oid` a full hex oid of this format.
+    pub fn is_oid(&self, oid: &str) -> bool {
+        oid.len() == self.hex_len() && oid.bytes().all(|b| b.is_ascii_hexdigit())
+    }
+
+    /// The `ring` algorithm implementing this format.
+    pub fn algorithm(&self) -> &'static Algorithm {
+        match self {
+            Self::Sha1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+            Self::Sha256 => &digest::SHA256,
+        }
+    }
+
+    /// Start an incremental hash.
+    pub fn context(&self) -> digest::Context {
+        digest::Context::new(self.algorithm())
+    }
+
+    /// Hash `data`.
+    pub fn digest(&self, data: &[u8]) -> Vec<u8> {
+        digest::digest(self.algorithm(), data).as_ref().to_vec()
+    }
+
+    /// Hash `data` into an `ObjectId`.
+    pub fn digest_oid(&self, data: &[u8]) -> ObjectId {
+        let hash = digest::digest(self.algorithm(), data);
+        ObjectId::from_bytes(hash.as_ref(), *self).expect("HashAlgorithm: Digest has wrong length")
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "sha1" => Ok(Self::Sha1),
+            "sha256" => Ok(Self::Sha256),
+            _ => bail!("HashAlgorithm: Unknown object format {}", s),
+        }
+    }
+}
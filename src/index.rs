@@ -0,0 +1,325 @@
+use crate::{lock_timeout, HashAlgorithm, Lockfile, ObjectId, DEFAULT_LOCK_TIMEOUT};
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::{File, Metadata};
+use std::io::prelude::*;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Signature at the start of every index file.
+const SIGNATURE: &[u8] = b"DIRC";
+/// The only index version we read and write.
+const VERSION: u32 = 2;
+/// Size of the header: signature, version and number of entries.
+const HEADER_SIZE: usize = 12;
+
+/// The `Index` is the staging area in `.git/index`.
+/// It records which content will go into the next commit.
+pub struct Index {
+    /// Path to the index file.
+    pathname: PathBuf,
+
+    /// Hash algorithm of the oids and the checksum.
+    algorithm: HashAlgorithm,
+
+    /// Entries sorted by path and stage.
+    entries: BTreeMap<(String, u8), IndexEntry>,
+
+    /// Paths of all directories implied by the entries, with the entries
+    /// below them.
+    parents: HashMap<String, BTreeSet<String>>,
+
+    /// The lock we hold while updating the index.
+    lockfile: Option<Lockfile>,
+
+    /// How long to wait for an index locked by another process.
+    lock_timeout: Duration,
+
+    /// Have the entries been modified since loading.
+    changed: bool,
+
+    /// Trailing checksum of the file as last loaded or written, `None` if
+    /// there was no file.
+    checksum: Option<Vec<u8>>,
+}
+
+impl Index {
+    /// Create a new `Index` for the file at `pathname` of a repository using
+    /// `algorithm`.
+    pub fn new(pathname: PathBuf, algorithm: HashAlgorithm) -> Result<Self> {
+        trace!("Creating new index");
+        let lock_timeout = match pathname.parent() {
+            Some(git_path) => {
+                lock_timeout(git_path).with_context(|| "Index: Could not read lock timeout")?
+            }
+            None => DEFAULT_LOCK_TIMEOUT,
+        };
+
+        Ok(Self {
+            pathname,
+            algorithm,
+            entries: BTreeMap::new(),
+            parents: HashMap::new(),
+            lockfile: None,
+            lock_timeout,
+            changed: false,
+            checksum: None,
+        })
+    }
+
+    /// Load the index from disk, a missing file is an empty index.
+    pub fn load(&mut self) -> Result<()> {
+        trace!("Loading index {:?}", self.pathname);
+        self.clear();
+
+        if !self.pathname.exists() {
+            debug!("Index does not exist yet");
+            return Ok(());
+        }
+
+        let mut data = Vec::new();
+        File::open(&self.pathname)
+            .with_context(|| "Index: Could not open index")?
+            .read_to_end(&mut data)
+            .with_context(|| "Index: Could not read index")?;
+
+        let checksum_len = self.algorithm.output_len();
+        if data.len() < HEADER_SIZE + checksum_len {
+            bail!("Index: {:?} is truncated", self.pathname);
+        }
+
+        // verify the trailing checksum first
+        let (content, checksum) = data.split_at(data.len() - checksum_len);
+        if self.algorithm.digest(content) != checksum {
+            bail!("Index: Checksum does not match value stored on disk");
+        }
+        self.checksum = Some(checksum.to_vec());
+
+        // header
+        if &content[0..4] != SIGNATURE {
+            bail!("Index: Signature is not `DIRC`");
+        }
+        let version = u32::from_be_bytes(content[4..8].try_into().unwrap());
+        if version != VERSION {
+            bail!("Index: Version {} is not supported", version);
+        }
+        let count = u32::from_be_bytes(content[8..12].try_into().unwrap());
+        debug!("Index has {} entries", count);
+
+        // entries
+        let mut pos = HEADER_SIZE;
+        for _ in 0..count {
+            let (entry, size) = IndexEntry::parse(&content[pos..], self.algorithm)
+                .with_context(|| "Index: Could not parse entry")?;
+            self.store_entry(entry);
+            pos += size;
+        }
+
+        // extensions are optional caches we do not use
+        while pos + 8 <= content.len() {
+            let size = u32::from_be_bytes(content[pos + 4..pos + 8].try_into().unwrap());
+            debug!(
+                "Skipping index extension {}",
+                String::from_utf8_lossy(&content[pos..pos + 4])
+            );
+            pos += 8 + size as usize;
+        }
+
+        Ok(())
+    }
+
+    /// Acquire the lock and load the index.
+    pub fn load_for_update(&mut self) -> Result<()> {
+        trace!("Loading index for update");
+        self.lockfile = Some(
+            Lockfile::acquire(self.pathname.clone(), self.lock_timeout)
+                .with_context(|| "Index: Could not lock index")?,
+        );
+
+        self.load()
+    }
+
+    /// Write the index if it changed and release the lock.
+    pub fn write_updates(&mut self) -> Result<()> {
+        trace!("Writing index updates");
+        if !self.changed {
+            return self.release_lock();
+        }
+
+        let mut content = Vec::new();
+        content.extend(SIGNATURE);
+        content.extend(VERSION.to_be_bytes());
+        content.extend((self.entries.len() as u32).to_be_bytes());
+        for entry in self.entries.values() {
+            content.extend(entry.get_data());
+        }
+        let checksum = self.algorithm.digest(&content);
+        content.extend(&checksum);
+
+        let mut lockfile = self
+            .lockfile
+            .take()
+            .with_context(|| "Index: Not holding the index lock")?;
+        lockfile
+            .write_bytes(&content)
+            .with_context(|| "Index: lockfile write failed")?;
+        lockfile
+            .commit()
+            .with_context(|| "Index: Could not write changes to index")?;
+        self.changed = false;
+        self.checksum = Some(checksum);
+
+        Ok(())
+    }
+
+    /// Write the changes of an index loaded without lock, but only if the
+    /// lock is free right now and nobody changed the file since it was
+    /// loaded. Returns whether the index was written.
+    pub fn try_write_updates(&mut self) -> Result<bool> {
+        trace!("Trying to write index updates");
+        if !self.changed {
+            return Ok(false);
+        }
+        let lockfile = match Lockfile::acquire(self.pathname.clone(), Duration::ZERO) {
+            Ok(lockfile) => lockfile,
+            Err(err) => {
+                debug!("Not writing index: {:#}", err);
+                return Ok(false);
+            }
+        };
+        if self.read_checksum()? != self.checksum {
+            debug!("Not writing index, it changed since it was loaded");
+            return Ok(false);
+        }
+
+        self.lockfile = Some(lockfile);
+        self.write_updates()?;
+
+        Ok(true)
+    }
+
+    /// Read the trailing checksum of the file on disk, `None` if it does not
+    /// exist.
+    fn read_checksum(&self) -> Result<Option<Vec<u8>>> {
+        let mut file = match File::open(&self.pathname) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).with_context(|| "Index: Could not open index"),
+        };
+        let checksum_len = self.algorithm.output_len();
+        let mut checksum = vec![0; checksum_len];
+        file.seek(SeekFrom::End(-(checksum_len as i64)))
+            .and_then(|_| file.read_exact(&mut checksum))
+            .with_context(|| "Index: Could not read checksum")?;
+
+        Ok(Some(checksum))
+    }
+
+    /// Release the lock without writing anything.
+    pub fn release_lock(&mut self) -> Result<()> {
+        trace!("Releasing index lock");
+        match self.lockfile.take() {
+            Some(lockfile) => lockfile
+                .rollback()
+                .with_context(|| "Index: Could not release lock"),
+            None => Ok(()),
+        }
+    }
+
+    /// Add a file with its `Blob` oid and stat to the index.
+    pub fn add(&mut self, path: String, oid: ObjectId, stat: &Metadata) {
+        trace!("Adding {} to index", path);
+        let entry = IndexEntry::new(path, oid, stat);
+        self.discard_conflicts(&entry);
+        self.store_entry(entry);
+        self.changed = true;
+    }
+
+    /// Remove `path` and everything below it from the index.
+    pub fn remove(&mut self, path: &str) {
+        trace!("Removing {} from index", path);
+        if let Some(children) = self.parents.get(path).cloned() {
+            children.iter().for_each(|child| self.remove_entry(child));
+        }
+        self.remove_entry(path);
+        self.changed = true;
+    }
+
+    /// Is `path` tracked as a file in the index.
+    pub fn is_tracked_file(&self, path: &str) -> bool {
+        (0..=3).any(|stage| self.entries.contains_key(&(path.to_string(), stage)))
+    }
+
+    /// Is `path` tracked, either as a file or as a directory.
+    pub fn is_tracked(&self, path: &str) -> bool {
+        self.is_tracked_file(path) || self.parents.contains_key(path)
+    }
+
+    /// Get the entry of `path` at stage 0.
+    pub fn get_entry(&self, path: &str) -> Option<&IndexEntry> {
+        self.entries.get(&(path.to_string(), 0))
+    }
+
+    /// Refresh the cached stat data of `path` at stage 0.
+    pub fn update_entry_stat(&mut self, path: &str, stat: &Metadata) {
+        if let Some(entry) = self.entries.get_mut(&(path.to_string(), 0)) {
+            entry.update_stat(stat);
+            self.changed = true;
+        }
+    }
+
+    /// Return all entries sorted by path and stage.
+    pub fn get_entries(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.entries.values()
+    }
+
+    /// Remove every entry.
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.parents.clear();
+        self.changed = false;
+        self.checksum = None;
+    }
+
+    /// A file replaces any directory of the same name and vice versa.
+    fn discard_conflicts(&mut self, entry: &IndexEntry) {
+        for parent in entry.parent_directories() {
+            self.remove_entry(&parent);
+        }
+        if let Some(children) = self.parents.get(&entry.path).cloned() {
+            children.iter().for_each(|child| self.remove_entry(child));
+        }
+    }
+
+    /// Insert `entry`, updating the directory lookup.
+    fn store_entry(&mut self, entry: IndexEntry) {
+        for parent in entry.parent_directories() {
+            self.parents
+                .entry(parent)
+                .or_default()
+                .insert(entry.path.clone());
+        }
+        self.entries.insert(entry.key(), entry);
+    }
+
+    /// Remove all stages of the file `path`.
+    fn remove_entry(&mut self, path: &str) {
+        for stage in 0..=3 {
+            if let Some(entry) = self.entries.remove(&(path.to_string(), stage)) {
+                for parent in entry.parent_directories() {
+                    if let Some(children) = self.parents.get_mut(&parent) {
+                        children.remove(&entry.path);
+                        if children.is_empty() {
+                            self.parents.remove(&parent);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+mod entry;
+pub use entry::{IndexEntry, EXECUTABLE_MODE, REGULAR_MODE};
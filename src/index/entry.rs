@@ -0,0 +1,203 @@
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::fs::Metadata;
+use std::os::unix::fs::MetadataExt;
+
+use crate::{HashAlgorithm, ObjectId};
+
+/// File mode of a regular file.
+pub const REGULAR_MODE: u32 = 0o100644;
+/// File mode of an executable file.
+pub const EXECUTABLE_MODE: u32 = 0o100755;
+
+/// Size of the ten 32-bit stat fields at the start of an entry.
+const STAT_SIZE: usize = 10 * 4;
+/// Size of the 16-bit flags following the oid.
+const FLAGS_SIZE: usize = 2;
+/// Entries are padded with null-bytes to a multiple of this.
+const ENTRY_BLOCK: usize = 8;
+/// Largest path length that fits into the flags.
+const MAX_PATH_SIZE: usize = 0xfff;
+
+/// An `IndexEntry` caches the stat data and oid of a single file in the
+/// staging area.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexEntry {
+    pub ctime: u32,
+    pub ctime_nsec: u32,
+    pub mtime: u32,
+    pub mtime_nsec: u32,
+    pub dev: u32,
+    pub ino: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+
+    /// Object id of the staged `Blob`.
+    pub oid: ObjectId,
+
+    /// Flags holding the stage and the length of `path`.
+    pub flags: u16,
+
+    /// Path relative to the root of the repository.
+    pub path: String,
+}
+
+impl IndexEntry {
+    /// Create a new `IndexEntry` at stage 0 from a file's `Metadata`.
+    pub fn new(path: String, oid: ObjectId, stat: &Metadata) -> Self {
+        trace!("Creating index entry for {}", path);
+        let flags = path.len().min(MAX_PATH_SIZE) as u16;
+        Self {
+            ctime: stat.ctime() as u32,
+            ctime_nsec: stat.ctime_nsec() as u32,
+            mtime: stat.mtime() as u32,
+            mtime_nsec: stat.mtime_nsec() as u32,
+            dev: stat.dev() as u32,
+            ino: stat.ino() as u32,
+            mode: Self::mode_for_stat(stat),
+            uid: stat.uid(),
+            gid: stat.gid(),
+            size: stat.size() as u32,
+            oid,
+            flags,
+            path,
+        }
+    }
+
+    /// Return the git file mode for `stat`.
+    pub fn mode_for_stat(stat: &Metadata) -> u32 {
+        if stat.mode() & 0o111 != 0 {
+            EXECUTABLE_MODE
+        } else {
+            REGULAR_MODE
+        }
+    }
+
+    /// Does the size and mode of `stat` match this entry.
+    /// An entry with size 0 might not have its size recorded.
+    pub fn stat_match(&self, stat: &Metadata) -> bool {
+        (self.size == 0 || self.size == stat.size() as u32)
+            && self.mode == Self::mode_for_stat(stat)
+    }
+
+    /// Do the timestamps of `stat` match this entry.
+    pub fn times_match(&self, stat: &Metadata) -> bool {
+        self.ctime == stat.ctime() as u32
+            && self.ctime_nsec == stat.ctime_nsec() as u32
+            && self.mtime == stat.mtime() as u32
+            && self.mtime_nsec == stat.mtime_nsec() as u32
+    }
+
+    /// Refresh the cached stat data from `stat`.
+    pub fn update_stat(&mut self, stat: &Metadata) {
+        let stage_bits = self.flags & !(MAX_PATH_SIZE as u16);
+        *self = Self::new(self.path.clone(), self.oid, stat);
+        self.flags |= stage_bits;
+    }
+
+    /// Get the merge stage of this entry, 0 for a regular entry.
+    pub fn get_stage(&self) -> u8 {
+        ((self.flags >> 12) & 0x3) as u8
+    }
+
+    /// Get the file mode formatted as in a `Tree`.
+    pub fn get_mode(&self) -> String {
+        format!("{:o}", self.mode)
+    }
+
+    /// Key identifying this entry in the `Index`.
+    pub fn key(&self) -> (String, u8) {
+        (self.path.clone(), self.get_stage())
+    }
+
+    /// Return all parent directories of `path`, outermost first.
+    pub fn parent_directories(&self) -> Vec<String> {
+        let mut dirs = Vec::new();
+        let mut pos = 0;
+        while let Some(i) = self.path[pos..].find('/') {
+            dirs.push(self.path[..pos + i].to_string());
+            pos += i + 1;
+        }
+        dirs
+    }
+
+    /// Parse an `IndexEntry` from the start of `data`, returning it together
+    /// with the number of bytes consumed. The oid is hashed with `algorithm`.
+    pub fn parse(data: &[u8], algorithm: HashAlgorithm) -> Result<(Self, usize)> {
+        let oid_len = algorithm.output_len();
+        let header_size = STAT_SIZE + oid_len + FLAGS_SIZE;
+        if data.len() < header_size {
+            bail!("IndexEntry: Entry is truncated");
+        }
+        let word = |i: usize| u32::from_be_bytes(data[4 * i..4 * i + 4].try_into().unwrap());
+        let oid = ObjectId::from_bytes(&data[STAT_SIZE..STAT_SIZE + oid_len], algorithm)?;
+        let flags = u16::from_be_bytes(data[STAT_SIZE + oid_len..header_size].try_into().unwrap());
+
+        // the path is null terminated and padded
+        let null = data[header_size..]
+            .iter()
+            .position(|&b| b == b'\x00')
+            .with_context(|| "IndexEntry: Path is not null terminated")?;
+        let path = String::from_utf8(data[header_size..header_size + null].to_vec())
+            .with_context(|| "IndexEntry: Path is not valid utf-8")?;
+        let size = Self::padded_size(oid_len, path.len());
+        if data.len() < size {
+            bail!("IndexEntry: Entry for {} is truncated", path);
+        }
+
+        Ok((
+            Self {
+                ctime: word(0),
+                ctime_nsec: word(1),
+                mtime: word(2),
+                mtime_nsec: word(3),
+                dev: word(4),
+                ino: word(5),
+                mode: word(6),
+                uid: word(7),
+                gid: word(8),
+                size: word(9),
+                oid,
+                flags,
+                path,
+            },
+            size,
+        ))
+    }
+
+    /// Serialize this `IndexEntry` including its padding.
+    pub fn get_data(&self) -> Vec<u8> {
+        let oid = self.oid.as_bytes();
+        let size = Self::padded_size(oid.len(), self.path.len());
+        let mut data = Vec::with_capacity(size);
+        for word in [
+            self.ctime,
+            self.ctime_nsec,
+            self.mtime,
+            self.mtime_nsec,
+            self.dev,
+            self.ino,
+            self.mode,
+            self.uid,
+            self.gid,
+            self.size,
+        ] {
+            data.extend(word.to_be_bytes());
+        }
+        data.extend(oid);
+        data.extend(self.flags.to_be_bytes());
+        data.extend(self.path.as_bytes());
+        data.resize(size, b'\x00');
+
+        data
+    }
+
+    /// Size of an entry with a raw oid of length `oid_len` for a path of
+    /// length `len`, there is always at least one null-byte terminating the path.
+    fn padded_size(oid_len: usize, len: usize) -> usize {
+        let size = STAT_SIZE + oid_len + FLAGS_SIZE + len + 1;
+        size.div_ceil(ENTRY_BLOCK) * ENTRY_BLOCK
+    }
+}
@@ -0,0 +1,69 @@
+use crate::pack::{indexer, writer};
+use crate::*;
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::env::current_dir;
+use std::fs::{write, File};
+use std::io::{self, BufReader, Write};
+
+/// Build the `.idx` of a pack file, or store a pack read from stdin in the
+/// repository, and print the checksum of the pack.
+pub fn build_pack_index(index_pack: cli::IndexPack) -> Result<()> {
+    trace!("Indexing pack");
+    debug!("Got arguments: {:?}", index_pack);
+
+    if index_pack.stdin {
+        // get current directory
+        let root_path = current_dir()
+            .with_context(|| "IndexPack: Could not get the current working directory!")?;
+        let db_path = root_path.join(".git").join("objects");
+
+        // check if this is actually a repository.
+        if !is_repo(&root_path)? {
+            bail!("IndexPack: {:?} is not a rit repository!", root_path);
+        }
+
+        let database =
+            Database::new(&db_path).with_context(|| "IndexPack: Could not load database")?;
+        let name = database
+            .index_pack(io::stdin().lock())
+            .with_context(|| "IndexPack: Could not store pack")?;
+        writeln!(io::stdout(), "pack\t{}", name)?;
+
+        return Ok(());
+    }
+
+    let pack_path = index_pack
+        .pack
+        .with_context(|| "IndexPack: No pack given")?;
+    if pack_path.extension().is_none_or(|ext| ext != "pack") {
+        bail!("IndexPack: {:?} does not end in .pack", pack_path);
+    }
+    let idx_path = index_pack
+        .output
+        .unwrap_or_else(|| pack_path.with_extension("idx"));
+
+    let algorithm = match index_pack.object_format {
+        Some(algorithm) => algorithm,
+        None => HashAlgorithm::detect(
+            &current_dir()
+                .with_context(|| "IndexPack: Could not get the current working directory!")?
+                .join(".git"),
+        )?,
+    };
+
+    let file = File::open(&pack_path)
+        .with_context(|| format!("IndexPack: Could not open {:?}", pack_path))?;
+    let mut reader = BufReader::new(file);
+    let (checksum, entries) = indexer::Indexer::read(&mut reader, None::<io::Sink>, algorithm)
+        .and_then(|indexer| indexer.resolve(&mut reader))
+        .with_context(|| format!("IndexPack: Could not index {:?}", pack_path))?;
+    write(
+        &idx_path,
+        writer::write_index(&entries, &checksum, algorithm),
+    )
+    .with_context(|| format!("IndexPack: Could not write {:?}", idx_path))?;
+    writeln!(io::stdout(), "{}", hex::encode(checksum))?;
+
+    Ok(())
+}
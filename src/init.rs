@@ -1,11 +1,14 @@
-use crate::{cli::Init, is_repo};
+use crate::{cli::Init, is_repo, Config, HashAlgorithm, CONFIG, OBJECT_FORMAT_KEY, REFS_HEADS};
 use anyhow::{bail, Context, Result};
 use log::*;
 use std::{
-    fs::{canonicalize, create_dir_all},
+    fs::{canonicalize, create_dir_all, write},
     path::PathBuf,
 };
 
+/// Branch HEAD points to in a new repository.
+const DEFAULT_BRANCH: &str = "master";
+
 /// Create the directory structure of a repository.
 pub fn create_repo(init: Init) -> Result<()> {
     trace!("Creating directory structure");
@@ -31,12 +34,37 @@ pub fn create_repo(init: Init) -> Result<()> {
     }
 
     // create basic structure
-    for dir in ["objects", "refs"] {
+    for dir in ["objects", REFS_HEADS] {
         create_dir_all(git_path.join(dir))
             .with_context(|| "Init: failed to create basic directory structure")?;
         debug!("Created directory '{}' under git_path", dir);
     }
 
+    // HEAD starts out on an unborn branch
+    write(
+        git_path.join("HEAD"),
+        format!("ref: {}/{}\n", REFS_HEADS, DEFAULT_BRANCH),
+    )
+    .with_context(|| "Init: failed to write HEAD")?;
+    debug!("HEAD points to {}", DEFAULT_BRANCH);
+
+    // anything but SHA-1 needs a repository format supporting extensions
+    let mut config = Config::load(&git_path.join(CONFIG))?;
+    let version = match init.object_format {
+        HashAlgorithm::Sha1 => "0",
+        _ => "1",
+    };
+    config.set("core.repositoryformatversion", version)?;
+    config.set("core.filemode", "true")?;
+    config.set("core.bare", "false")?;
+    if init.object_format != HashAlgorithm::Sha1 {
+        config.set(OBJECT_FORMAT_KEY, init.object_format.name())?;
+    }
+    config
+        .save()
+        .with_context(|| "Init: failed to write config")?;
+    debug!("Object format is {}", init.object_format.name());
+
     info!("Initialized empty rit repository in {:?}", git_path);
     Ok(())
 }
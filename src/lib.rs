@@ -10,6 +10,45 @@ pub use commit::*;
 pub mod cat_file;
 pub use cat_file::*;
 
+pub mod add;
+pub use add::*;
+
+pub mod status;
+pub use status::*;
+
+pub mod branch;
+pub use branch::*;
+
+pub mod revision;
+pub use revision::*;
+
+pub mod rev_parse;
+pub use rev_parse::*;
+
+pub mod repack;
+pub use repack::*;
+
+pub mod index_pack;
+pub use index_pack::*;
+
+pub mod verify_pack;
+pub use verify_pack::*;
+
+pub mod fsck;
+pub use fsck::*;
+
+pub mod reflog;
+pub use reflog::*;
+
+pub mod update_ref;
+pub use update_ref::*;
+
+pub mod pack_refs;
+pub use pack_refs::*;
+
+pub mod tag;
+pub use tag::*;
+
 pub mod workspace;
 pub use workspace::*;
 
@@ -19,6 +58,12 @@ pub use utility::*;
 pub mod database;
 pub use database::*;
 
+pub mod hash;
+pub use hash::*;
+
+pub mod config;
+pub use config::*;
+
 pub mod types;
 pub use types::*;
 
@@ -30,3 +75,6 @@ pub use refs::*;
 
 pub mod lockfile;
 pub use lockfile::*;
+
+pub mod index;
+pub use index::*;
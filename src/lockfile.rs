@@ -1,10 +1,28 @@
 use anyhow::{bail, Context, Result};
 use log::*;
-use std::fs::{rename, File};
-use std::io::Write;
+use rand::{thread_rng, Rng};
+use std::fs::{metadata, remove_file, rename, File};
+use std::io::{ErrorKind, Write};
 use std::path::*;
-
-/// A `Lockfile` should prevent simultaneous access to a file.
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{Config, CONFIG};
+
+/// Config key holding how many milliseconds to retry taking a held lock.
+pub const LOCK_TIMEOUT_KEY: &str = "core.locktimeout";
+/// How long to retry taking a held lock if not configured.
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_millis(1000);
+/// Locks older than this were most likely left behind by a crashed process.
+pub const STALE_LOCK_AGE: Duration = Duration::from_secs(10 * 60);
+/// First wait between attempts, doubled after each one.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(1);
+/// Longest wait between attempts.
+const MAX_BACKOFF: Duration = Duration::from_millis(100);
+
+/// A `Lockfile` prevents simultaneous access to a file.
+/// It is held from `acquire` until it is committed, rolled back or dropped,
+/// a dropped lock is rolled back.
 pub struct Lockfile {
     /// The file that is locked.
     file_path: PathBuf,
@@ -12,94 +30,160 @@ pub struct Lockfile {
     /// The file-lock.
     lock_path: PathBuf,
 
-    /// Is `file_path` locked.
+    /// The open lock, `None` once it was released.
     lock: Option<File>,
 }
 
 impl Lockfile {
-    /// Create a new `Lockfile`.
-    pub fn new(path: PathBuf) -> Result<Self> {
-        trace!("Creating new Lockfile");
+    /// Acquire the lock on `path`. While another process holds it, retry with
+    /// a growing backoff for up to `timeout`.
+    pub fn acquire(path: PathBuf, timeout: Duration) -> Result<Self> {
+        trace!("Acquiring lock on {:?}", path);
         let file_path = path;
-        let mut lock_path = file_path.to_owned();
-        lock_path.set_extension("lock");
-        Ok(Self {
-            file_path,
-            lock_path,
-            lock: None,
-        })
-    }
-
-    /// Try acquiring the lock.
-    /// false: we must not wait
-    /// true: we must wait
-    pub fn hold_for_update(&mut self) -> Result<bool> {
-        trace!("Trying to acquire lock");
-        // we hold the lock
-        if self.lock.is_some() {
-            debug!("Lock is held by us");
-            return Ok(false);
-        }
-
-        // is it locked by somebody else
-        if self.lock_path.exists() {
-            debug!("Lock is held by else");
-            return Ok(true);
-        }
-
-        // create it
-        self.lock = Some(
-            File::options()
+        // append `.lock`, ref names may contain dots themselves
+        let mut lock_path = file_path.to_owned().into_os_string();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        let start = Instant::now();
+        let mut backoff = INITIAL_BACKOFF;
+        loop {
+            let error = match File::options()
                 .read(true)
                 .write(true)
                 .create_new(true)
-                .open(&self.lock_path)
-                .with_context(|| "Lockfile: Failed to create lockfile")?,
-        );
-        debug!("Lock aquired");
-
-        Ok(false)
+                .open(&lock_path)
+            {
+                Ok(file) => {
+                    debug!("Lock acquired");
+                    return Ok(Self {
+                        file_path,
+                        lock_path,
+                        lock: Some(file),
+                    });
+                }
+                Err(err) => err,
+            };
+            if error.kind() != ErrorKind::AlreadyExists {
+                return Err(error)
+                    .with_context(|| format!("Lockfile: Could not create {:?}", lock_path));
+            }
+
+            let age = Self::age(&lock_path);
+            if age >= STALE_LOCK_AGE {
+                bail!(
+                    "Lockfile: {:?} is {} old, it was probably left behind by a crashed \
+                     process. Remove it if no other rit process is running.",
+                    lock_path,
+                    format_age(age)
+                );
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                bail!(
+                    "Lockfile: Unable to lock {:?}, held by another process for {}",
+                    lock_path,
+                    format_age(age)
+                );
+            }
+
+            // jitter keeps waiting processes from retrying in lockstep
+            let wait = backoff.mul_f64(thread_rng().gen_range(0.5..1.5));
+            debug!("{:?} is held, retrying in {:?}", lock_path, wait);
+            sleep(wait.min(timeout - elapsed));
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
     }
 
     /// Write the data to the lockfile.
     pub fn write(&mut self, s: String) -> Result<()> {
-        trace!("Trying to write to lockfile");
-        self.raise_on_stale_lock()
-            .with_context(|| "Lockfile: While trying write did not have lock")?;
+        self.write_bytes(s.as_bytes())
+    }
 
+    /// Write raw bytes to the lockfile.
+    pub fn write_bytes(&mut self, data: &[u8]) -> Result<()> {
+        trace!("Trying to write to lockfile");
         self.lock
             .as_ref()
-            .unwrap()
-            .write(s.as_bytes())
-            .with_context(|| "Lockfile: Could not write to lockfile")?;
+            .with_context(|| format!("Lockfile: Not holding lock {:?}", self.lock_path))?
+            .write_all(data)
+            .with_context(|| format!("Lockfile: Could not write to {:?}", self.lock_path))?;
         debug!("Write successful");
 
         Ok(())
     }
 
-    /// Commit changes to the file.
-    pub fn commit(&mut self) -> Result<()> {
-        trace!("Committing changes for lockfile");
-        self.raise_on_stale_lock()
-            .with_context(|| "Lockfile: While trying to commit did not have lock")?;
+    /// Release the lock without changing the file.
+    pub fn rollback(mut self) -> Result<()> {
+        trace!("Rolling back lockfile");
+        drop(self.lock.take());
+        remove_file(&self.lock_path)
+            .with_context(|| format!("Lockfile: Could not remove {:?}", self.lock_path))?;
+        debug!("Rollback successful");
 
+        Ok(())
+    }
+
+    /// Replace the file with the written data and release the lock.
+    pub fn commit(mut self) -> Result<()> {
+        trace!("Committing changes for lockfile");
         drop(self.lock.take());
-        rename(&self.lock_path, &self.file_path)
-            .with_context(|| "Lockfile: Could not rename lockfile")?;
+        if let Err(err) = rename(&self.lock_path, &self.file_path) {
+            remove_file(&self.lock_path).ok();
+            return Err(err)
+                .with_context(|| format!("Lockfile: Could not rename {:?}", self.lock_path));
+        }
         debug!("Commit successful");
 
         Ok(())
     }
 
-    /// Raise an error if we do not have the lock
-    fn raise_on_stale_lock(&self) -> Result<()> {
-        trace!("Checking if lockfile is stale");
-        // is there a lock?
-        if self.lock.is_none() {
-            bail!("Not holding lock on file {:?}", self.lock_path);
+    /// How long ago the lock at `lock_path` was taken, zero if unknown.
+    fn age(lock_path: &Path) -> Duration {
+        metadata(lock_path)
+            .and_then(|stat| stat.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for Lockfile {
+    fn drop(&mut self) {
+        // neither committed nor rolled back, e.g. after an error
+        if self.lock.take().is_some() {
+            debug!("Rolling back dropped lock {:?}", self.lock_path);
+            if let Err(err) = remove_file(&self.lock_path) {
+                warn!("Could not remove lock {:?}: {}", self.lock_path, err);
+            }
         }
-        debug!("Lockfile not stale");
+    }
+}
 
-        Ok(())
+/// Read how long to retry taking a lock from the config of the repository
+/// at `git_path`.
+pub fn lock_timeout(git_path: &Path) -> Result<Duration> {
+    let config = Config::load(&git_path.join(CONFIG))?;
+    let millis = match config.get(LOCK_TIMEOUT_KEY) {
+        Some(millis) => millis,
+        None => return Ok(DEFAULT_LOCK_TIMEOUT),
+    };
+    let millis = millis.parse().with_context(|| {
+        format!(
+            "Lockfile: {} = {} is not a number",
+            LOCK_TIMEOUT_KEY, millis
+        )
+    })?;
+
+    Ok(Duration::from_millis(millis))
+}
+
+/// Format `age` in whole seconds, minutes or hours.
+fn format_age(age: Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        0..=119 => format!("{}s", seconds),
+        120..=7199 => format!("{}m", seconds / 60),
+        _ => format!("{}h", seconds / 3600),
     }
 }
@@ -20,7 +20,49 @@ fn main() -> Result<()> {
             make_commit(commit).with_context(|| "Main: make_commit unsuccessful")?;
         }
         cli::Commands::CatFile(cat_file) => {
-            print_object(cat_file).with_context(|| "Main: print_object unsuccessful")?;
+            let code = print_object(cat_file).with_context(|| "Main: print_object unsuccessful")?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+        cli::Commands::Add(add) => {
+            add_files(add).with_context(|| "Main: add_files unsuccessful")?;
+        }
+        cli::Commands::Status(status) => {
+            show_status(status).with_context(|| "Main: show_status unsuccessful")?;
+        }
+        cli::Commands::Branch(branch) => {
+            manage_branches(branch).with_context(|| "Main: manage_branches unsuccessful")?;
+        }
+        cli::Commands::RevParse(rev_parse) => {
+            parse_revisions(rev_parse).with_context(|| "Main: parse_revisions unsuccessful")?;
+        }
+        cli::Commands::Repack(repack) => {
+            repack_objects(repack).with_context(|| "Main: repack_objects unsuccessful")?;
+        }
+        cli::Commands::IndexPack(index_pack) => {
+            build_pack_index(index_pack).with_context(|| "Main: build_pack_index unsuccessful")?;
+        }
+        cli::Commands::VerifyPack(verify_pack) => {
+            verify_packs(verify_pack).with_context(|| "Main: verify_packs unsuccessful")?;
+        }
+        cli::Commands::Fsck(fsck) => {
+            let code = check_objects(fsck).with_context(|| "Main: check_objects unsuccessful")?;
+            if code != 0 {
+                std::process::exit(code);
+            }
+        }
+        cli::Commands::Reflog(reflog) => {
+            manage_reflog(reflog).with_context(|| "Main: manage_reflog unsuccessful")?;
+        }
+        cli::Commands::UpdateRef(update_ref) => {
+            update_refs(update_ref).with_context(|| "Main: update_refs unsuccessful")?;
+        }
+        cli::Commands::PackRefs(args) => {
+            pack_refs(args).with_context(|| "Main: pack_refs unsuccessful")?;
+        }
+        cli::Commands::Tag(tag) => {
+            manage_tags(tag).with_context(|| "Main: manage_tags unsuccessful")?;
         }
         cli::Commands::External(args) => {
             println!("Calling out to {:?} with {:?}", &args[0], &args[1..]);
@@ -0,0 +1,30 @@
+use crate::*;
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::env::current_dir;
+
+/// Move the loose refs into `packed-refs`.
+pub fn pack_refs(pack_refs: cli::PackRefs) -> Result<()> {
+    trace!("Packing refs");
+    debug!("Got arguments: {:?}", pack_refs);
+
+    // get current directory
+    let root_path =
+        current_dir().with_context(|| "PackRefs: Could not get the current working directory!")?;
+    let git_path = root_path.join(".git");
+    let db_path = git_path.join("objects");
+
+    // check if this is actually a repository.
+    if !is_repo(&root_path)? {
+        bail!("PackRefs: {:?} is not a rit repository!", root_path);
+    }
+
+    let database = Database::new(&db_path).with_context(|| "PackRefs: Could not load database")?;
+    let refs = Refs::new(git_path).with_context(|| "PackRefs: Could not load refs")?;
+    let count = refs
+        .pack_refs(&database, pack_refs.all, !pack_refs.no_prune)
+        .with_context(|| "PackRefs: Could not pack refs")?;
+    info!("Packed {} refs", count);
+
+    Ok(())
+}
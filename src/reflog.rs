@@ -0,0 +1,205 @@
+use crate::cli::reflog::{ReflogDelete, ReflogExpire};
+use crate::*;
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::collections::BTreeMap;
+use std::env::current_dir;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Config key overriding the default age of expired reflog entries.
+pub const REFLOG_EXPIRE_KEY: &str = "gc.reflogexpire";
+/// Age of expired reflog entries if neither given nor configured.
+pub const DEFAULT_REFLOG_EXPIRE: &str = "90.days.ago";
+
+/// Show, expire or delete reflog entries.
+pub fn manage_reflog(reflog: cli::Reflog) -> Result<()> {
+    trace!("Managing reflogs");
+    debug!("Got arguments: {:?}", reflog);
+
+    // get current directory
+    let root_path =
+        current_dir().with_context(|| "Reflog: Could not get the current working directory!")?;
+    let git_path = root_path.join(".git");
+    let db_path = git_path.join("objects");
+
+    // check if this is actually a repository.
+    if !is_repo(&root_path)? {
+        bail!("Reflog: {:?} is not a rit repository!", root_path);
+    }
+
+    let database = Database::new(&db_path).with_context(|| "Reflog: Could not load database")?;
+    let refs = Refs::new(git_path).with_context(|| "Reflog: Could not load refs")?;
+
+    match reflog.command {
+        None => show_reflog(reflog.name.as_deref(), &database, &refs),
+        Some(cli::ReflogCommands::Show(show)) => {
+            show_reflog(show.name.as_deref(), &database, &refs)
+        }
+        Some(cli::ReflogCommands::Expire(expire)) => expire_reflogs(&expire, &refs),
+        Some(cli::ReflogCommands::Delete(delete)) => delete_entries(&delete, &refs),
+    }
+}
+
+/// Print the reflog of `name`, newest entry first.
+fn show_reflog(name: Option<&str>, database: &Database, refs: &Refs) -> Result<()> {
+    let name = name.unwrap_or(HEAD);
+    let full_name = full_ref_name(name, refs)?;
+
+    for (n, entry) in refs.reflog_entries(&full_name)?.iter().rev().enumerate() {
+        let oid = database
+            .short_oid(&entry.new)
+            .unwrap_or_else(|_| entry.new.abbrev(ABBREV_LEN));
+        println!("{} {}@{{{}}}: {}", oid, name, n, entry.message);
+    }
+
+    Ok(())
+}
+
+/// Remove the entries older than the expiry time from the given reflogs.
+fn expire_reflogs(expire: &ReflogExpire, refs: &Refs) -> Result<()> {
+    let expiry = match &expire.expire {
+        Some(expiry) => expiry.clone(),
+        None => Config::load(&refs.get_path().join(CONFIG))?
+            .get(REFLOG_EXPIRE_KEY)
+            .unwrap_or(DEFAULT_REFLOG_EXPIRE)
+            .to_string(),
+    };
+    let cutoff = match parse_expiry(&expiry, now()?)? {
+        Some(cutoff) => cutoff,
+        None => return Ok(()),
+    };
+    debug!("Expiring reflog entries before {}", cutoff);
+
+    let names = if expire.all {
+        refs.list_reflogs()?
+    } else {
+        expire
+            .refs
+            .iter()
+            .map(|name| full_ref_name(name, refs))
+            .collect::<Result<Vec<String>>>()?
+    };
+
+    for full_name in names {
+        let entries = refs.reflog_entries(&full_name)?;
+        let count = entries.len();
+        let mut kept = Vec::new();
+        for entry in entries {
+            // entries with a broken time are kept rather than guessed at
+            if entry.get_timestamp().is_ok_and(|time| time < cutoff) {
+                if expire.dry_run {
+                    println!("would prune {}", entry.message);
+                }
+            } else {
+                kept.push(entry);
+            }
+        }
+
+        if kept.len() != count && !expire.dry_run {
+            info!("Pruning {} entries of {}", count - kept.len(), full_name);
+            refs.write_reflog(&full_name, &kept)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove the entries named like `HEAD@{2}`.
+fn delete_entries(delete: &ReflogDelete, refs: &Refs) -> Result<()> {
+    // collect the positions per reflog, newest entry is 0
+    let mut positions: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for spec in delete.entries.iter() {
+        let (name, n) = spec
+            .strip_suffix('}')
+            .and_then(|spec| spec.rsplit_once("@{"))
+            .with_context(|| format!("Reflog: '{}' is not a reflog entry", spec))?;
+        let n: usize = n
+            .parse()
+            .with_context(|| format!("Reflog: '{}' is not a reflog entry", spec))?;
+        let name = if name.is_empty() { HEAD } else { name };
+        positions
+            .entry(full_ref_name(name, refs)?)
+            .or_default()
+            .push(n);
+    }
+
+    for (full_name, mut positions) in positions {
+        let mut entries = refs.reflog_entries(&full_name)?;
+        positions.sort_unstable();
+        positions.dedup();
+        // remove from the oldest end so the positions stay valid
+        for n in positions.into_iter().rev() {
+            if n >= entries.len() {
+                bail!(
+                    "Reflog: log for '{}' only has {} entries",
+                    Refs::short_name(&full_name),
+                    entries.len()
+                );
+            }
+            let entry = entries.remove(entries.len() - 1 - n);
+            if delete.dry_run {
+                println!("would prune {}", entry.message);
+            }
+        }
+
+        if !delete.dry_run {
+            refs.write_reflog(&full_name, &entries)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Expand `name` into the full name of an existing ref.
+fn full_ref_name(name: &str, refs: &Refs) -> Result<String> {
+    if name == HEAD || name == "@" {
+        return Ok(HEAD.to_string());
+    }
+    refs.full_name(name)
+        .with_context(|| format!("Reflog: unknown ref '{}'", name))
+}
+
+/// Seconds since the epoch.
+fn now() -> Result<i64> {
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .with_context(|| "Reflog: Could not get the time")?
+        .as_secs() as i64)
+}
+
+/// Parse an expiry time like `90.days.ago`, `2 weeks ago`, `now`, `all`,
+/// `never` or seconds since the epoch into the time before which entries
+/// expire, `None` if nothing expires.
+pub fn parse_expiry(value: &str, now: i64) -> Result<Option<i64>> {
+    match value.to_lowercase().as_str() {
+        "never" | "false" => return Ok(None),
+        "all" => return Ok(Some(i64::MAX)),
+        "now" => return Ok(Some(now)),
+        _ => (),
+    }
+    if let Ok(time) = value.parse() {
+        return Ok(Some(time));
+    }
+
+    let normalized = value.to_lowercase().replace('.', " ");
+    let words: Vec<&str> = normalized.split_whitespace().collect();
+    let (count, unit) = match words.as_slice() {
+        [count, unit] | [count, unit, "ago"] => (*count, *unit),
+        _ => bail!("Reflog: Invalid expiry time '{}'", value),
+    };
+    let count: i64 = count
+        .parse()
+        .with_context(|| format!("Reflog: Invalid expiry time '{}'", value))?;
+    let seconds = match unit.strip_suffix('s').unwrap_or(unit) {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        "week" => 7 * 24 * 60 * 60,
+        "month" => 30 * 24 * 60 * 60,
+        "year" => 365 * 24 * 60 * 60,
+        _ => bail!("Reflog: Unknown time unit '{}' in '{}'", unit, value),
+    };
+
+    Ok(Some(now - count * seconds))
+}
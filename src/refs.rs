@@ -1,16 +1,144 @@
 use anyhow::{bail, Context, Result};
 use log::*;
-use std::fs::File;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::env::var;
+use std::fmt::Display;
+use std::fs::{
+    create_dir_all, metadata, read_dir, read_to_string, remove_dir, remove_file, rename, write,
+    File,
+};
 use std::io::prelude::*;
+use std::io::ErrorKind;
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::Lockfile;
+use crate::{
+    lock_timeout, Author, Config, Database, Lockfile, ObjectId, ObjectStore, ParsedObject, Types,
+    CONFIG, GIT_AUTHOR_EMAIL, GIT_AUTHOR_NAME, GIT_COMMITTER_EMAIL, GIT_COMMITTER_NAME,
+};
+
+/// Name of the file pointing to the current branch or commit.
+pub const HEAD: &str = "HEAD";
+/// Directory holding all branches.
+pub const REFS_HEADS: &str = "refs/heads";
+/// Directory holding all tags.
+pub const REFS_TAGS: &str = "refs/tags";
+/// Directory holding all refs.
+pub const REFS: &str = "refs";
+/// Directory holding the reflogs.
+pub const LOGS: &str = "logs";
+/// Prefix marking a symbolic ref.
+const SYMREF_PREFIX: &str = "ref: ";
+/// Maximum depth of symbolic refs we follow, as in git.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// Modification time and size identifying a version of a file.
+type FileStamp = (SystemTime, u64);
+
+/// The content of a single ref file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RefValue {
+    /// Points to another ref, e.g. `refs/heads/master`.
+    Symbolic(String),
+
+    /// Points directly to an object.
+    Oid(ObjectId),
+}
+
+/// The state of HEAD.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HeadState {
+    /// HEAD points to a branch, which might not have any commits yet.
+    Branch(String),
+
+    /// HEAD points directly to a commit.
+    Detached(ObjectId),
+}
+
+/// A single line of a reflog.
+#[derive(Clone, Debug)]
+pub struct ReflogEntry {
+    /// Value of the ref before the update, null if the ref was created.
+    pub old: ObjectId,
+
+    /// Value of the ref after the update.
+    pub new: ObjectId,
+
+    /// Who updated the ref and when.
+    pub committer: Author,
+
+    /// Reason for the update, e.g. `commit: Fix typo`.
+    pub message: String,
+}
+
+impl ReflogEntry {
+    /// Create a `ReflogEntry`, squeezing the whitespace of `message` into
+    /// single spaces as git does.
+    pub fn new(old: ObjectId, new: ObjectId, committer: Author, message: &str) -> Self {
+        Self {
+            old,
+            new,
+            committer,
+            message: message.split_whitespace().collect::<Vec<&str>>().join(" "),
+        }
+    }
+
+    /// Parse a line of the form `old new name <email> time tz\tmessage`.
+    pub fn parse(line: &str) -> Result<Self> {
+        let (header, message) = line.split_once('\t').unwrap_or((line, ""));
+        let mut fields = header.splitn(3, ' ');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(old), Some(new), Some(committer)) => Ok(Self {
+                old: old
+                    .parse()
+                    .with_context(|| "Refs: Invalid old oid in reflog")?,
+                new: new
+                    .parse()
+                    .with_context(|| "Refs: Invalid new oid in reflog")?,
+                committer: Author::parse(committer)?,
+                message: message.to_string(),
+            }),
+            _ => bail!("Refs: Malformed reflog line {:?}", line),
+        }
+    }
+
+    /// Seconds since the epoch at which the ref was updated.
+    pub fn get_timestamp(&self) -> Result<i64> {
+        self.committer
+            .time
+            .split(' ')
+            .next()
+            .unwrap_or_default()
+            .parse()
+            .with_context(|| format!("Refs: Invalid time {:?} in reflog", self.committer.time))
+    }
+}
+
+impl Display for ReflogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.old, self.new, self.committer)?;
+        if !self.message.is_empty() {
+            write!(f, "\t{}", self.message)?;
+        }
+
+        Ok(())
+    }
+}
 
 /// `Refs` manages all files under ~.git/refs~.
 /// It also can update HEAD.
 pub struct Refs {
     /// Points to ~.git~.
     pathname: PathBuf,
+
+    /// `packed-refs` as last read, with the modification time and size it
+    /// had then.
+    packed: RefCell<Option<(FileStamp, Rc<PackedRefs>)>>,
+
+    /// How long to wait for refs locked by another process.
+    lock_timeout: Duration,
 }
 
 impl Refs {
@@ -21,64 +149,788 @@ impl Refs {
             bail!("Refs: .git does not exists!");
         }
 
-        Ok(Self { pathname })
+        let lock_timeout =
+            lock_timeout(&pathname).with_context(|| "Refs: Could not read lock timeout")?;
+
+        Ok(Self {
+            pathname,
+            packed: RefCell::new(None),
+            lock_timeout,
+        })
     }
 
-    /// Update HEAD to point to a new `Tree`.
-    pub fn update_head(&self, oid: String) -> Result<()> {
+    /// Update whatever HEAD points to, the current branch or HEAD itself if
+    /// it is detached, logging `message` as the reason.
+    pub fn update_head(&self, oid: ObjectId, message: &str) -> Result<()> {
         trace!("Updating HEAD to {}", oid);
-        let mut lockfile = Lockfile::new(self.head_path())
-            .with_context(|| "Refs: Failed to create lockfile while updating HEAD")?;
+        let target = match self
+            .head_state()
+            .with_context(|| "Refs: Could not determine state of HEAD")?
+        {
+            HeadState::Branch(branch) => branch,
+            HeadState::Detached(_) => HEAD.to_string(),
+        };
+
+        self.update_ref(&target, oid, message)
+            .with_context(|| "Refs: Could not write changes to HEAD")
+    }
+
+    /// Point HEAD to the branch `name` or, if there is no such branch, detach
+    /// it at `oid`.
+    pub fn set_head(&self, name: &str, oid: ObjectId) -> Result<()> {
+        trace!("Setting HEAD to {}", name);
+        let branch = format!("{}/{}", REFS_HEADS, name);
+        let value = if self.ref_exists(&branch) {
+            RefValue::Symbolic(branch)
+        } else {
+            RefValue::Oid(oid)
+        };
+
+        self.write_ref(HEAD, &value)
+            .with_context(|| "Refs: Could not set HEAD")
+    }
+
+    /// Read the HEAD file if it exists and return the oid it resolves to.
+    pub fn read_head(&self) -> Result<Option<ObjectId>> {
+        trace!("Reading HEAD");
+        self.read_ref(HEAD)
+    }
 
-        if lockfile
-            .hold_for_update()
-            .with_context(|| "Refs: Lockfile creation went wrong")?
+    /// Determine whether HEAD is on a branch or detached.
+    pub fn head_state(&self) -> Result<HeadState> {
+        trace!("Reading state of HEAD");
+        match self
+            .read_ref_file(HEAD)
+            .with_context(|| "Refs: Could not read HEAD")?
         {
-            bail!("Could not acquire lock on file: {:?}", self.head_path());
+            Some(RefValue::Symbolic(target)) => Ok(HeadState::Branch(target)),
+            Some(RefValue::Oid(oid)) => Ok(HeadState::Detached(oid)),
+            None => bail!("Refs: HEAD does not exist, corrupt repository?"),
+        }
+    }
+
+    /// Return the name of the current branch, without `refs/heads/`.
+    pub fn current_branch(&self) -> Result<Option<String>> {
+        match self.head_state()? {
+            HeadState::Branch(branch) => Ok(Some(Self::short_name(&branch))),
+            HeadState::Detached(_) => Ok(None),
+        }
+    }
+
+    /// Resolve `name` to an oid, following symbolic refs.
+    /// `name` is looked up as is, then below `refs`, `refs/tags` and `refs/heads`.
+    /// An unborn branch resolves to `None`.
+    pub fn read_ref(&self, name: &str) -> Result<Option<ObjectId>> {
+        trace!("Reading ref {}", name);
+        let path = match self.find_ref(name) {
+            Some(path) => path,
+            None => {
+                debug!("Ref {} does not exist", name);
+                return Ok(None);
+            }
+        };
+        self.resolve(&path, 0)
+    }
+
+    /// Update the ref `name`, a full name like `refs/heads/master`, to `oid`
+    /// and record the update with `message` in its reflog.
+    pub fn update_ref(&self, name: &str, oid: ObjectId, message: &str) -> Result<()> {
+        trace!("Updating ref {} to {}", name, oid);
+        let mut transaction = RefTransaction::new(self);
+        transaction.update(name, oid, None, message)?;
+        transaction.commit()
+    }
+
+    /// Create the branch `name` pointing to `oid`, logging `message`.
+    /// An existing branch is only overwritten if `force` is set.
+    pub fn create_branch(
+        &self,
+        name: &str,
+        oid: ObjectId,
+        force: bool,
+        message: &str,
+    ) -> Result<()> {
+        trace!("Creating branch {}", name);
+        if !is_valid_ref_name(name) || name == HEAD {
+            bail!("Refs: '{}' is not a valid branch name", name);
+        }
+        let branch = Self::branch_ref(name);
+        if !force && self.ref_exists(&branch) {
+            bail!("Refs: A branch named '{}' already exists", name);
+        }
+
+        // without force the branch must still not exist once it is locked
+        let mut transaction = RefTransaction::new(self);
+        if force {
+            transaction.update(&branch, oid, None, message)?;
+        } else {
+            transaction.create(&branch, oid, message)?;
+        }
+        transaction.commit()
+    }
+
+    /// Delete the branch `name` and its reflog, returning the oid it pointed to.
+    pub fn delete_branch(&self, name: &str) -> Result<ObjectId> {
+        trace!("Deleting branch {}", name);
+        let branch = Self::branch_ref(name);
+        let oid = match self.read_ref_file(&branch)? {
+            Some(RefValue::Oid(oid)) => oid,
+            Some(RefValue::Symbolic(_)) => bail!("Refs: Branch '{}' is a symbolic ref", name),
+            None => bail!("Refs: Branch '{}' not found", name),
+        };
+
+        // fails if the branch moved since we read it
+        let mut transaction = RefTransaction::new(self);
+        transaction.delete(&branch, Some(oid), "")?;
+        transaction
+            .commit()
+            .with_context(|| format!("Refs: Could not delete branch {}", name))?;
+
+        Ok(oid)
+    }
+
+    /// Rename the branch `old` to `new` together with its reflog.
+    /// HEAD follows the branch if it is checked out.
+    pub fn rename_branch(&self, old: &str, new: &str, force: bool) -> Result<()> {
+        trace!("Renaming branch {} to {}", old, new);
+        let message = format!(
+            "Branch: renamed {} to {}",
+            Self::branch_ref(old),
+            Self::branch_ref(new)
+        );
+        self.transfer_branch(old, new, force, true, &message)?;
+
+        Ok(())
+    }
+
+    /// Copy the branch `old` to `new` together with its reflog.
+    pub fn copy_branch(&self, old: &str, new: &str, force: bool) -> Result<()> {
+        let message = format!(
+            "Branch: copied {} to {}",
+            Self::branch_ref(old),
+            Self::branch_ref(new)
+        );
+        self.transfer_branch(old, new, force, false, &message)?;
+
+        Ok(())
+    }
+
+    /// Create the branch `new` at `old` with a copy of its reflog, to which
+    /// `message` is appended, and delete `old` if `delete_old` is set.
+    /// HEAD then follows `old` if it points to it. All of this happens in one
+    /// transaction. Returns the oid of the branches.
+    fn transfer_branch(
+        &self,
+        old: &str,
+        new: &str,
+        force: bool,
+        delete_old: bool,
+        message: &str,
+    ) -> Result<ObjectId> {
+        trace!("Copying branch {} to {}", old, new);
+        let old_branch = Self::branch_ref(old);
+        let oid = match self.read_ref_file(&old_branch)? {
+            Some(RefValue::Oid(oid)) => oid,
+            _ => bail!("Refs: No branch named '{}'", old),
+        };
+        if old == new {
+            return Ok(oid);
+        }
+        if !is_valid_ref_name(new) || new == HEAD {
+            bail!("Refs: '{}' is not a valid branch name", new);
+        }
+        let branch = Self::branch_ref(new);
+        if !force && self.ref_exists(&branch) {
+            bail!("Refs: A branch named '{}' already exists", new);
+        }
+        let mut transaction = RefTransaction::new(self);
+        if force {
+            transaction.update(&branch, oid, None, message)?;
+        } else {
+            transaction.create(&branch, oid, message)?;
+        }
+        if delete_old {
+            transaction.delete(&old_branch, Some(oid), "")?;
+            if self.current_branch()?.as_deref() == Some(old) {
+                transaction.update_symbolic(HEAD, &branch, Some(&old_branch), message)?;
+            }
+        } else {
+            // keeps the old branch and its reflog still while it is copied
+            transaction.verify(&old_branch, oid)?;
+        }
+        // the new branch continues the history of the old one
+        transaction.copy_reflog(&branch, &old_branch)?;
+        transaction
+            .commit()
+            .with_context(|| format!("Refs: Could not transfer branch {} to {}", old, new))?;
+
+        Ok(oid)
+    }
+
+    /// List the short names of all branches, sorted.
+    pub fn list_branches(&self) -> Result<Vec<String>> {
+        trace!("Listing branches");
+        let mut branches = Vec::new();
+        self.list_refs(REFS_HEADS, &mut branches)?;
+        branches.sort();
+
+        Ok(branches.iter().map(|name| Self::short_name(name)).collect())
+    }
+
+    /// List the short names of all tags, sorted.
+    pub fn list_tags(&self) -> Result<Vec<String>> {
+        trace!("Listing tags");
+        let mut tags = Vec::new();
+        self.list_refs(REFS_TAGS, &mut tags)?;
+        tags.sort();
+
+        Ok(tags
+            .iter()
+            .filter_map(|name| name.strip_prefix(&format!("{}/", REFS_TAGS)))
+            .map(|name| name.to_string())
+            .collect())
+    }
+
+    /// List the full names of all refs below `refs`, sorted.
+    pub fn list_all(&self) -> Result<Vec<String>> {
+        trace!("Listing all refs");
+        let mut refs = Vec::new();
+        self.list_refs(REFS, &mut refs)?;
+        refs.sort();
+
+        Ok(refs)
+    }
+
+    /// Get the refs in `packed-refs`, reading the file again only if it
+    /// changed.
+    pub fn packed_refs(&self) -> Result<Rc<PackedRefs>> {
+        let path = self.pathname.join(PACKED_REFS);
+        let stamp = metadata(&path)
+            .ok()
+            .and_then(|stat| Some((stat.modified().ok()?, stat.len())));
+        if let Some((cached, packed)) = self.packed.borrow().as_ref() {
+            if Some(*cached) == stamp {
+                return Ok(packed.clone());
+            }
+        }
+
+        let packed = Rc::new(PackedRefs::load(&path)?);
+        *self.packed.borrow_mut() = stamp.map(|stamp| (stamp, packed.clone()));
+
+        Ok(packed)
+    }
+
+    /// Get the object the ref `name` peels to if `packed-refs` records it and
+    /// no loose ref overrides the packed one.
+    pub fn peeled(&self, name: &str) -> Result<Option<ObjectId>> {
+        let name = match self.find_ref(name) {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let packed = match self.packed_refs()?.get(&name) {
+            Some(packed) => *packed,
+            None => return Ok(None),
+        };
+
+        Ok(match self.read_ref_file(&name)? {
+            Some(RefValue::Oid(oid)) if oid == packed.oid => packed.peeled,
+            _ => None,
+        })
+    }
+
+    /// Move the loose refs into `packed-refs`, all of them or only tags.
+    /// Tags are peeled with the objects of `database`.
+    /// With `prune` the loose files are removed afterwards.
+    /// Returns the number of refs packed.
+    pub fn pack_refs(&self, database: &Database, all: bool, prune: bool) -> Result<usize> {
+        trace!("Packing refs");
+        let (mut lockfile, mut packed) = self.lock_packed_refs()?;
+
+        let mut loose = Vec::new();
+        self.list_loose(REFS, &mut loose)?;
+        let mut packed_names = Vec::new();
+        for name in loose {
+            if !all && !name.starts_with(&format!("{}/", REFS_TAGS)) {
+                continue;
+            }
+            let oid = match self.read_ref_file(&name)? {
+                Some(RefValue::Oid(oid)) => oid,
+                // symbolic refs cannot be packed
+                _ => continue,
+            };
+            let peeled = Self::peel_tag(database, oid);
+            packed.insert(&name, PackedRef { oid, peeled });
+            packed_names.push((name, oid));
+        }
+        // refs packed earlier without the trait might lack their peeled value
+        if !packed.is_fully_peeled() {
+            let unpeeled: Vec<(String, ObjectId)> = packed
+                .iter()
+                .map(|(name, packed)| (name.clone(), packed.oid))
+                .collect();
+            for (name, oid) in unpeeled {
+                let peeled = Self::peel_tag(database, oid);
+                packed.insert(&name, PackedRef { oid, peeled });
+            }
+            packed.set_fully_peeled(true);
         }
 
         lockfile
-            .write(oid)
-            .with_context(|| "Refs: lockfile write failed while updating HEAD")?;
+            .write(packed.serialize())
+            .with_context(|| "Refs: lockfile write failed for packed-refs")?;
         lockfile
-            .write("\n".to_string())
-            .with_context(|| "Refs: lockfile write failed while updating HEAD")?;
+            .commit()
+            .with_context(|| "Refs: Could not write packed-refs")?;
+        self.invalidate_packed();
+        debug!("Packed {} refs", packed_names.len());
+
+        if prune {
+            for (name, oid) in packed_names.iter() {
+                self.prune_loose(name, *oid)?;
+            }
+        }
+
+        Ok(packed_names.len())
+    }
+
+    /// Follow the tag `oid` to the object it finally points to, `None` if it
+    /// is not a tag or cannot be read.
+    fn peel_tag(database: &Database, oid: ObjectId) -> Option<ObjectId> {
+        let mut current = oid;
+        loop {
+            match database.read_header(&current) {
+                Ok((Types::Tag, _)) => (),
+                Ok(_) => break,
+                Err(err) => {
+                    debug!("Not peeling {}: {:#}", oid, err);
+                    return None;
+                }
+            }
+            current = match database.load(&current) {
+                Ok(ParsedObject::Tag(tag)) => tag.get_object(),
+                _ => return None,
+            };
+        }
+
+        (current != oid).then_some(current)
+    }
+
+    /// Lock `packed-refs` and read its current content.
+    fn lock_packed_refs(&self) -> Result<(Lockfile, PackedRefs)> {
+        let path = self.pathname.join(PACKED_REFS);
+        let lockfile = Lockfile::acquire(path.clone(), self.lock_timeout)
+            .with_context(|| "Refs: Could not lock packed-refs")?;
+        let packed = PackedRefs::load(&path)?;
+
+        Ok((lockfile, packed))
+    }
+
+    /// Replace `packed-refs` with `packed` while its lock is held, so it stays
+    /// locked after the write.
+    fn replace_packed_refs(&self, packed: &PackedRefs) -> Result<()> {
+        let path = self.pathname.join(PACKED_REFS);
+        let temp = self.pathname.join(format!("{}.new", PACKED_REFS));
+        let written = write(&temp, packed.serialize())
+            .and_then(|_| rename(&temp, &path))
+            .with_context(|| "Refs: Could not write packed-refs");
+        self.invalidate_packed();
+        if written.is_err() {
+            remove_file(&temp).ok();
+        }
+
+        written
+    }
+
+    /// Forget the cached `packed-refs` after writing it.
+    fn invalidate_packed(&self) {
+        self.packed.borrow_mut().take();
+    }
+
+    /// Remove the loose file of `name` if it still points to `oid`.
+    fn prune_loose(&self, name: &str, oid: ObjectId) -> Result<()> {
+        let path = self.pathname.join(name);
+        let lockfile = match Lockfile::acquire(path.clone(), Duration::ZERO) {
+            Ok(lockfile) => lockfile,
+            Err(err) => {
+                // somebody is updating the ref, it stays loose
+                debug!("Not pruning {}: {:#}", name, err);
+                return Ok(());
+            }
+        };
+
+        let unchanged = matches!(
+            self.read_ref_file(name),
+            Ok(Some(RefValue::Oid(current))) if current == oid
+        );
+        if unchanged {
+            match remove_file(&path) {
+                Err(err) if err.kind() != ErrorKind::NotFound => {
+                    return Err(err)
+                        .with_context(|| format!("Refs: Could not prune loose ref {}", name));
+                }
+                _ => (),
+            }
+        }
+        lockfile
+            .rollback()
+            .with_context(|| "Refs: Could not release lock")?;
+        self.prune_empty_dirs(name, &Self::namespace(name))
+    }
+
+    /// Read the reflog of the full ref `name`, oldest entry first.
+    pub fn reflog_entries(&self, name: &str) -> Result<Vec<ReflogEntry>> {
+        let path = self.pathname.join(LOGS).join(name);
+        let content = match read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => return Ok(Vec::new()),
+        };
+
+        content
+            .lines()
+            .map(|line| {
+                ReflogEntry::parse(line)
+                    .with_context(|| format!("Refs: Malformed reflog of {}", name))
+            })
+            .collect()
+    }
+
+    /// Does the full ref `name` have a reflog.
+    pub fn has_reflog(&self, name: &str) -> bool {
+        self.pathname.join(LOGS).join(name).is_file()
+    }
+
+    /// Append an entry for the update of the full ref `name` from `old` to
+    /// `new` to its reflog. Only HEAD and branches get a new reflog, other
+    /// refs are logged if they already have one.
+    pub fn append_reflog(
+        &self,
+        name: &str,
+        old: ObjectId,
+        new: ObjectId,
+        message: &str,
+    ) -> Result<()> {
+        if name != HEAD && !name.starts_with(REFS_HEADS) && !self.has_reflog(name) {
+            return Ok(());
+        }
+        trace!("Logging update of {}", name);
+
+        let path = self.pathname.join(LOGS).join(name);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).with_context(|| "Refs: Could not create log directory")?;
+        }
+        let entry = ReflogEntry::new(old, new, self.identity(), message);
+        // appends of a single line are atomic, no lock needed
+        File::options()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(format!("{}\n", entry).as_bytes()))
+            .with_context(|| format!("Refs: Could not append to reflog of {}", name))?;
+
+        Ok(())
+    }
+
+    /// Replace the reflog of the full ref `name` with `entries`.
+    pub fn write_reflog(&self, name: &str, entries: &[ReflogEntry]) -> Result<()> {
+        trace!("Rewriting reflog of {}", name);
+        let path = self.pathname.join(LOGS).join(name);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).with_context(|| "Refs: Could not create log directory")?;
+        }
+        let mut lockfile = Lockfile::acquire(path, self.lock_timeout)
+            .with_context(|| format!("Refs: Could not lock reflog of {}", name))?;
+
+        let content: String = entries.iter().map(|entry| format!("{}\n", entry)).collect();
+        lockfile
+            .write(content)
+            .with_context(|| format!("Refs: lockfile write failed for reflog of {}", name))?;
         lockfile
             .commit()
-            .with_context(|| "Refs: Could not write changes to HEAD")?;
+            .with_context(|| format!("Refs: Could not write reflog of {}", name))?;
 
         Ok(())
     }
 
-    /// Read the HEAD file if it exists and return the content.
-    pub fn read_head(&self) -> Result<Option<String>> {
-        trace!("Reading HEAD");
+    /// Delete the reflog of the full ref `name` if it has one.
+    fn delete_reflog(&self, name: &str) -> Result<()> {
+        let log = format!("{}/{}", LOGS, name);
+        if self.pathname.join(&log).is_file() {
+            remove_file(self.pathname.join(&log))
+                .with_context(|| format!("Refs: Could not delete reflog of {}", name))?;
+            self.prune_empty_dirs(&log, &format!("{}/{}", LOGS, Self::namespace(name)))?;
+        }
+
+        Ok(())
+    }
+
+    /// The identity recorded in reflog entries and tags, taken from the
+    /// environment or the config.
+    pub fn identity(&self) -> Author {
+        let config = Config::load(&self.pathname.join(CONFIG)).ok();
+        let lookup = |vars: [&str; 2], key: &str| {
+            vars.iter()
+                .find_map(|name| var(name).ok())
+                .or_else(|| config.as_ref()?.get(key).map(|value| value.to_string()))
+        };
+        let name = lookup([GIT_COMMITTER_NAME, GIT_AUTHOR_NAME], "user.name")
+            .unwrap_or_else(|| String::from("unknown"));
+        let email =
+            lookup([GIT_COMMITTER_EMAIL, GIT_AUTHOR_EMAIL], "user.email").unwrap_or_default();
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+
+        Author::new(name, email, time.to_string())
+    }
+
+    /// List the full names of all refs that have a reflog, sorted.
+    pub fn list_reflogs(&self) -> Result<Vec<String>> {
+        trace!("Listing reflogs");
+        let mut logs = Vec::new();
+        if self.pathname.join(LOGS).join(HEAD).is_file() {
+            logs.push(format!("{}/{}", LOGS, HEAD));
+        }
+        self.list_loose(&format!("{}/{}", LOGS, REFS), &mut logs)?;
+        logs.sort();
+
+        Ok(logs
+            .iter()
+            .filter_map(|log| log.strip_prefix(&format!("{}/", LOGS)))
+            .map(|name| name.to_string())
+            .collect())
+    }
+
+    /// Get the path to `.git`.
+    pub fn get_path(&self) -> &PathBuf {
+        &self.pathname
+    }
+
+    /// Expand `name` into the full name of an existing ref, e.g. `master` into
+    /// `refs/heads/master`.
+    pub fn full_name(&self, name: &str) -> Option<String> {
+        self.find_ref(name)
+    }
+
+    /// Get the full ref name of the branch `name`.
+    pub fn branch_ref(name: &str) -> String {
+        format!("{}/{}", REFS_HEADS, name)
+    }
+
+    /// Get the full ref name of the tag `name`.
+    pub fn tag_ref(name: &str) -> String {
+        format!("{}/{}", REFS_TAGS, name)
+    }
+
+    /// Turn a full ref name into its short form, e.g. `refs/heads/master` into
+    /// `master`.
+    pub fn short_name(name: &str) -> String {
+        for prefix in ["refs/heads/", "refs/"] {
+            if let Some(short) = name.strip_prefix(prefix) {
+                return short.to_string();
+            }
+        }
+        name.to_string()
+    }
+
+    /// The directory grouping the full ref `name`, e.g. `refs/heads`, which is
+    /// kept when the ref is deleted.
+    fn namespace(name: &str) -> String {
+        name.splitn(3, '/').take(2).collect::<Vec<&str>>().join("/")
+    }
+
+    /// Follow the symbolic refs starting at `name` to the name of the ref
+    /// that holds an oid or does not exist yet.
+    fn resolve_name(&self, name: &str) -> Result<String> {
+        let mut name = name.to_string();
+        for _ in 0..=MAX_SYMREF_DEPTH {
+            match self.read_ref_file(&name)? {
+                Some(RefValue::Symbolic(target)) => name = target,
+                _ => return Ok(name),
+            }
+        }
+        bail!(
+            "Refs: Symbolic ref {} nests too deep, is there a loop?",
+            name
+        )
+    }
+
+    /// Collect the names of all loose and packed refs below `dir`.
+    fn list_refs(&self, dir: &str, refs: &mut Vec<String>) -> Result<()> {
+        let mut loose = Vec::new();
+        self.list_loose(dir, &mut loose)?;
+        let prefix = format!("{}/", dir);
+        let mut names: BTreeSet<String> = loose.into_iter().collect();
+        names.extend(
+            self.packed_refs()?
+                .iter()
+                .map(|(name, _)| name)
+                .filter(|name| name.starts_with(&prefix))
+                .cloned(),
+        );
+        refs.extend(names);
+
+        Ok(())
+    }
+
+    /// Recursively collect the names of all loose refs below `dir`.
+    fn list_loose(&self, dir: &str, refs: &mut Vec<String>) -> Result<()> {
+        let path = self.pathname.join(dir);
+        if !path.is_dir() {
+            return Ok(());
+        }
+
+        for entry in read_dir(&path).with_context(|| format!("Refs: Could not list {}", dir))? {
+            let entry = entry.with_context(|| format!("Refs: Could not list {}", dir))?;
+            let name = format!("{}/{}", dir, entry.file_name().to_string_lossy());
+            if entry.path().is_dir() {
+                self.list_loose(&name, refs)?;
+            } else if !name.ends_with(".lock") {
+                refs.push(name);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the now empty parent directories of `name` up to `stop`.
+    fn prune_empty_dirs(&self, name: &str, stop: &str) -> Result<()> {
+        let stop = self.pathname.join(stop);
+        let path = self.pathname.join(name);
+        for dir in path.ancestors().skip(1) {
+            if dir == stop || !dir.starts_with(&stop) {
+                break;
+            }
+            // stop at the first directory that still has content
+            if remove_dir(dir).is_err() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find the full name of the ref `name` refers to.
+    fn find_ref(&self, name: &str) -> Option<String> {
+        [
+            name.to_string(),
+            format!("refs/{}", name),
+            format!("{}/{}", REFS_TAGS, name),
+            format!("{}/{}", REFS_HEADS, name),
+        ]
+        .into_iter()
+        .find(|candidate| self.ref_exists(candidate))
+    }
+
+    /// Follow the ref `name` until it points to an oid.
+    fn resolve(&self, name: &str, depth: usize) -> Result<Option<ObjectId>> {
+        if depth > MAX_SYMREF_DEPTH {
+            bail!(
+                "Refs: Symbolic ref {} nests too deep, is there a loop?",
+                name
+            );
+        }
 
-        // does file even exist
-        if !self.head_path().exists() {
-            debug!("HEAD does not exists (commit maybe parentless)");
-            return Ok(None);
+        match self.read_ref_file(name)? {
+            Some(RefValue::Symbolic(target)) => self.resolve(&target, depth + 1),
+            Some(RefValue::Oid(oid)) => Ok(Some(oid)),
+            None => Ok(None),
+        }
+    }
+
+    /// Does the loose or packed ref `name` exist.
+    fn ref_exists(&self, name: &str) -> bool {
+        self.pathname.join(name).is_file()
+            || self.packed_refs().is_ok_and(|packed| packed.contains(name))
+    }
+
+    /// Read the content of the ref file `name`, falling back to
+    /// `packed-refs`. `None` if it does not exist.
+    fn read_ref_file(&self, name: &str) -> Result<Option<RefValue>> {
+        let path = self.pathname.join(name);
+        if !path.is_file() {
+            return Ok(self
+                .packed_refs()?
+                .get(name)
+                .map(|packed| RefValue::Oid(packed.oid)));
         }
-        debug!("HEAD already exists, reading it now");
 
         // read it
         let mut buffer = Vec::new();
         let mut f = File::options()
             .read(true)
             .write(false)
-            .open(self.head_path())
-            .with_context(|| "Refs: Could not open HEAD")?;
+            .open(&path)
+            .with_context(|| format!("Refs: Could not open {}", name))?;
         f.read_to_end(&mut buffer)
-            .with_context(|| "Refs: Unable to read HEAD")?;
+            .with_context(|| format!("Refs: Unable to read {}", name))?;
         let s =
             String::from_utf8(buffer).with_context(|| "Refs: Encoding bytes into utf-8 failed")?;
-        debug!("HEAD contains: {s}");
+        let s = s.trim();
+        debug!("{} contains: {}", name, s);
+
+        match s.strip_prefix(SYMREF_PREFIX) {
+            Some(target) => Ok(Some(RefValue::Symbolic(target.trim().to_string()))),
+            None => Ok(Some(RefValue::Oid(s.parse().with_context(|| {
+                format!("Refs: {} does not contain a valid object id", name)
+            })?))),
+        }
+    }
+
+    /// Write `value` to the ref file `name` under a lock.
+    fn write_ref(&self, name: &str, value: &RefValue) -> Result<()> {
+        let path = self.pathname.join(name);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).with_context(|| "Refs: Could not create ref directory")?;
+        }
 
-        Ok(Some(s))
+        let mut lockfile = Lockfile::acquire(path, self.lock_timeout)
+            .with_context(|| format!("Refs: Could not lock {}", name))?;
+
+        let content = match value {
+            RefValue::Symbolic(target) => format!("{}{}", SYMREF_PREFIX, target),
+            RefValue::Oid(oid) => oid.to_hex(),
+        };
+        lockfile
+            .write(content)
+            .with_context(|| format!("Refs: lockfile write failed while updating {}", name))?;
+        lockfile
+            .write("\n".to_string())
+            .with_context(|| format!("Refs: lockfile write failed while updating {}", name))?;
+        lockfile
+            .commit()
+            .with_context(|| format!("Refs: Could not write changes to {}", name))?;
+
+        Ok(())
+    }
+}
+
+/// Check `name` against the rules of `git check-ref-format`.
+pub fn is_valid_ref_name(name: &str) -> bool {
+    if name.is_empty()
+        || name == "@"
+        || name.starts_with('/')
+        || name.starts_with('-')
+        || name.ends_with('/')
+        || name.ends_with('.')
+        || name.contains("..")
+        || name.contains("//")
+        || name.contains("@{")
+    {
+        return false;
     }
 
-    /// Get the path to HEAD.
-    fn head_path(&self) -> PathBuf {
-        self.pathname.join("HEAD")
+    let bad_char = |c: char| {
+        c.is_ascii_control() || matches!(c, ' ' | '~' | '^' | ':' | '?' | '*' | '[' | '\\')
+    };
+    if name.chars().any(bad_char) {
+        return false;
     }
+
+    name.split('/')
+        .all(|component| !component.starts_with('.') && !component.ends_with(".lock"))
 }
+
+pub mod transaction;
+pub use transaction::RefTransaction;
+pub mod packed;
+pub use packed::{PackedRef, PackedRefs, PACKED_REFS};
@@ -0,0 +1,213 @@
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::path::Path;
+
+use crate::ObjectId;
+
+/// Name of the file below `.git` holding packed refs.
+pub const PACKED_REFS: &str = "packed-refs";
+/// Start of the header of `packed-refs`, the traits of the file follow.
+const PACKED_HEADER: &str = "# pack-refs with:";
+/// Trait of files recording the peeled value of every tag.
+const FULLY_PEELED: &str = "fully-peeled";
+/// Prefix of the line holding the peeled value of the ref above it.
+const PEELED_PREFIX: char = '^';
+
+/// A ref stored in `packed-refs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PackedRef {
+    /// The oid the ref points to.
+    pub oid: ObjectId,
+
+    /// The object `oid` peels to if it is a tag.
+    pub peeled: Option<ObjectId>,
+}
+
+/// The content of `packed-refs`, sorted by ref name.
+#[derive(Clone, Debug, Default)]
+pub struct PackedRefs {
+    refs: BTreeMap<String, PackedRef>,
+
+    /// Does every ref to a tag carry its peeled value, so refs without one
+    /// are known not to be tags.
+    fully_peeled: bool,
+}
+
+impl PackedRefs {
+    /// Load `packed-refs` from `path`, a missing file holds no refs.
+    pub fn load(path: &Path) -> Result<Self> {
+        trace!("Loading packed refs");
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let content =
+            read_to_string(path).with_context(|| format!("Refs: Could not read {:?}", path))?;
+
+        Self::parse(&content).with_context(|| format!("Refs: Malformed {:?}", path))
+    }
+
+    /// Parse lines of `oid name`, each optionally followed by `^peeled`.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut refs: BTreeMap<String, PackedRef> = BTreeMap::new();
+        let mut last: Option<String> = None;
+        let mut fully_peeled = false;
+        for (number, line) in content.lines().enumerate() {
+            if let Some(traits) = line.strip_prefix(PACKED_HEADER) {
+                fully_peeled = traits.split_whitespace().any(|t| t == FULLY_PEELED);
+                continue;
+            }
+            if line.starts_with('#') || line.is_empty() {
+                continue;
+            }
+
+            if let Some(peeled) = line.strip_prefix(PEELED_PREFIX) {
+                let packed = last
+                    .take()
+                    .and_then(|name| refs.get_mut(&name))
+                    .with_context(|| format!("Refs: Peeled line {} without ref", number + 1))?;
+                packed.peeled = Some(
+                    peeled
+                        .parse()
+                        .with_context(|| format!("Refs: Invalid oid in line {}", number + 1))?,
+                );
+                continue;
+            }
+
+            let (oid, name) = match line.split_once(' ') {
+                Some(fields) => fields,
+                None => bail!("Refs: Malformed line {}", number + 1),
+            };
+            let oid = oid
+                .parse()
+                .with_context(|| format!("Refs: Invalid oid in line {}", number + 1))?;
+            refs.insert(name.to_string(), PackedRef { oid, peeled: None });
+            last = Some(name.to_string());
+        }
+        debug!("Loaded {} packed refs", refs.len());
+
+        Ok(Self { refs, fully_peeled })
+    }
+
+    /// Get the packed ref `name`.
+    pub fn get(&self, name: &str) -> Option<&PackedRef> {
+        self.refs.get(name)
+    }
+
+    /// Does `name` exist.
+    pub fn contains(&self, name: &str) -> bool {
+        self.refs.contains_key(name)
+    }
+
+    /// Add or replace the packed ref `name`.
+    pub fn insert(&mut self, name: &str, packed: PackedRef) {
+        self.refs.insert(name.to_string(), packed);
+    }
+
+    /// Remove the packed ref `name`.
+    pub fn remove(&mut self, name: &str) -> Option<PackedRef> {
+        self.refs.remove(name)
+    }
+
+    /// Iterate over the names and refs, sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &PackedRef)> {
+        self.refs.iter()
+    }
+
+    /// Is the peeled value of every tag recorded.
+    pub fn is_fully_peeled(&self) -> bool {
+        self.fully_peeled
+    }
+
+    /// Mark that the peeled value of every tag is recorded.
+    pub fn set_fully_peeled(&mut self, fully_peeled: bool) {
+        self.fully_peeled = fully_peeled;
+    }
+
+    /// Number of packed refs.
+    pub fn len(&self) -> usize {
+        self.refs.len()
+    }
+
+    /// Are there no packed refs.
+    pub fn is_empty(&self) -> bool {
+        self.refs.is_empty()
+    }
+
+    /// Serialize into the format of `packed-refs`.
+    pub fn serialize(&self) -> String {
+        let traits = if self.fully_peeled {
+            format!("peeled {} sorted", FULLY_PEELED)
+        } else {
+            "sorted".to_string()
+        };
+        let mut content = format!("{} {} \n", PACKED_HEADER, traits);
+        for (name, packed) in self.refs.iter() {
+            content.push_str(&format!("{} {}\n", packed.oid, name));
+            if let Some(peeled) = packed.peeled {
+                content.push_str(&format!("{}{}\n", PEELED_PREFIX, peeled));
+            }
+        }
+
+        content
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Database, HashAlgorithm, Types};
+
+    fn oid(data: &str) -> ObjectId {
+        Database::hash_data(HashAlgorithm::Sha1, Types::Blob, data.as_bytes())
+    }
+
+    #[test]
+    fn round_trip_with_traits() {
+        let mut packed = PackedRefs::default();
+        packed.insert(
+            "refs/heads/main",
+            PackedRef {
+                oid: oid("a"),
+                peeled: None,
+            },
+        );
+        packed.insert(
+            "refs/tags/v1",
+            PackedRef {
+                oid: oid("t"),
+                peeled: Some(oid("a")),
+            },
+        );
+        assert!(packed
+            .serialize()
+            .starts_with("# pack-refs with: sorted \n"));
+
+        packed.set_fully_peeled(true);
+        let content = packed.serialize();
+        assert_eq!(
+            content,
+            format!(
+                "# pack-refs with: peeled fully-peeled sorted \n\
+                 {} refs/heads/main\n{} refs/tags/v1\n^{}\n",
+                oid("a"),
+                oid("t"),
+                oid("a")
+            )
+        );
+
+        let parsed = PackedRefs::parse(&content).unwrap();
+        assert!(parsed.is_fully_peeled());
+        assert_eq!(parsed.get("refs/tags/v1").unwrap().peeled, Some(oid("a")));
+        assert_eq!(parsed.get("refs/heads/main").unwrap().peeled, None);
+        assert!(!PackedRefs::parse("# pack-refs with: peeled sorted \n")
+            .unwrap()
+            .is_fully_peeled());
+    }
+
+    #[test]
+    fn peeled_line_without_ref_is_rejected() {
+        assert!(PackedRefs::parse(&format!("^{}\n", oid("a"))).is_err());
+    }
+}
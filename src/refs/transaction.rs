@@ -0,0 +1,718 @@
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::fs::{create_dir_all, remove_file};
+use std::io::ErrorKind;
+
+use super::{
+    is_valid_ref_name, HeadState, PackedRefs, RefValue, ReflogEntry, Refs, HEAD, REFS,
+    SYMREF_PREFIX,
+};
+use crate::{Lockfile, ObjectId};
+
+/// What a `RefTransaction` does to a single ref.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RefChange {
+    /// Point the ref to an oid.
+    Update(ObjectId),
+
+    /// Point the ref itself, not the ref it follows, to another ref.
+    Symbolic(String),
+
+    /// Remove the ref and its reflog.
+    Delete,
+
+    /// Only check the old value.
+    Verify,
+}
+
+/// A change queued in a `RefTransaction`.
+#[derive(Debug)]
+struct RefUpdate {
+    /// Full name of the ref as given, symbolic refs are followed on commit.
+    name: String,
+
+    /// The change to make.
+    change: RefChange,
+
+    /// Value the ref must have, the null oid if it must not exist.
+    old: Option<ObjectId>,
+
+    /// Ref a symbolic ref must point to.
+    old_target: Option<String>,
+
+    /// Ref whose reflog the updated ref starts out with.
+    reflog_from: Option<String>,
+
+    /// Reason recorded in the reflog.
+    message: String,
+}
+
+/// A `RefTransaction` updates several refs at once. On commit every ref is
+/// locked and its old value checked before anything is changed, so either
+/// all updates are applied or none.
+pub struct RefTransaction<'a> {
+    /// The refs to change.
+    refs: &'a Refs,
+
+    /// Queued changes.
+    updates: Vec<RefUpdate>,
+}
+
+impl<'a> RefTransaction<'a> {
+    /// Start an empty transaction on `refs`.
+    pub fn new(refs: &'a Refs) -> Self {
+        trace!("Starting ref transaction");
+        Self {
+            refs,
+            updates: Vec::new(),
+        }
+    }
+
+    /// Queue pointing the full ref `name` to `new`. If `old` is given the ref
+    /// must currently point to it, the null oid means it must not exist.
+    pub fn update(
+        &mut self,
+        name: &str,
+        new: ObjectId,
+        old: Option<ObjectId>,
+        message: &str,
+    ) -> Result<()> {
+        if new.is_null() {
+            bail!("Refs: Cannot update {} to the null oid", name);
+        }
+        self.push(name, RefChange::Update(new), old, message)
+    }
+
+    /// Queue creating the full ref `name` at `new`, it must not exist.
+    pub fn create(&mut self, name: &str, new: ObjectId, message: &str) -> Result<()> {
+        self.update(
+            name,
+            new,
+            Some(ObjectId::null(new.get_algorithm())),
+            message,
+        )
+    }
+
+    /// Queue pointing the symbolic ref `name`, e.g. HEAD, to the full ref
+    /// `target`. If `old` is given `name` must currently point to it.
+    /// The reflog of `name` records the oid `target` has after the commit.
+    pub fn update_symbolic(
+        &mut self,
+        name: &str,
+        target: &str,
+        old: Option<&str>,
+        message: &str,
+    ) -> Result<()> {
+        if !(target.starts_with(&format!("{}/", REFS)) && is_valid_ref_name(target)) {
+            bail!("Refs: '{}' is not a valid ref name", target);
+        }
+        self.push(name, RefChange::Symbolic(target.to_string()), None, message)?;
+        if let Some(update) = self.updates.last_mut() {
+            update.old_target = old.map(|old| old.to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Let the ref `name`, which must be queued for an update, start out with
+    /// a copy of the reflog of `source`, which must be queued as well. The
+    /// reflog is read and written while both refs are locked.
+    pub fn copy_reflog(&mut self, name: &str, source: &str) -> Result<()> {
+        if !self.updates.iter().any(|update| update.name == source) {
+            bail!("Refs: Cannot copy reflog of {} which is not locked", source);
+        }
+        let update = self
+            .updates
+            .iter_mut()
+            .find(|update| update.name == name && matches!(update.change, RefChange::Update(_)))
+            .with_context(|| format!("Refs: No update of {} to copy a reflog to", name))?;
+        update.reflog_from = Some(source.to_string());
+
+        Ok(())
+    }
+
+    /// Queue deleting the full ref `name`, which must point to `old` if given.
+    pub fn delete(&mut self, name: &str, old: Option<ObjectId>, message: &str) -> Result<()> {
+        if old.is_some_and(|old| old.is_null()) {
+            bail!("Refs: Cannot delete {} expecting it not to exist", name);
+        }
+        self.push(name, RefChange::Delete, old, message)
+    }
+
+    /// Queue checking that the full ref `name` points to `old`, or does not
+    /// exist for the null oid.
+    pub fn verify(&mut self, name: &str, old: ObjectId) -> Result<()> {
+        self.push(name, RefChange::Verify, Some(old), "")
+    }
+
+    /// Is the transaction empty.
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// Lock all refs, check their old values and apply every change.
+    /// Nothing is changed if a ref is locked elsewhere or has moved.
+    pub fn commit(self) -> Result<()> {
+        trace!(
+            "Committing ref transaction of {} updates",
+            self.updates.len()
+        );
+        let mut updates = Vec::new();
+        let refs = self.refs;
+        for update in self.updates {
+            let target = match update.change {
+                RefChange::Symbolic(_) => update.name.clone(),
+                _ => refs.resolve_name(&update.name)?,
+            };
+            updates.push((target, update));
+        }
+        // lock in a fixed order so concurrent transactions cannot deadlock
+        updates.sort_by(|(a, _), (b, _)| a.cmp(b));
+        if let Some(pair) = updates.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+            bail!("Refs: Multiple updates for ref '{}' not allowed", pair[0].0);
+        }
+
+        // deletes lock packed-refs before any ref, like pack-refs does, and
+        // hold it until the loose files are gone, so the refs cannot be
+        // packed again in between
+        let deletes = updates
+            .iter()
+            .any(|(_, update)| update.change == RefChange::Delete);
+        let mut packed = if deletes {
+            Some(refs.lock_packed_refs()?)
+        } else {
+            None
+        };
+
+        // locks still held on an error are released when dropped
+        let locked = match Self::prepare(refs, &updates) {
+            Ok(locked) => locked,
+            Err(err) => {
+                // the failure to lock is what matters, not the cleanup
+                if let Err(cleanup) = Self::prune_empty_dirs(refs, &updates) {
+                    warn!("Could not remove empty ref directories: {:#}", cleanup);
+                }
+                return Err(err);
+            }
+        };
+
+        // copied reflogs are read once their source is locked
+        let reflogs = updates
+            .iter()
+            .map(|(_, update)| {
+                update
+                    .reflog_from
+                    .as_ref()
+                    .map(|source| refs.reflog_entries(source))
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // deleted refs leave packed-refs first, so their packed value cannot
+        // show through once the loose file is gone
+        if let Some((_, packed)) = packed.as_mut() {
+            if Self::remove_packed(&updates, packed) {
+                refs.replace_packed_refs(packed)?;
+            }
+        }
+
+        // every ref is locked and checked, apply the changes, symbolic refs
+        // last so they never point to a ref that is not written yet
+        let head = refs.head_state().ok();
+        let (symbolic, direct): (Vec<_>, Vec<_>) = updates
+            .iter()
+            .zip(locked)
+            .zip(reflogs)
+            .partition(|(((_, update), _), _)| matches!(update.change, RefChange::Symbolic(_)));
+        for (((target, update), (lock, old)), reflog) in direct.into_iter().chain(symbolic) {
+            Self::apply(refs, target, update, lock, old, reflog, head.as_ref())?;
+        }
+        if let Some((lock, _)) = packed {
+            lock.rollback()
+                .with_context(|| "Refs: Could not release packed-refs")?;
+        }
+
+        Ok(())
+    }
+
+    /// Lock every ref, check its old value and write the new one to its
+    /// lock. Returns the locks with the current values.
+    fn prepare(
+        refs: &Refs,
+        updates: &[(String, RefUpdate)],
+    ) -> Result<Vec<(Lockfile, Option<ObjectId>)>> {
+        let mut locked = Vec::new();
+        for (target, update) in updates.iter() {
+            // packed refs have no directory, but their lock still needs one
+            let path = refs.pathname.join(target);
+            if let Some(parent) = path.parent() {
+                create_dir_all(parent).with_context(|| "Refs: Could not create ref directory")?;
+            }
+
+            let mut lockfile = Lockfile::acquire(path, refs.lock_timeout)
+                .with_context(|| format!("Refs: Cannot lock ref '{}'", target))?;
+
+            if let RefChange::Symbolic(new) = &update.change {
+                if let Some(old) = &update.old_target {
+                    if refs.read_ref_file(target)? != Some(RefValue::Symbolic(old.clone())) {
+                        bail!(
+                            "Refs: Cannot lock ref '{}': it no longer points to {}",
+                            target,
+                            old
+                        );
+                    }
+                }
+                lockfile
+                    .write(format!("{}{}\n", SYMREF_PREFIX, new))
+                    .with_context(|| format!("Refs: lockfile write failed for {}", target))?;
+                locked.push((lockfile, None));
+                continue;
+            }
+
+            let value = match refs.read_ref_file(target)? {
+                Some(RefValue::Oid(oid)) => Some(oid),
+                Some(RefValue::Symbolic(_)) => {
+                    bail!(
+                        "Refs: Cannot lock ref '{}': it became a symbolic ref",
+                        target
+                    )
+                }
+                None => None,
+            };
+            match (update.old, value) {
+                (None, _) => (),
+                (Some(old), None) if old.is_null() => (),
+                (Some(old), None) => {
+                    bail!(
+                        "Refs: Cannot lock ref '{}': expected {} but it is missing",
+                        target,
+                        old
+                    )
+                }
+                (Some(old), Some(value)) if old.is_null() => {
+                    bail!(
+                        "Refs: Cannot lock ref '{}': reference already exists at {}",
+                        target,
+                        value
+                    )
+                }
+                (Some(old), Some(value)) if old != value => {
+                    bail!(
+                        "Refs: Cannot lock ref '{}': is at {} but expected {}",
+                        target,
+                        value,
+                        old
+                    )
+                }
+                _ => (),
+            }
+            if update.change == RefChange::Delete && value.is_none() {
+                bail!("Refs: Cannot delete ref '{}', it does not exist", target);
+            }
+
+            if let RefChange::Update(new) = update.change {
+                lockfile
+                    .write(format!("{}\n", new.to_hex()))
+                    .with_context(|| format!("Refs: lockfile write failed for {}", target))?;
+            }
+            locked.push((lockfile, value));
+        }
+
+        Ok(locked)
+    }
+
+    /// Remove the deleted refs from the locked `packed` refs, returns if any
+    /// of them was packed.
+    fn remove_packed(updates: &[(String, RefUpdate)], packed: &mut PackedRefs) -> bool {
+        let mut removed = false;
+        for (target, update) in updates.iter() {
+            if update.change == RefChange::Delete {
+                removed |= packed.remove(target).is_some();
+            }
+        }
+
+        removed
+    }
+
+    /// Apply a prepared change to `target` and release its lock.
+    fn apply(
+        refs: &Refs,
+        target: &str,
+        update: &RefUpdate,
+        lock: Lockfile,
+        old: Option<ObjectId>,
+        reflog: Option<Vec<ReflogEntry>>,
+        head: Option<&HeadState>,
+    ) -> Result<()> {
+        match update.change {
+            RefChange::Update(new) => {
+                // the reflog is written while the ref is still locked
+                let old = match reflog {
+                    // a copied history continues at the same oid
+                    Some(mut entries) => {
+                        entries.push(ReflogEntry::new(new, new, refs.identity(), &update.message));
+                        refs.write_reflog(target, &entries)?;
+                        old.unwrap_or_else(|| ObjectId::null(new.get_algorithm()))
+                    }
+                    // a missing ref is logged as created
+                    None => {
+                        let old = old.unwrap_or_else(|| ObjectId::null(new.get_algorithm()));
+                        refs.append_reflog(target, old, new, &update.message)?;
+                        old
+                    }
+                };
+                // HEAD also logs updates of the branch it points to
+                if target != HEAD && head == Some(&HeadState::Branch(target.to_string())) {
+                    refs.append_reflog(HEAD, old, new, &update.message)?;
+                }
+
+                lock.commit()
+                    .with_context(|| format!("Refs: Could not write changes to {}", target))?;
+                debug!("Updated {} to {}", target, new);
+            }
+            RefChange::Delete => {
+                // the ref might only have been packed
+                match remove_file(refs.pathname.join(target)) {
+                    Err(err) if err.kind() != ErrorKind::NotFound => {
+                        return Err(err)
+                            .with_context(|| format!("Refs: Could not delete {}", target));
+                    }
+                    _ => (),
+                }
+                lock.rollback()
+                    .with_context(|| "Refs: Could not release lock")?;
+                debug!("Deleted {}", target);
+
+                if target != HEAD {
+                    refs.prune_empty_dirs(target, &Refs::namespace(target))?;
+                }
+                refs.delete_reflog(target)?;
+            }
+            RefChange::Symbolic(ref new) => {
+                lock.commit()
+                    .with_context(|| format!("Refs: Could not write changes to {}", target))?;
+                debug!("Pointed {} to {}", target, new);
+
+                if let Some(oid) = refs.read_ref(new)? {
+                    refs.append_reflog(target, oid, oid, &update.message)?;
+                }
+            }
+            RefChange::Verify => {
+                lock.rollback()
+                    .with_context(|| "Refs: Could not release lock")?;
+                if target != HEAD {
+                    refs.prune_empty_dirs(target, &Refs::namespace(target))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the directories `prepare` created for locks that are now
+    /// empty again.
+    fn prune_empty_dirs(refs: &Refs, updates: &[(String, RefUpdate)]) -> Result<()> {
+        for (target, _) in updates.iter().filter(|(target, _)| target != HEAD) {
+            refs.prune_empty_dirs(target, &Refs::namespace(target))?;
+        }
+
+        Ok(())
+    }
+
+    /// Queue a change after validating the ref name.
+    fn push(
+        &mut self,
+        name: &str,
+        change: RefChange,
+        old: Option<ObjectId>,
+        message: &str,
+    ) -> Result<()> {
+        if name != HEAD && !(name.starts_with(&format!("{}/", REFS)) && is_valid_ref_name(name)) {
+            bail!("Refs: '{}' is not a valid ref name", name);
+        }
+        debug!("Queueing {:?} of {}", change, name);
+        self.updates.push(RefUpdate {
+            name: name.to_string(),
+            change,
+            old,
+            old_target: None,
+            reflog_from: None,
+            message: message.to_string(),
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Database, HashAlgorithm, TestDir, Types, PACKED_REFS};
+
+    fn oid(data: &str) -> ObjectId {
+        Database::hash_data(HashAlgorithm::Sha1, Types::Blob, data.as_bytes())
+    }
+
+    /// No `.lock` file is left anywhere below `path`.
+    fn no_locks(path: &std::path::Path) -> bool {
+        std::fs::read_dir(path).unwrap().all(|entry| {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                no_locks(&path)
+            } else {
+                path.extension().is_none_or(|ext| ext != "lock")
+            }
+        })
+    }
+
+    /// An empty object database, none of the oids above exist in it.
+    fn database(dir: &TestDir) -> Database {
+        std::fs::create_dir_all(dir.path().join("objects")).unwrap();
+        Database::new(&dir.path().join("objects")).unwrap()
+    }
+
+    fn setup() -> (TestDir, Refs) {
+        let dir = TestDir::new();
+        let refs = Refs::new(dir.path().to_path_buf()).unwrap();
+        let mut transaction = RefTransaction::new(&refs);
+        transaction.create("refs/heads/a", oid("a"), "").unwrap();
+        transaction.create("refs/heads/b", oid("b"), "").unwrap();
+        transaction.commit().unwrap();
+
+        (dir, refs)
+    }
+
+    #[test]
+    fn updates_with_old_values() {
+        let (dir, refs) = setup();
+        let mut transaction = RefTransaction::new(&refs);
+        transaction
+            .update("refs/heads/a", oid("c"), Some(oid("a")), "")
+            .unwrap();
+        transaction
+            .delete("refs/heads/b", Some(oid("b")), "")
+            .unwrap();
+        transaction.commit().unwrap();
+
+        assert_eq!(refs.read_ref("refs/heads/a").unwrap(), Some(oid("c")));
+        assert_eq!(refs.read_ref("refs/heads/b").unwrap(), None);
+        assert!(no_locks(dir.path()));
+    }
+
+    #[test]
+    fn stale_old_value_is_rejected() {
+        let (dir, refs) = setup();
+        let mut transaction = RefTransaction::new(&refs);
+        transaction
+            .update("refs/heads/a", oid("c"), Some(oid("b")), "")
+            .unwrap();
+        assert!(transaction.commit().is_err());
+
+        assert_eq!(refs.read_ref("refs/heads/a").unwrap(), Some(oid("a")));
+        assert!(no_locks(dir.path()));
+    }
+
+    #[test]
+    fn create_of_existing_ref_is_rejected() {
+        let (dir, refs) = setup();
+        let mut transaction = RefTransaction::new(&refs);
+        transaction.create("refs/heads/a", oid("c"), "").unwrap();
+        assert!(transaction.commit().is_err());
+
+        assert_eq!(refs.read_ref("refs/heads/a").unwrap(), Some(oid("a")));
+        assert!(no_locks(dir.path()));
+    }
+
+    #[test]
+    fn delete_of_missing_ref_is_rejected() {
+        let (dir, refs) = setup();
+        for old in [None, Some(oid("a"))] {
+            let mut transaction = RefTransaction::new(&refs);
+            transaction.delete("refs/heads/missing", old, "").unwrap();
+            assert!(transaction.commit().is_err());
+        }
+        assert!(!dir.path().join("refs/heads/missing").exists());
+        assert!(no_locks(dir.path()));
+    }
+
+    #[test]
+    fn failed_update_rolls_back_all_locks() {
+        let (dir, refs) = setup();
+        // the refs are locked in sorted order, so the stale `refs/heads/b`
+        // fails after the others are locked
+        let mut transaction = RefTransaction::new(&refs);
+        transaction
+            .update("refs/heads/a", oid("c"), Some(oid("a")), "")
+            .unwrap();
+        transaction.create("refs/heads/a2", oid("c"), "").unwrap();
+        transaction
+            .delete("refs/heads/b", Some(oid("a")), "")
+            .unwrap();
+        transaction.verify("refs/heads/z", oid("z")).unwrap();
+        assert!(transaction.commit().is_err());
+
+        assert_eq!(refs.read_ref("refs/heads/a").unwrap(), Some(oid("a")));
+        assert_eq!(refs.read_ref("refs/heads/a2").unwrap(), None);
+        assert_eq!(refs.read_ref("refs/heads/b").unwrap(), Some(oid("b")));
+        assert!(no_locks(dir.path()));
+    }
+
+    #[test]
+    fn deletes_packed_ref_without_directory() {
+        let (dir, refs) = setup();
+        let mut transaction = RefTransaction::new(&refs);
+        transaction.create("refs/heads/x/y", oid("a"), "").unwrap();
+        transaction.commit().unwrap();
+        refs.pack_refs(&database(&dir), true, true).unwrap();
+        assert!(!dir.path().join("refs/heads/x").exists());
+
+        let mut transaction = RefTransaction::new(&refs);
+        transaction.verify("refs/heads/x/y", oid("a")).unwrap();
+        transaction.commit().unwrap();
+        assert!(!dir.path().join("refs/heads/x").exists());
+
+        let mut transaction = RefTransaction::new(&refs);
+        transaction
+            .delete("refs/heads/x/y", Some(oid("a")), "")
+            .unwrap();
+        transaction.commit().unwrap();
+
+        assert_eq!(refs.read_ref("refs/heads/x/y").unwrap(), None);
+        assert!(!dir.path().join("refs/heads/x").exists());
+        assert!(dir.path().join("refs/heads").exists());
+        assert!(no_locks(dir.path()));
+    }
+
+    #[test]
+    fn deletes_lock_packed_refs() {
+        let (dir, refs) = setup();
+        let lock =
+            Lockfile::acquire(dir.path().join(PACKED_REFS), std::time::Duration::ZERO).unwrap();
+
+        let mut transaction = RefTransaction::new(&refs);
+        transaction.delete("refs/heads/a", None, "").unwrap();
+        assert!(transaction.commit().is_err());
+        assert_eq!(refs.read_ref("refs/heads/a").unwrap(), Some(oid("a")));
+
+        // updates do not touch packed-refs
+        let mut transaction = RefTransaction::new(&refs);
+        transaction
+            .update("refs/heads/a", oid("c"), Some(oid("a")), "")
+            .unwrap();
+        transaction.commit().unwrap();
+        drop(lock);
+
+        // a ref packed by somebody else after we cached packed-refs
+        refs.packed_refs().unwrap();
+        Refs::new(dir.path().to_path_buf())
+            .unwrap()
+            .pack_refs(&database(&dir), true, false)
+            .unwrap();
+        let mut transaction = RefTransaction::new(&refs);
+        transaction
+            .delete("refs/heads/b", Some(oid("b")), "")
+            .unwrap();
+        transaction.commit().unwrap();
+
+        assert_eq!(refs.read_ref("refs/heads/b").unwrap(), None);
+        assert!(!refs.packed_refs().unwrap().contains("refs/heads/b"));
+        assert!(refs.packed_refs().unwrap().contains("refs/heads/a"));
+        assert!(no_locks(dir.path()));
+    }
+
+    #[test]
+    fn failed_transaction_removes_new_directories() {
+        let (dir, refs) = setup();
+        let mut transaction = RefTransaction::new(&refs);
+        transaction.create("refs/heads/x/y", oid("a"), "").unwrap();
+        transaction.verify("refs/heads/z", oid("z")).unwrap();
+        assert!(transaction.commit().is_err());
+
+        assert!(!dir.path().join("refs/heads/x").exists());
+        assert!(no_locks(dir.path()));
+    }
+
+    #[test]
+    fn symbolic_update_checks_old_target() {
+        let (dir, refs) = setup();
+        std::fs::write(dir.path().join(HEAD), "ref: refs/heads/a\n").unwrap();
+
+        let mut transaction = RefTransaction::new(&refs);
+        transaction
+            .update_symbolic(HEAD, "refs/heads/b", Some("refs/heads/b"), "")
+            .unwrap();
+        assert!(transaction.commit().is_err());
+        assert_eq!(refs.current_branch().unwrap().as_deref(), Some("a"));
+
+        let mut transaction = RefTransaction::new(&refs);
+        transaction
+            .update_symbolic(HEAD, "refs/heads/b", Some("refs/heads/a"), "move")
+            .unwrap();
+        transaction.commit().unwrap();
+        assert_eq!(refs.current_branch().unwrap().as_deref(), Some("b"));
+        assert_eq!(refs.reflog_entries(HEAD).unwrap().len(), 1);
+        assert!(no_locks(dir.path()));
+    }
+
+    #[test]
+    fn rename_moves_head_in_the_same_transaction() {
+        let (dir, refs) = setup();
+        std::fs::write(dir.path().join(HEAD), "ref: refs/heads/a\n").unwrap();
+
+        // a held HEAD lock fails the rename without touching any branch
+        let lock = Lockfile::acquire(dir.path().join(HEAD), std::time::Duration::ZERO).unwrap();
+        assert!(refs.rename_branch("a", "c", false).is_err());
+        drop(lock);
+        assert_eq!(refs.read_ref("refs/heads/a").unwrap(), Some(oid("a")));
+        assert_eq!(refs.read_ref("refs/heads/c").unwrap(), None);
+
+        refs.rename_branch("a", "c", false).unwrap();
+        assert_eq!(refs.current_branch().unwrap().as_deref(), Some("c"));
+        assert_eq!(refs.read_ref(HEAD).unwrap(), Some(oid("a")));
+        assert_eq!(refs.read_ref("refs/heads/a").unwrap(), None);
+        assert!(no_locks(dir.path()));
+    }
+
+    #[test]
+    fn copies_reflog_of_locked_source() {
+        let (dir, refs) = setup();
+        refs.update_ref("refs/heads/a", oid("c"), "move").unwrap();
+
+        let mut transaction = RefTransaction::new(&refs);
+        transaction
+            .create("refs/heads/c", oid("c"), "copy")
+            .unwrap();
+        assert!(transaction
+            .copy_reflog("refs/heads/c", "refs/heads/a")
+            .is_err());
+        transaction.verify("refs/heads/a", oid("c")).unwrap();
+        transaction
+            .copy_reflog("refs/heads/c", "refs/heads/a")
+            .unwrap();
+        transaction.commit().unwrap();
+
+        let source = refs.reflog_entries("refs/heads/a").unwrap();
+        let copy = refs.reflog_entries("refs/heads/c").unwrap();
+        assert_eq!(source.len(), 2);
+        assert_eq!(copy.len(), 3);
+        let lines = |entries: &[ReflogEntry]| -> Vec<String> {
+            entries.iter().map(|entry| entry.to_string()).collect()
+        };
+        assert_eq!(lines(&copy[..2]), lines(&source));
+        assert_eq!((copy[2].old, copy[2].new), (oid("c"), oid("c")));
+        assert!(no_locks(dir.path()));
+    }
+
+    #[test]
+    fn duplicate_updates_are_rejected() {
+        let (_dir, refs) = setup();
+        let mut transaction = RefTransaction::new(&refs);
+        transaction
+            .update("refs/heads/a", oid("c"), None, "")
+            .unwrap();
+        transaction.delete("refs/heads/a", None, "").unwrap();
+        assert!(transaction.commit().is_err());
+
+        assert_eq!(refs.read_ref("refs/heads/a").unwrap(), Some(oid("a")));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::pack::writer::{self, PackObject};
+use crate::*;
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::collections::HashSet;
+use std::env::current_dir;
+use std::io::{self, BufWriter};
+
+/// Pack all objects reachable from HEAD and the refs, either into
+/// `objects/pack`, removing the loose objects it makes redundant, or to stdout.
+pub fn repack_objects(repack: cli::Repack) -> Result<()> {
+    trace!("Repacking objects");
+    debug!("Got arguments: {:?}", repack);
+
+    // get current directory
+    let root_path =
+        current_dir().with_context(|| "Repack: Could not get the current working directory!")?;
+    let git_path = root_path.join(".git");
+    let db_path = git_path.join("objects");
+
+    // check if this is actually a repository.
+    if !is_repo(&root_path)? {
+        bail!("Repack: {:?} is not a rit repository!", root_path);
+    }
+
+    let database = Database::new(&db_path).with_context(|| "Repack: Could not load database")?;
+    let refs = Refs::new(git_path).with_context(|| "Repack: Could not load refs")?;
+
+    let mut roots = Vec::new();
+    roots.extend(refs.read_head()?);
+    for name in refs.list_all()? {
+        roots.extend(refs.read_ref(&name)?);
+    }
+    let objects = reachable_objects(&database, roots)?;
+    info!("Found {} reachable objects", objects.len());
+
+    if repack.stdout {
+        let out = BufWriter::new(io::stdout().lock());
+        writer::pack_objects(&database, &objects, repack.window, repack.depth, out)
+            .with_context(|| "Repack: Could not write pack to stdout")?;
+        return Ok(());
+    }
+    if objects.is_empty() {
+        info!("Nothing to pack");
+        return Ok(());
+    }
+
+    let name = database
+        .write_pack(&objects, repack.window, repack.depth)
+        .with_context(|| "Repack: Could not write pack")?;
+    database
+        .prune_packed(&name)
+        .with_context(|| "Repack: Could not remove packed objects")?;
+    info!("Wrote pack-{}", name);
+
+    Ok(())
+}
+
+/// Collect every object reachable from the commits and tags `roots`.
+fn reachable_objects(database: &Database, roots: Vec<ObjectId>) -> Result<Vec<PackObject>> {
+    trace!("Collecting reachable objects");
+    let mut seen = HashSet::new();
+    let mut objects = Vec::new();
+    let mut queue: Vec<(ObjectId, String)> =
+        roots.into_iter().map(|oid| (oid, String::new())).collect();
+    while let Some((oid, name)) = queue.pop() {
+        if !seen.insert(oid) {
+            continue;
+        }
+
+        let (type_, data) = database
+            .read_object(&oid)
+            .with_context(|| format!("Repack: Could not read reachable object {}", oid))?;
+        objects.push(PackObject {
+            oid,
+            type_,
+            size: data.len(),
+            name_hash: writer::name_hash(&name),
+        });
+        match ParsedObject::parse(type_, data, database.get_algorithm())? {
+            ParsedObject::Commit(commit) => {
+                queue.push((commit.get_tree(), String::new()));
+                queue.extend(commit.get_parents().iter().map(|p| (*p, String::new())));
+            }
+            ParsedObject::Tree(tree) => {
+                for (entry_name, entry) in tree.get_entries() {
+                    match entry.get_type() {
+                        Types::Tree => queue.push((entry.get_oid(), entry_name.clone())),
+                        // submodule commits live in another repository,
+                        // trees never hold tags
+                        Types::Commit | Types::Tag => (),
+                        Types::Blob => {
+                            let oid = entry.get_oid();
+                            if seen.insert(oid) {
+                                let (type_, size) = database.read_header(&oid)?;
+                                objects.push(PackObject {
+                                    oid,
+                                    type_,
+                                    size,
+                                    name_hash: writer::name_hash(entry_name),
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+            ParsedObject::Tag(tag) => queue.push((tag.get_object(), String::new())),
+            ParsedObject::Blob(_) => (),
+        }
+    }
+
+    Ok(objects)
+}
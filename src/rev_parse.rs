@@ -0,0 +1,38 @@
+use crate::*;
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::env::current_dir;
+
+/// Print the oid of every given revision.
+pub fn parse_revisions(rev_parse: cli::RevParse) -> Result<()> {
+    trace!("Parsing revisions");
+    debug!("Got arguments: {:?}", rev_parse);
+
+    // get current directory
+    let root_path =
+        current_dir().with_context(|| "RevParse: Could not get the current working directory!")?;
+    let git_path = root_path.join(".git");
+    let db_path = git_path.join("objects");
+
+    // check if this is actually a repository.
+    if !is_repo(&root_path)? {
+        bail!("RevParse: {:?} is not a rit repository!", root_path);
+    }
+
+    let database = Database::new(&db_path).with_context(|| "RevParse: Could not load database")?;
+    let refs = Refs::new(git_path).with_context(|| "RevParse: Could not load refs")?;
+
+    if rev_parse.verify && rev_parse.revisions.len() != 1 {
+        bail!("RevParse: Needed a single revision");
+    }
+
+    for expr in rev_parse.revisions.iter() {
+        let oid = Revision::new(&refs, &database, expr)?.resolve(None)?;
+        match rev_parse.short {
+            Some(len) => println!("{}", database.abbreviate(&oid, len.max(MIN_PREFIX_LEN))?),
+            None => println!("{}", oid),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,605 @@
+use crate::*;
+use anyhow::{bail, Context, Result};
+use log::*;
+
+/// Shortest prefix of an oid we try to expand.
+pub const MIN_PREFIX_LEN: usize = 4;
+
+/// What `^{...}` peels a revision to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PeelTarget {
+    /// `^{}`, dereference tags until something else is found.
+    Deref,
+
+    /// `^{object}`, the object only has to exist.
+    Object,
+
+    /// `^{type}`, peel until an object of that type is found.
+    Type(Types),
+}
+
+/// The parsed form of a revision expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rev {
+    /// A ref name or an (abbreviated) oid.
+    Ref(String),
+
+    /// `rev^n`, the n-th parent, `rev^0` is the commit itself.
+    Parent(Box<Rev>, usize),
+
+    /// `rev~n`, the n-th generation ancestor following first parents.
+    Ancestor(Box<Rev>, usize),
+
+    /// `rev^{type}`.
+    Peel(Box<Rev>, PeelTarget),
+
+    /// `ref@{n}`, the n-th prior value of a ref.
+    Reflog(Option<String>, usize),
+
+    /// `@{-n}`, the n-th branch checked out before the current one.
+    PreviousBranch(usize),
+
+    /// `rev:path`, the object at `path` in the tree of `rev`.
+    Path(Box<Rev>, String),
+
+    /// `:path` or `:n:path`, the object at `path` in the `Index` at stage n.
+    IndexPath(u8, String),
+}
+
+/// A `Revision` turns a user supplied expression like `HEAD~3` or
+/// `master:src/lib.rs` into an oid.
+pub struct Revision<'a> {
+    /// Refs to resolve names against.
+    refs: &'a Refs,
+
+    /// Database holding the objects.
+    database: &'a Database,
+
+    /// The expression as given.
+    expr: String,
+
+    /// The parsed expression.
+    query: Rev,
+}
+
+impl<'a> Revision<'a> {
+    /// Parse `expr` into a new `Revision`.
+    pub fn new(refs: &'a Refs, database: &'a Database, expr: &str) -> Result<Self> {
+        trace!("Parsing revision {}", expr);
+        let query = Self::parse(expr)
+            .with_context(|| format!("Revision: ambiguous argument '{}'", expr))?;
+        debug!("Parsed {} into {:?}", expr, query);
+
+        Ok(Self {
+            refs,
+            database,
+            expr: expr.to_string(),
+            query,
+        })
+    }
+
+    /// Parse a revision expression.
+    pub fn parse(expr: &str) -> Result<Rev> {
+        if expr.is_empty() {
+            bail!("Revision: empty revision");
+        }
+
+        // `:path` and `:n:path` refer to the index
+        if let Some(rest) = expr.strip_prefix(':') {
+            let (stage, path) = match rest.split_once(':') {
+                Some((stage, path)) if stage.len() == 1 && stage.as_bytes()[0].is_ascii_digit() => {
+                    (stage.parse::<u8>()?, path)
+                }
+                _ => (0, rest),
+            };
+            if stage > 3 || path.is_empty() {
+                bail!("Revision: invalid index path {}", expr);
+            }
+            return Ok(Rev::IndexPath(stage, path.to_string()));
+        }
+
+        // `rev:path`, the colon must not be inside braces
+        if let Some(colon) = Self::find_unbraced(expr, ':') {
+            let rev = Self::parse(&expr[..colon])?;
+            return Ok(Rev::Path(Box::new(rev), expr[colon + 1..].to_string()));
+        }
+
+        // the base ends at the first operator
+        let end = expr
+            .find(['^', '~'])
+            .unwrap_or(expr.len())
+            .min(expr.find("@{").unwrap_or(expr.len()));
+        let (base, mut rest) = expr.split_at(end);
+
+        let mut rev = if let Some(selector) = rest.strip_prefix("@{") {
+            let close = selector
+                .find('}')
+                .with_context(|| "Revision: unterminated `@{`")?;
+            let (inner, after) = selector.split_at(close);
+            rest = &after[1..];
+            if let Some(n) = inner.strip_prefix('-') {
+                if !base.is_empty() {
+                    bail!("Revision: `@{{-n}}` cannot follow a ref name");
+                }
+                Rev::PreviousBranch(Self::parse_number(n)?.max(1))
+            } else {
+                let name = match base {
+                    "" => None,
+                    name => Some(Self::parse_name(name)?),
+                };
+                Rev::Reflog(name, Self::parse_number(inner)?)
+            }
+        } else {
+            Rev::Ref(Self::parse_name(base)?)
+        };
+
+        // apply suffix operators left to right
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix("^{") {
+                let close = after
+                    .find('}')
+                    .with_context(|| "Revision: unterminated `^{`")?;
+                let target = match &after[..close] {
+                    "" => PeelTarget::Deref,
+                    "object" => PeelTarget::Object,
+                    type_ => PeelTarget::Type(type_.parse()?),
+                };
+                rev = Rev::Peel(Box::new(rev), target);
+                rest = &after[close + 1..];
+            } else if let Some(after) = rest.strip_prefix('^') {
+                let (n, after) = Self::split_number(after, 1)?;
+                rev = Rev::Parent(Box::new(rev), n);
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix('~') {
+                let (n, after) = Self::split_number(after, 1)?;
+                rev = Rev::Ancestor(Box::new(rev), n);
+                rest = after;
+            } else {
+                bail!("Revision: unexpected {:?}", rest);
+            }
+        }
+
+        Ok(rev)
+    }
+
+    /// Resolve this `Revision` to an oid.
+    /// If `type_` is given the result is peeled to an object of that type.
+    pub fn resolve(&self, type_: Option<Types>) -> Result<ObjectId> {
+        trace!("Resolving revision {}", self.expr);
+        let oid = self
+            .resolve_rev(&self.query)
+            .with_context(|| format!("Revision: bad revision '{}'", self.expr))?;
+
+        match type_ {
+            Some(type_) => self.peel(&oid, &PeelTarget::Type(type_)).with_context(|| {
+                format!("Revision: '{}' is not a {}", self.expr, type_.as_string())
+            }),
+            None => Ok(oid),
+        }
+    }
+
+    /// Resolve a parsed expression.
+    fn resolve_rev(&self, rev: &Rev) -> Result<ObjectId> {
+        match rev {
+            Rev::Ref(name) => self.read_ref(name),
+            Rev::Parent(rev, n) => {
+                let oid = self.peel(&self.resolve_rev(rev)?, &PeelTarget::Type(Types::Commit))?;
+                if *n == 0 {
+                    return Ok(oid);
+                }
+                self.load_commit(&oid)?
+                    .get_parents()
+                    .get(n - 1)
+                    .copied()
+                    .with_context(|| format!("Revision: {} has no parent {}", oid, n))
+            }
+            Rev::Ancestor(rev, n) => {
+                let mut oid =
+                    self.peel(&self.resolve_rev(rev)?, &PeelTarget::Type(Types::Commit))?;
+                for _ in 0..*n {
+                    oid = self
+                        .load_commit(&oid)?
+                        .get_parent()
+                        .with_context(|| format!("Revision: {} has no parent", oid))?;
+                }
+                Ok(oid)
+            }
+            Rev::Peel(rev, target) => {
+                let oid = self.resolve_rev(rev)?;
+                // packed-refs might already know what the ref peels to
+                if let (Rev::Ref(name), PeelTarget::Deref) = (rev.as_ref(), target) {
+                    if self.refs.read_ref(name)? == Some(oid) {
+                        if let Some(peeled) = self.refs.peeled(name)? {
+                            return Ok(peeled);
+                        }
+                    }
+                }
+                self.peel(&oid, target)
+            }
+            Rev::Reflog(name, n) => self.read_reflog(name.as_deref(), *n),
+            Rev::PreviousBranch(n) => self.previous_branch(*n),
+            Rev::Path(rev, path) => {
+                let tree = self.peel(&self.resolve_rev(rev)?, &PeelTarget::Type(Types::Tree))?;
+                self.lookup_path(&tree, path)
+            }
+            Rev::IndexPath(stage, path) => {
+                let mut index = Index::new(
+                    self.refs.get_path().join("index"),
+                    self.database.get_algorithm(),
+                )?;
+                index.load()?;
+                let oid = index
+                    .get_entries()
+                    .find(|entry| &entry.path == path && entry.get_stage() == *stage)
+                    .map(|entry| entry.oid)
+                    .with_context(|| format!("Revision: path '{}' is not in the index", path))?;
+                Ok(oid)
+            }
+        }
+    }
+
+    /// Resolve a name, trying ref names before (abbreviated) oids.
+    fn read_ref(&self, name: &str) -> Result<ObjectId> {
+        let name = if name == "@" { HEAD } else { name };
+        let is_hex = name.len() >= MIN_PREFIX_LEN && name.bytes().all(|b| b.is_ascii_hexdigit());
+
+        // a full oid wins if it exists
+        if let Ok(oid) = ObjectId::from_hex(name, self.database.get_algorithm()) {
+            if self.database.contains(&oid) {
+                return Ok(oid);
+            }
+        }
+        if let Some(oid) = self.refs.read_ref(name)? {
+            return Ok(oid);
+        }
+        if is_hex {
+            if let Some(oid) = self.database.prefix_match(name)? {
+                return Ok(oid);
+            }
+        }
+
+        bail!("Revision: unknown revision {}", name)
+    }
+
+    /// Peel `oid` to `target`, following tags until it is reached.
+    fn peel(&self, oid: &ObjectId, target: &PeelTarget) -> Result<ObjectId> {
+        let mut oid = *oid;
+        loop {
+            let object = self.database.load(&oid)?;
+            match (target, object) {
+                (PeelTarget::Object, _) => return Ok(oid),
+                (PeelTarget::Type(type_), object) if object.get_type() == *type_ => return Ok(oid),
+                (_, ParsedObject::Tag(tag)) => oid = tag.get_object(),
+                (PeelTarget::Deref, _) => return Ok(oid),
+                (PeelTarget::Type(Types::Tree), ParsedObject::Commit(commit)) => {
+                    return Ok(commit.get_tree())
+                }
+                (PeelTarget::Type(type_), object) => bail!(
+                    "Revision: {} is a {}, not a {}",
+                    oid,
+                    object.get_type().as_string(),
+                    type_.as_string()
+                ),
+            }
+        }
+    }
+
+    /// Load the `Commit` `oid`.
+    fn load_commit(&self, oid: &ObjectId) -> Result<database::Commit> {
+        match self.database.load(oid)? {
+            ParsedObject::Commit(commit) => Ok(commit),
+            object => bail!(
+                "Revision: {} is a {}, not a commit",
+                oid,
+                object.get_type().as_string()
+            ),
+        }
+    }
+
+    /// Find the entry at `path` below the tree `oid`.
+    fn lookup_path(&self, oid: &ObjectId, path: &str) -> Result<ObjectId> {
+        let mut oid = *oid;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            let tree = match self.database.load(&oid)? {
+                ParsedObject::Tree(tree) => tree,
+                _ => bail!(
+                    "Revision: path '{}' does not exist in '{}'",
+                    path,
+                    self.expr
+                ),
+            };
+            oid = tree
+                .get_entries()
+                .get(name)
+                .with_context(|| {
+                    format!(
+                        "Revision: path '{}' does not exist in '{}'",
+                        path, self.expr
+                    )
+                })?
+                .get_oid();
+        }
+
+        Ok(oid)
+    }
+
+    /// Read the `n`-th prior value of the ref `name` from its reflog.
+    /// Without a name the current branch is used.
+    fn read_reflog(&self, name: Option<&str>, n: usize) -> Result<ObjectId> {
+        let full_name = match name {
+            Some(name) if name == HEAD || name == "@" => HEAD.to_string(),
+            Some(name) => self
+                .refs
+                .full_name(name)
+                .with_context(|| format!("Revision: unknown ref {}", name))?,
+            None => match self.refs.head_state()? {
+                HeadState::Branch(branch) => branch,
+                HeadState::Detached(_) => HEAD.to_string(),
+            },
+        };
+
+        let entries = self.refs.reflog_entries(&full_name)?;
+        entries
+            .iter()
+            .rev()
+            .nth(n)
+            .map(|entry| entry.new)
+            .with_context(|| {
+                format!(
+                    "Revision: log for '{}' only has {} entries",
+                    Refs::short_name(&full_name),
+                    entries.len()
+                )
+            })
+    }
+
+    /// Find the `n`-th branch checked out before the current one.
+    fn previous_branch(&self, n: usize) -> Result<ObjectId> {
+        let entries = self.refs.reflog_entries(HEAD)?;
+        let previous = entries
+            .iter()
+            .rev()
+            .filter_map(|entry| entry.message.strip_prefix("checkout: moving from "))
+            .filter_map(|moving| moving.split_once(" to ").map(|(from, _)| from.to_string()))
+            .nth(n - 1)
+            .with_context(|| format!("Revision: no {}-th previous branch", n))?;
+
+        self.read_ref(&previous)
+    }
+
+    /// Validate a ref name or oid in an expression.
+    fn parse_name(name: &str) -> Result<String> {
+        if name == "@" || name == HEAD || is_valid_ref_name(name) {
+            return Ok(name.to_string());
+        }
+        bail!("Revision: invalid name {:?}", name)
+    }
+
+    /// Parse a decimal number.
+    fn parse_number(s: &str) -> Result<usize> {
+        s.parse()
+            .with_context(|| format!("Revision: {:?} is not a number", s))
+    }
+
+    /// Split leading digits off `s`, returning `default` if there are none.
+    fn split_number(s: &str, default: usize) -> Result<(usize, &str)> {
+        let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        match end {
+            0 => Ok((default, s)),
+            end => Ok((Self::parse_number(&s[..end])?, &s[end..])),
+        }
+    }
+
+    /// Find the first `c` in `s` that is not enclosed in braces.
+    fn find_unbraced(s: &str, c: char) -> Option<usize> {
+        let mut depth = 0usize;
+        for (i, ch) in s.char_indices() {
+            match ch {
+                '{' => depth += 1,
+                '}' => depth = depth.saturating_sub(1),
+                ch if ch == c && depth == 0 => return Some(i),
+                _ => (),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::create_dir_all;
+
+    fn name(name: &str) -> Box<Rev> {
+        Box::new(Rev::Ref(name.to_string()))
+    }
+
+    fn parse(expr: &str) -> Rev {
+        Revision::parse(expr).unwrap()
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(parse("HEAD"), Rev::Ref("HEAD".into()));
+        assert_eq!(parse("@"), Rev::Ref("@".into()));
+        assert_eq!(parse("feature/x"), Rev::Ref("feature/x".into()));
+        assert_eq!(parse("1a2b3c"), Rev::Ref("1a2b3c".into()));
+    }
+
+    #[test]
+    fn parses_parents() {
+        assert_eq!(parse("master^"), Rev::Parent(name("master"), 1));
+        assert_eq!(parse("master^2"), Rev::Parent(name("master"), 2));
+        assert_eq!(parse("HEAD^0"), Rev::Parent(name("HEAD"), 0));
+        assert_eq!(
+            parse("HEAD^^"),
+            Rev::Parent(Box::new(Rev::Parent(name("HEAD"), 1)), 1)
+        );
+    }
+
+    #[test]
+    fn parses_ancestors() {
+        assert_eq!(parse("HEAD~"), Rev::Ancestor(name("HEAD"), 1));
+        assert_eq!(parse("HEAD~3"), Rev::Ancestor(name("HEAD"), 3));
+        assert_eq!(parse("HEAD~10"), Rev::Ancestor(name("HEAD"), 10));
+        // operators apply left to right
+        assert_eq!(
+            parse("HEAD~2^2"),
+            Rev::Parent(Box::new(Rev::Ancestor(name("HEAD"), 2)), 2)
+        );
+        assert_eq!(
+            parse("HEAD^2~"),
+            Rev::Ancestor(Box::new(Rev::Parent(name("HEAD"), 2)), 1)
+        );
+    }
+
+    #[test]
+    fn parses_reflog_selectors() {
+        assert_eq!(parse("master@{3}"), Rev::Reflog(Some("master".into()), 3));
+        assert_eq!(parse("@{0}"), Rev::Reflog(None, 0));
+        assert_eq!(parse("HEAD@{1}"), Rev::Reflog(Some("HEAD".into()), 1));
+        assert_eq!(
+            parse("master@{1}~2"),
+            Rev::Ancestor(Box::new(Rev::Reflog(Some("master".into()), 1)), 2)
+        );
+    }
+
+    #[test]
+    fn parses_previous_branches() {
+        assert_eq!(parse("@{-1}"), Rev::PreviousBranch(1));
+        assert_eq!(parse("@{-3}"), Rev::PreviousBranch(3));
+        assert_eq!(
+            parse("@{-1}^"),
+            Rev::Parent(Box::new(Rev::PreviousBranch(1)), 1)
+        );
+    }
+
+    #[test]
+    fn parses_peeling() {
+        assert_eq!(parse("v1^{}"), Rev::Peel(name("v1"), PeelTarget::Deref));
+        assert_eq!(
+            parse("v1^{object}"),
+            Rev::Peel(name("v1"), PeelTarget::Object)
+        );
+        for type_ in [Types::Commit, Types::Tree, Types::Blob, Types::Tag] {
+            assert_eq!(
+                parse(&format!("v1^{{{}}}", type_.as_string())),
+                Rev::Peel(name("v1"), PeelTarget::Type(type_))
+            );
+        }
+        assert_eq!(
+            parse("HEAD~1^{tree}"),
+            Rev::Peel(
+                Box::new(Rev::Ancestor(name("HEAD"), 1)),
+                PeelTarget::Type(Types::Tree)
+            )
+        );
+    }
+
+    #[test]
+    fn parses_paths() {
+        assert_eq!(
+            parse("HEAD:src/lib.rs"),
+            Rev::Path(name("HEAD"), "src/lib.rs".into())
+        );
+        assert_eq!(
+            parse("HEAD^{tree}:a"),
+            Rev::Path(
+                Box::new(Rev::Peel(name("HEAD"), PeelTarget::Type(Types::Tree))),
+                "a".into()
+            )
+        );
+        assert_eq!(parse(":a"), Rev::IndexPath(0, "a".into()));
+        assert_eq!(parse(":2:a/b"), Rev::IndexPath(2, "a/b".into()));
+    }
+
+    #[test]
+    fn rejects_invalid_input() {
+        for expr in [
+            "",
+            "HEAD^{tree",
+            "HEAD^{bogus}",
+            "HEAD@{",
+            "HEAD@{x}",
+            "HEAD@{-1}",
+            "@{-x}",
+            "HEAD^x",
+            "HEAD~x",
+            "a..b",
+            "foo.lock",
+            ":4:a",
+            ":",
+        ] {
+            assert!(
+                Revision::parse(expr).is_err(),
+                "{:?} should not parse",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn peels_tags() {
+        let dir = TestDir::new();
+        create_dir_all(dir.path().join("objects")).unwrap();
+        let database = Database::new(&dir.path().join("objects")).unwrap();
+        let refs = Refs::new(dir.path().to_path_buf()).unwrap();
+
+        let tree = Tree::new().write(&database).unwrap();
+        let author = Author::parse("A <a@x> 0 +0000").unwrap();
+        let commit =
+            &mut database::Commit::new(None, tree, Message::new(author.clone(), "c".into()));
+        let commit = database.store(commit).unwrap();
+        let tag = &mut Tag::new(
+            commit,
+            Types::Commit,
+            "v1".into(),
+            author.clone(),
+            "t".into(),
+        );
+        let tag = database.store(tag).unwrap();
+        let outer = &mut Tag::new(tag, Types::Tag, "v2".into(), author, "t".into());
+        let outer = database.store(outer).unwrap();
+
+        let resolve = |expr: String| Revision::new(&refs, &database, &expr)?.resolve(None);
+        assert_eq!(resolve(format!("{}^{{}}", outer)).unwrap(), commit);
+        assert_eq!(resolve(format!("{}^{{tag}}", outer)).unwrap(), outer);
+        assert_eq!(resolve(format!("{}^{{object}}", outer)).unwrap(), outer);
+        assert_eq!(resolve(format!("{}^{{commit}}", outer)).unwrap(), commit);
+        assert_eq!(resolve(format!("{}^{{tree}}", outer)).unwrap(), tree);
+        assert_eq!(resolve(format!("{}^0", tag)).unwrap(), commit);
+        assert_eq!(resolve(format!("{}^{{}}", commit)).unwrap(), commit);
+        assert!(resolve(format!("{}^{{blob}}", outer)).is_err());
+        assert!(resolve(format!("{}^{{tag}}", commit)).is_err());
+
+        // packing records what the tag peels to
+        let mut transaction = RefTransaction::new(&refs);
+        transaction.create("refs/tags/v2", outer, "").unwrap();
+        transaction.create("refs/heads/main", commit, "").unwrap();
+        transaction.commit().unwrap();
+        refs.pack_refs(&database, true, true).unwrap();
+        let packed = std::fs::read_to_string(dir.path().join(PACKED_REFS)).unwrap();
+        assert_eq!(
+            packed,
+            format!(
+                "# pack-refs with: peeled fully-peeled sorted \n\
+                 {} refs/heads/main\n{} refs/tags/v2\n^{}\n",
+                commit, outer, commit
+            )
+        );
+        assert_eq!(refs.peeled("v2").unwrap(), Some(commit));
+        assert_eq!(refs.peeled("main").unwrap(), None);
+        assert_eq!(resolve("v2^{}".into()).unwrap(), commit);
+
+        // `^{}` takes the recorded value without reading the tags
+        std::fs::write(
+            dir.path().join(PACKED_REFS),
+            packed.replace(&format!("^{}", commit), &format!("^{}", tree)),
+        )
+        .unwrap();
+        let refs = Refs::new(dir.path().to_path_buf()).unwrap();
+        let resolve = |expr: &str| Revision::new(&refs, &database, expr)?.resolve(None);
+        assert_eq!(resolve("v2^{}").unwrap(), tree);
+        assert_eq!(resolve("v2^{commit}").unwrap(), commit);
+    }
+}
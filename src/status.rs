@@ -0,0 +1,475 @@
+use crate::*;
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env::current_dir;
+use std::fs::Metadata;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Width of the labels in the long format, e.g. `new file:   `.
+const LABEL_WIDTH: usize = 12;
+
+/// The kind of change a path underwent.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ChangeType {
+    Added,
+    Modified,
+    Deleted,
+}
+
+impl ChangeType {
+    /// Status letter as used in the short format.
+    fn short(change: Option<&ChangeType>) -> char {
+        match change {
+            Some(ChangeType::Added) => 'A',
+            Some(ChangeType::Modified) => 'M',
+            Some(ChangeType::Deleted) => 'D',
+            None => ' ',
+        }
+    }
+
+    /// Status letter as used in porcelain v2.
+    fn porcelain_v2(change: Option<&ChangeType>) -> char {
+        match change {
+            None => '.',
+            change => Self::short(change),
+        }
+    }
+
+    /// Label as used in the long format.
+    fn long(&self) -> &'static str {
+        match self {
+            ChangeType::Added => "new file:",
+            ChangeType::Modified => "modified:",
+            ChangeType::Deleted => "deleted:",
+        }
+    }
+}
+
+/// The differences between HEAD, the `Index` and the `Workspace`.
+struct Changes {
+    /// Files in the `Tree` of HEAD.
+    head_tree: BTreeMap<String, Entry>,
+
+    /// Files in the `Index` at stage 0, as they were before refreshing.
+    index_entries: BTreeMap<String, IndexEntry>,
+
+    /// Modes of files in the `Workspace` that are tracked.
+    workspace_modes: BTreeMap<String, u32>,
+
+    /// Differences between HEAD and the `Index`.
+    index_changes: BTreeMap<String, ChangeType>,
+
+    /// Differences between the `Index` and the `Workspace`.
+    workspace_changes: BTreeMap<String, ChangeType>,
+
+    /// Every path with a change in the `Index` or the `Workspace`.
+    changed: BTreeSet<String>,
+
+    /// Untracked files, untracked directories end with `/`.
+    untracked: BTreeSet<String>,
+
+    /// Does HEAD point to a commit.
+    has_head: bool,
+
+    /// The state of HEAD, to print the current branch.
+    head_state: HeadState,
+
+    /// Abbreviated oid of a detached HEAD.
+    detached_at: Option<String>,
+}
+
+/// Show the status of the working tree.
+pub fn show_status(status: cli::Status) -> Result<()> {
+    trace!("Showing status");
+    debug!("Got arguments: {:?}", status);
+
+    // get current directory
+    let root_path =
+        current_dir().with_context(|| "Status: Could not get the current working directory!")?;
+    let git_path = root_path.join(".git");
+    let db_path = git_path.join("objects");
+
+    // check if this is actually a repository.
+    if !is_repo(&root_path)? {
+        bail!("Status: {:?} is not a rit repository!", root_path);
+    }
+
+    let workspace =
+        Workspace::new(&root_path).with_context(|| "Status: Could not load workspace!")?;
+    let database = Database::new(&db_path).with_context(|| "Status: Could not load database")?;
+    let refs = Refs::new(git_path.clone()).with_context(|| "Status: Could not load refs")?;
+    let mut index = Index::new(git_path.join("index"), database.get_algorithm())
+        .with_context(|| "Status: Could not create index")?;
+    index
+        .load()
+        .with_context(|| "Status: Could not load index")?;
+
+    let changes = Changes::collect(&workspace, &database, &refs, &mut index)?;
+    // refreshed stat data is only a cache, skip storing it if the index is
+    // busy
+    index
+        .try_write_updates()
+        .with_context(|| "Status: Could not write index")?;
+
+    let terminator = if status.null { '\0' } else { '\n' };
+    let quote = |path: &str| {
+        if status.null {
+            path.to_string()
+        } else {
+            quote_path(path)
+        }
+    };
+
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    match (status.porcelain.as_deref(), status.short || status.null) {
+        (Some("v2"), _) => {
+            changes.print_porcelain_v2(&mut out, terminator, &quote, database.get_algorithm())
+        }
+        (Some(_), _) | (None, true) => changes.print_short(&mut out, terminator, &quote),
+        (None, false) => changes.print_long(&mut out, &quote),
+    }
+    .with_context(|| "Status: Could not write output")?;
+    out.flush()
+        .with_context(|| "Status: Could not flush output")?;
+
+    Ok(())
+}
+
+impl Changes {
+    /// Compare HEAD, the `index` and the `workspace`.
+    /// Entries of unchanged files with stale stat data are refreshed.
+    fn collect(
+        workspace: &Workspace,
+        database: &Database,
+        refs: &Refs,
+        index: &mut Index,
+    ) -> Result<Self> {
+        trace!("Collecting changes");
+        let head = refs
+            .read_head()
+            .with_context(|| "Status: Could not read HEAD")?;
+        let head_tree = database
+            .load_tree_list(head.as_ref())
+            .with_context(|| "Status: Could not load tree of HEAD")?;
+        let index_entries: BTreeMap<String, IndexEntry> = index
+            .get_entries()
+            .filter(|entry| entry.get_stage() == 0)
+            .map(|entry| (entry.path.clone(), entry.clone()))
+            .collect();
+
+        let head_state = refs
+            .head_state()
+            .with_context(|| "Status: Could not determine state of HEAD")?;
+        let detached_at = match &head_state {
+            HeadState::Detached(oid) => Some(database.short_oid(oid)?),
+            HeadState::Branch(_) => None,
+        };
+
+        let mut changes = Self {
+            head_tree,
+            index_entries,
+            workspace_modes: BTreeMap::new(),
+            index_changes: BTreeMap::new(),
+            workspace_changes: BTreeMap::new(),
+            changed: BTreeSet::new(),
+            untracked: BTreeSet::new(),
+            has_head: head.is_some(),
+            head_state,
+            detached_at,
+        };
+
+        changes.collect_untracked(workspace, index);
+        changes.check_index_against_workspace(workspace, database, index)?;
+        changes.check_index_against_head_tree();
+
+        Ok(changes)
+    }
+
+    /// Find files that are not in the `index`, collapsing directories that
+    /// contain no tracked file at all.
+    fn collect_untracked(&mut self, workspace: &Workspace, index: &Index) {
+        for path in workspace.get_list_files() {
+            let name = path.to_string_lossy().to_string();
+            if index.is_tracked_file(&name) {
+                continue;
+            }
+
+            let untracked_dir = path
+                .ancestors()
+                .skip(1)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .map(|dir| dir.to_string_lossy().to_string())
+                .filter(|dir| !index.is_tracked(dir))
+                .last();
+            match untracked_dir {
+                Some(dir) => self.untracked.insert(format!("{}/", dir)),
+                None => self.untracked.insert(name),
+            };
+        }
+    }
+
+    /// Compare every entry of the `index` with the file in the `workspace`.
+    fn check_index_against_workspace(
+        &mut self,
+        workspace: &Workspace,
+        database: &Database,
+        index: &mut Index,
+    ) -> Result<()> {
+        for (path, entry) in self.index_entries.iter() {
+            let stat = match workspace.read_metadata(&Path::new(path).to_path_buf()) {
+                Ok(stat) if stat.is_file() => stat,
+                _ => {
+                    Self::record(
+                        &mut self.changed,
+                        &mut self.workspace_changes,
+                        path,
+                        ChangeType::Deleted,
+                    );
+                    continue;
+                }
+            };
+            self.workspace_modes
+                .insert(path.clone(), IndexEntry::mode_for_stat(&stat));
+
+            if Self::is_modified(workspace, database, entry, &stat)? {
+                Self::record(
+                    &mut self.changed,
+                    &mut self.workspace_changes,
+                    path,
+                    ChangeType::Modified,
+                );
+            } else if !entry.times_match(&stat) {
+                debug!("Refreshing stat data of {}", path);
+                index.update_entry_stat(path, &stat);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Has the file of `entry` changed, only hashing it if the stat data is
+    /// inconclusive.
+    fn is_modified(
+        workspace: &Workspace,
+        database: &Database,
+        entry: &IndexEntry,
+        stat: &Metadata,
+    ) -> Result<bool> {
+        if !entry.stat_match(stat) {
+            return Ok(true);
+        }
+        if entry.times_match(stat) {
+            return Ok(false);
+        }
+
+        // hash the file in chunks like `add` stores it
+        let (mut file, len) = workspace
+            .open_file(&Path::new(&entry.path).to_path_buf())
+            .with_context(|| format!("Status: Could not open {}", entry.path))?;
+        let oid = Database::hash_stream(database.get_algorithm(), Types::Blob, len, &mut file)
+            .with_context(|| format!("Status: Could not hash {}", entry.path))?;
+
+        Ok(oid != entry.oid)
+    }
+
+    /// Compare every entry of the `index` with the `Tree` of HEAD.
+    fn check_index_against_head_tree(&mut self) {
+        for (path, entry) in self.index_entries.iter() {
+            let change = match self.head_tree.get(path) {
+                None => Some(ChangeType::Added),
+                Some(item)
+                    if item.get_mode() != entry.get_mode() || item.get_oid() != entry.oid =>
+                {
+                    Some(ChangeType::Modified)
+                }
+                Some(_) => None,
+            };
+            if let Some(change) = change {
+                Self::record(&mut self.changed, &mut self.index_changes, path, change);
+            }
+        }
+
+        for path in self.head_tree.keys() {
+            if !self.index_entries.contains_key(path) {
+                Self::record(
+                    &mut self.changed,
+                    &mut self.index_changes,
+                    path,
+                    ChangeType::Deleted,
+                );
+            }
+        }
+    }
+
+    /// Remember `change` of `path` in `set`.
+    fn record(
+        changed: &mut BTreeSet<String>,
+        set: &mut BTreeMap<String, ChangeType>,
+        path: &str,
+        change: ChangeType,
+    ) {
+        changed.insert(path.to_string());
+        set.insert(path.to_string(), change);
+    }
+
+    /// Print the human readable long format.
+    fn print_long(&self, out: &mut impl Write, quote: &dyn Fn(&str) -> String) -> io::Result<()> {
+        match &self.head_state {
+            HeadState::Branch(branch) => writeln!(out, "On branch {}", Refs::short_name(branch))?,
+            HeadState::Detached(oid) => writeln!(
+                out,
+                "HEAD detached at {}",
+                self.detached_at.as_deref().unwrap_or(&oid.to_hex())
+            )?,
+        }
+        if !self.has_head {
+            writeln!(out)?;
+            writeln!(out, "No commits yet")?;
+            writeln!(out)?;
+        }
+
+        self.print_changes(
+            out,
+            "Changes to be committed:",
+            &[],
+            &self.index_changes,
+            quote,
+        )?;
+        self.print_changes(
+            out,
+            "Changes not staged for commit:",
+            &["(use \"rit add <file>...\" to update what will be committed)"],
+            &self.workspace_changes,
+            quote,
+        )?;
+        if !self.untracked.is_empty() {
+            writeln!(out, "Untracked files:")?;
+            writeln!(
+                out,
+                "  (use \"rit add <file>...\" to include in what will be committed)"
+            )?;
+            for path in self.untracked.iter() {
+                writeln!(out, "\t{}", quote(path))?;
+            }
+            writeln!(out)?;
+        }
+
+        if !self.index_changes.is_empty() {
+            return Ok(());
+        }
+        if !self.workspace_changes.is_empty() {
+            writeln!(
+                out,
+                "no changes added to commit (use \"rit add\" and/or \"rit commit\")"
+            )
+        } else if !self.untracked.is_empty() {
+            writeln!(
+                out,
+                "nothing added to commit but untracked files present (use \"rit add\" to track)"
+            )
+        } else if !self.has_head {
+            writeln!(
+                out,
+                "nothing to commit (create/copy files and use \"rit add\" to track)"
+            )
+        } else {
+            writeln!(out, "nothing to commit, working tree clean")
+        }
+    }
+
+    /// Print one section of changes of the long format.
+    fn print_changes(
+        &self,
+        out: &mut impl Write,
+        title: &str,
+        hints: &[&str],
+        changes: &BTreeMap<String, ChangeType>,
+        quote: &dyn Fn(&str) -> String,
+    ) -> io::Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(out, "{}", title)?;
+        for hint in hints {
+            writeln!(out, "  {}", hint)?;
+        }
+        for (path, change) in changes {
+            writeln!(
+                out,
+                "\t{:width$}{}",
+                change.long(),
+                quote(path),
+                width = LABEL_WIDTH
+            )?;
+        }
+        writeln!(out)
+    }
+
+    /// Print the short format, which is also porcelain v1.
+    fn print_short(
+        &self,
+        out: &mut impl Write,
+        terminator: char,
+        quote: &dyn Fn(&str) -> String,
+    ) -> io::Result<()> {
+        for path in self.changed.iter() {
+            write!(
+                out,
+                "{}{} {}{}",
+                ChangeType::short(self.index_changes.get(path)),
+                ChangeType::short(self.workspace_changes.get(path)),
+                quote(path),
+                terminator
+            )?;
+        }
+        for path in self.untracked.iter() {
+            write!(out, "?? {}{}", quote(path), terminator)?;
+        }
+
+        Ok(())
+    }
+
+    /// Print porcelain v2, a missing side has the null oid of `algorithm`.
+    fn print_porcelain_v2(
+        &self,
+        out: &mut impl Write,
+        terminator: char,
+        quote: &dyn Fn(&str) -> String,
+        algorithm: HashAlgorithm,
+    ) -> io::Result<()> {
+        let null_oid = algorithm.null_oid();
+        for path in self.changed.iter() {
+            let head = self.head_tree.get(path);
+            let index = self.index_entries.get(path);
+            let head_mode = head.map_or(0, |e| u32::from_str_radix(&e.get_mode(), 8).unwrap_or(0));
+            let index_mode = index.map_or(0, |e| e.mode);
+            let workspace_mode = match self.workspace_changes.get(path) {
+                Some(ChangeType::Deleted) => 0,
+                Some(_) => self.workspace_modes.get(path).copied().unwrap_or(0),
+                None => index_mode,
+            };
+
+            write!(
+                out,
+                "1 {}{} N... {:06o} {:06o} {:06o} {} {} {}{}",
+                ChangeType::porcelain_v2(self.index_changes.get(path)),
+                ChangeType::porcelain_v2(self.workspace_changes.get(path)),
+                head_mode,
+                index_mode,
+                workspace_mode,
+                head.map_or(null_oid, |e| e.get_oid()),
+                index.map_or(null_oid, |e| e.oid),
+                quote(path),
+                terminator
+            )?;
+        }
+        for path in self.untracked.iter() {
+            write!(out, "? {}{}", quote(path), terminator)?;
+        }
+
+        Ok(())
+    }
+}
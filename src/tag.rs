@@ -0,0 +1,198 @@
+use crate::*;
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::env::current_dir;
+
+/// Create, list, delete or verify tags.
+pub fn manage_tags(tag: cli::Tag) -> Result<()> {
+    trace!("Managing tags");
+    debug!("Got arguments: {:?}", tag);
+
+    // get current directory
+    let root_path =
+        current_dir().with_context(|| "Tag: Could not get the current working directory!")?;
+    let git_path = root_path.join(".git");
+    let db_path = git_path.join("objects");
+
+    // check if this is actually a repository.
+    if !is_repo(&root_path)? {
+        bail!("Tag: {:?} is not a rit repository!", root_path);
+    }
+
+    let database = Database::new(&db_path).with_context(|| "Tag: Could not load database")?;
+    let refs = Refs::new(git_path).with_context(|| "Tag: Could not load refs")?;
+
+    if tag.delete {
+        delete_tags(&tag, &database, &refs)
+    } else if tag.verify {
+        verify_tags(&tag, &database, &refs)
+    } else if tag.list || tag.args.is_empty() {
+        list_tags(&tag, &refs)
+    } else {
+        create_tag(&tag, &database, &refs)
+    }
+}
+
+/// Create a lightweight tag, or an annotated one with a message, pointing
+/// to the given object or HEAD.
+fn create_tag(tag: &cli::Tag, database: &Database, refs: &Refs) -> Result<()> {
+    let (name, target) = match tag.args.as_slice() {
+        [name] => (name, HEAD),
+        [name, target] => (name, target.as_str()),
+        _ => bail!("Tag: too many arguments"),
+    };
+    let full_name = Refs::tag_ref(name);
+    if !is_valid_ref_name(&full_name) {
+        bail!("Tag: '{}' is not a valid tag name", name);
+    }
+    let old = refs.read_ref(&full_name)?;
+    if old.is_some() && !tag.force {
+        bail!("Tag: tag '{}' already exists", name);
+    }
+
+    let mut oid = Revision::new(refs, database, target)?
+        .resolve(None)
+        .with_context(|| format!("Tag: Failed to resolve '{}' as a valid ref", target))?;
+    if tag.annotate || tag.message.is_some() {
+        let message = tag
+            .message
+            .as_deref()
+            .with_context(|| "Tag: An annotated tag needs a message, give it with -m")?;
+        let (type_, _) = database
+            .read_header(&oid)
+            .with_context(|| format!("Tag: Could not read object {}", oid))?;
+        let object = &mut database::Tag::new(
+            oid,
+            type_,
+            name.clone(),
+            refs.identity(),
+            message.trim_end().to_string(),
+        );
+        oid = database
+            .store(object)
+            .with_context(|| "Tag: Failed to store tag")?;
+    }
+
+    let mut transaction = RefTransaction::new(refs);
+    match old {
+        Some(old) => transaction.update(&full_name, oid, Some(old), "")?,
+        None => transaction.create(&full_name, oid, "")?,
+    }
+    transaction
+        .commit()
+        .with_context(|| format!("Tag: Could not create tag {}", name))?;
+    if let Some(old) = old.filter(|old| *old != oid) {
+        println!("Updated tag '{}' (was {})", name, database.short_oid(&old)?);
+    }
+
+    Ok(())
+}
+
+/// Delete every named tag.
+fn delete_tags(tag: &cli::Tag, database: &Database, refs: &Refs) -> Result<()> {
+    if tag.args.is_empty() {
+        bail!("Tag: tag name required");
+    }
+
+    for name in tag.args.iter() {
+        let full_name = Refs::tag_ref(name);
+        let oid = refs
+            .read_ref(&full_name)?
+            .with_context(|| format!("Tag: tag '{}' not found", name))?;
+
+        let mut transaction = RefTransaction::new(refs);
+        transaction.delete(&full_name, Some(oid), "")?;
+        transaction
+            .commit()
+            .with_context(|| format!("Tag: Could not delete tag {}", name))?;
+        println!("Deleted tag '{}' (was {})", name, database.short_oid(&oid)?);
+    }
+
+    Ok(())
+}
+
+/// Check that every named tag is a well formed tag object whose target
+/// exists with the recorded type, and print it.
+fn verify_tags(tag: &cli::Tag, database: &Database, refs: &Refs) -> Result<()> {
+    if tag.args.is_empty() {
+        bail!("Tag: tag name required");
+    }
+
+    for name in tag.args.iter() {
+        let oid = refs
+            .read_ref(&Refs::tag_ref(name))?
+            .with_context(|| format!("Tag: tag '{}' not found", name))?;
+        let object = match database.load(&oid)? {
+            ParsedObject::Tag(object) => object,
+            object => bail!(
+                "Tag: '{}' points to a {}, not a tag object",
+                name,
+                object.get_type().as_string()
+            ),
+        };
+
+        if object.get_tag() != name {
+            bail!(
+                "Tag: '{}' holds the tag object of '{}'",
+                name,
+                object.get_tag()
+            );
+        }
+        if object.get_tagger().is_none() {
+            bail!("Tag: '{}' has no tagger", name);
+        }
+        let (type_, _) = database
+            .read_header(&object.get_object())
+            .with_context(|| {
+                format!(
+                    "Tag: '{}' points to the missing object {}",
+                    name,
+                    object.get_object()
+                )
+            })?;
+        if type_ != object.get_object_type() {
+            bail!(
+                "Tag: '{}' claims {} is a {}, but it is a {}",
+                name,
+                object.get_object(),
+                object.get_object_type().as_string(),
+                type_.as_string()
+            );
+        }
+
+        print!("{}", object);
+    }
+
+    Ok(())
+}
+
+/// Print the tags matching any of the patterns, all if none are given.
+fn list_tags(tag: &cli::Tag, refs: &Refs) -> Result<()> {
+    for name in refs.list_tags()? {
+        if tag.args.is_empty()
+            || tag
+                .args
+                .iter()
+                .any(|pattern| matches_pattern(pattern.as_bytes(), name.as_bytes()))
+        {
+            println!("{}", name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Match `name` against a shell glob where `*` matches any run of characters
+/// and `?` a single one.
+fn matches_pattern(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => {
+            matches_pattern(&pattern[1..], name)
+                || (!name.is_empty() && matches_pattern(pattern, &name[1..]))
+        }
+        (Some(b'?'), Some(_)) => matches_pattern(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => matches_pattern(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
@@ -1,9 +1,13 @@
+use anyhow::{bail, Error, Result};
+use std::str::FromStr;
+
 /// Custom types that can be stored in the `Database`.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Types {
     Blob,
     Tree,
     Commit,
+    Tag,
 }
 
 impl Types {
@@ -12,20 +16,28 @@ impl Types {
             Self::Blob => String::from("blob"),
             Self::Tree => String::from("tree"),
             Self::Commit => String::from("commit"),
+            Self::Tag => String::from("tag"),
         }
     }
 
     pub fn as_type(s: String) -> Types {
-        if s == "blob" {
-            return Types::Blob;
-        }
-        if s == "tree" {
-            return Types::Tree;
-        }
-        if s == "commit" {
-            return Types::Commit;
+        match s.parse() {
+            Ok(t) => t,
+            Err(_) => panic!("Type {} not recognized, corrupt db?", s),
         }
+    }
+}
+
+impl FromStr for Types {
+    type Err = Error;
 
-        panic!("Type {} not recognized, corrupt db?", s);
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "blob" => Ok(Types::Blob),
+            "tree" => Ok(Types::Tree),
+            "commit" => Ok(Types::Commit),
+            "tag" => Ok(Types::Tag),
+            _ => bail!("Types: Type {} not recognized, corrupt db?", s),
+        }
     }
 }
@@ -0,0 +1,120 @@
+use crate::*;
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::env::current_dir;
+use std::io::{stdin, BufRead};
+
+/// Update, create, delete or verify refs in a single transaction.
+pub fn update_refs(update_ref: cli::UpdateRef) -> Result<()> {
+    trace!("Updating refs");
+    debug!("Got arguments: {:?}", update_ref);
+
+    // get current directory
+    let root_path =
+        current_dir().with_context(|| "UpdateRef: Could not get the current working directory!")?;
+    let git_path = root_path.join(".git");
+    let db_path = git_path.join("objects");
+
+    // check if this is actually a repository.
+    if !is_repo(&root_path)? {
+        bail!("UpdateRef: {:?} is not a rit repository!", root_path);
+    }
+
+    let database = Database::new(&db_path).with_context(|| "UpdateRef: Could not load database")?;
+    let refs = Refs::new(git_path).with_context(|| "UpdateRef: Could not load refs")?;
+    let message = update_ref.message.clone().unwrap_or_default();
+    let mut transaction = RefTransaction::new(&refs);
+
+    if update_ref.stdin {
+        for line in stdin().lock().lines() {
+            let line = line.with_context(|| "UpdateRef: Could not read from stdin")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            queue_command(&line, &message, &mut transaction, &refs, &database)
+                .with_context(|| format!("UpdateRef: Invalid command {:?}", line))?;
+        }
+    } else {
+        let name = update_ref.name.as_deref().unwrap_or_default();
+        if update_ref.delete {
+            if update_ref.old.is_some() {
+                bail!("UpdateRef: -d takes the ref and at most its old value");
+            }
+            let old = parse_old(update_ref.new.as_deref(), &refs, &database)?;
+            transaction.delete(name, old, &message)?;
+        } else {
+            let new = update_ref
+                .new
+                .as_deref()
+                .with_context(|| "UpdateRef: Missing new value")?;
+            let new = parse_value(new, &refs, &database)?;
+            let old = parse_old(update_ref.old.as_deref(), &refs, &database)?;
+            transaction.update(name, new, old, &message)?;
+        }
+    }
+
+    transaction.commit()
+}
+
+/// Queue a `--stdin` command like `update <ref> <new> [<old>]`.
+fn queue_command(
+    line: &str,
+    message: &str,
+    transaction: &mut RefTransaction,
+    refs: &Refs,
+    database: &Database,
+) -> Result<()> {
+    let args: Vec<&str> = line.split(' ').collect();
+    match args.as_slice() {
+        ["update", name, new] | ["update", name, new, ""] => {
+            let new = parse_value(new, refs, database)?;
+            if new.is_null() {
+                return transaction.delete(name, None, message);
+            }
+            transaction.update(name, new, None, message)
+        }
+        ["update", name, new, old] => {
+            let new = parse_value(new, refs, database)?;
+            let old = parse_old(Some(old), refs, database)?;
+            if new.is_null() {
+                return transaction.delete(name, old, message);
+            }
+            transaction.update(name, new, old, message)
+        }
+        ["create", name, new] => {
+            let new = parse_value(new, refs, database)?;
+            transaction.create(name, new, message)
+        }
+        ["delete", name] => transaction.delete(name, None, message),
+        ["delete", name, old] => {
+            let old = parse_old(Some(old), refs, database)?;
+            transaction.delete(name, old, message)
+        }
+        ["verify", name] => transaction.verify(name, ObjectId::null(database.get_algorithm())),
+        ["verify", name, old] => {
+            let old = parse_old(Some(old), refs, database)?
+                .unwrap_or_else(|| ObjectId::null(database.get_algorithm()));
+            transaction.verify(name, old)
+        }
+        _ => bail!("UpdateRef: Unknown command"),
+    }
+}
+
+/// Resolve a value, zeros stand for the null oid.
+fn parse_value(value: &str, refs: &Refs, database: &Database) -> Result<ObjectId> {
+    if !value.is_empty() && value.bytes().all(|b| b == b'0') {
+        return Ok(ObjectId::null(database.get_algorithm()));
+    }
+    Revision::new(refs, database, value)?
+        .resolve(None)
+        .with_context(|| format!("UpdateRef: {}: not a valid SHA1", value))
+}
+
+/// Resolve an optional old value, an empty one must not exist.
+fn parse_old(value: Option<&str>, refs: &Refs, database: &Database) -> Result<Option<ObjectId>> {
+    match value {
+        None => Ok(None),
+        Some("") => Ok(Some(ObjectId::null(database.get_algorithm()))),
+        Some(value) => parse_value(value, refs, database).map(Some),
+    }
+}
@@ -14,6 +14,7 @@ pub fn get_files(path: &PathBuf) -> Vec<PathBuf> {
         .into_iter()
         .filter_map(|v| v.ok()) // is file ok
         .filter(|e| !is_ignored(path, e)) // is ignored
+        .filter(|e| e.file_type().is_file()) // directories are implied by their files
         .for_each(|entry| {
             let strip = entry
                 .path()
@@ -87,3 +88,77 @@ pub fn is_repo(root_path: &PathBuf) -> Result<bool> {
     debug!("{:?} is a repository.", root_path);
     Ok(true)
 }
+
+/// Quote `path` the way git does for display, unusual characters are escaped
+/// and the whole path is put into double quotes.
+pub fn quote_path(path: &str) -> String {
+    let needs_quoting = path
+        .bytes()
+        .any(|b| !(0x20..0x7f).contains(&b) || b == b'"' || b == b'\\');
+    if !needs_quoting {
+        return path.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    for b in path.bytes() {
+        match b {
+            0x07 => quoted.push_str("\\a"),
+            0x08 => quoted.push_str("\\b"),
+            b'\t' => quoted.push_str("\\t"),
+            b'\n' => quoted.push_str("\\n"),
+            0x0b => quoted.push_str("\\v"),
+            0x0c => quoted.push_str("\\f"),
+            b'\r' => quoted.push_str("\\r"),
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            b if !(0x20..0x7f).contains(&b) => quoted.push_str(&format!("\\{:03o}", b)),
+            b => quoted.push(b as char),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+/// A scratch directory for tests, removed again when dropped.
+#[cfg(test)]
+pub struct TestDir {
+    path: PathBuf,
+}
+
+#[cfg(test)]
+impl TestDir {
+    /// Create an empty directory below the system temp directory.
+    pub fn new() -> Self {
+        use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+        let name: String = thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(12)
+            .map(char::from)
+            .collect();
+        let path = std::env::temp_dir().join(format!("rit-test-{}", name));
+        std::fs::create_dir_all(&path).expect("Utility: Could not create test directory");
+
+        Self { path }
+    }
+
+    /// Get the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+impl Default for TestDir {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.path).ok();
+    }
+}
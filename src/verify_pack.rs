@@ -0,0 +1,145 @@
+use crate::pack::{DeltaBase, PackIndex};
+use crate::*;
+use anyhow::{bail, Context, Result};
+use log::*;
+use std::collections::{BTreeMap, HashMap};
+use std::env::current_dir;
+use std::io::{self, BufWriter, Write};
+
+/// Verify the checksums of packs and their indices and that every object
+/// hashes to its oid. With `-v` every object is listed with its delta chain.
+pub fn verify_packs(verify_pack: cli::VerifyPack) -> Result<()> {
+    trace!("Verifying packs");
+    debug!("Got arguments: {:?}", verify_pack);
+
+    // packs outside of a repository are assumed to use SHA-1
+    let algorithm = HashAlgorithm::detect(
+        &current_dir()
+            .with_context(|| "VerifyPack: Could not get the current working directory!")?
+            .join(".git"),
+    )?;
+
+    let mut out = BufWriter::new(io::stdout().lock());
+    for path in verify_pack.packs.iter() {
+        let idx_path = path.with_extension("idx");
+        PackIndex::verify_checksum(&idx_path, algorithm)?;
+        let pack = Pack::open(&idx_path, algorithm)?;
+        pack.verify_checksum()?;
+
+        // delta depth of the entry at each offset
+        let mut depths: HashMap<u64, usize> = HashMap::new();
+        let mut chains: BTreeMap<usize, usize> = BTreeMap::new();
+        for (offset, oid) in pack.get_index().oids_by_offset() {
+            let (type_, data) = pack
+                .read_at(offset)
+                .with_context(|| format!("VerifyPack: Could not read {}", oid))?;
+            if Database::hash_data(algorithm, type_, &data) != oid {
+                bail!("VerifyPack: {} does not hash to its oid", oid);
+            }
+            if pack.get_index().crc32(&oid) != Some(pack.entry_crc32(&oid)?) {
+                bail!("VerifyPack: CRC of {} does not match", oid);
+            }
+
+            let header = pack.read_entry_header(offset)?;
+            let disk_size = pack.disk_size(&oid).unwrap_or(0);
+            let base = match &header.base {
+                Some(DeltaBase::Offset(base)) => Some((
+                    *base,
+                    pack.get_index()
+                        .oid_at(*base)
+                        .with_context(|| format!("VerifyPack: No object at offset {}", base))?,
+                )),
+                Some(DeltaBase::Oid(base)) => Some((
+                    pack.get_index()
+                        .find(base)
+                        .with_context(|| format!("VerifyPack: Delta base {} is missing", base))?,
+                    *base,
+                )),
+                None => None,
+            };
+
+            match base {
+                Some((base_offset, base_oid)) => {
+                    let depth = depth_of(&pack, base_offset, &mut depths)? + 1;
+                    depths.insert(offset, depth);
+                    *chains.entry(depth).or_default() += 1;
+                    if verify_pack.verbose {
+                        writeln!(
+                            out,
+                            "{} {:<6} {} {} {} {} {}",
+                            oid,
+                            type_.as_string(),
+                            header.size,
+                            disk_size,
+                            offset,
+                            depth,
+                            base_oid
+                        )?;
+                    }
+                }
+                None => {
+                    depths.insert(offset, 0);
+                    *chains.entry(0).or_default() += 1;
+                    if verify_pack.verbose {
+                        writeln!(
+                            out,
+                            "{} {:<6} {} {} {}",
+                            oid,
+                            type_.as_string(),
+                            header.size,
+                            disk_size,
+                            offset
+                        )?;
+                    }
+                }
+            }
+        }
+
+        if verify_pack.verbose {
+            for (depth, count) in chains {
+                let objects = match count {
+                    1 => "object",
+                    _ => "objects",
+                };
+                match depth {
+                    0 => writeln!(out, "non delta: {} {}", count, objects)?,
+                    _ => writeln!(out, "chain length = {}: {} {}", depth, count, objects)?,
+                }
+            }
+            writeln!(out, "{}: ok", path.with_extension("pack").display())?;
+        }
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+/// Length of the delta chain of the entry at `offset`.
+fn depth_of(pack: &Pack, offset: u64, depths: &mut HashMap<u64, usize>) -> Result<usize> {
+    // walk down until an entry with known depth, then fill in the chain
+    let mut chain = Vec::new();
+    let mut current = offset;
+    let mut depth = loop {
+        if let Some(depth) = depths.get(&current) {
+            break *depth + 1;
+        }
+        chain.push(current);
+        current = match pack.read_entry_header(current)?.base {
+            Some(DeltaBase::Offset(base)) => base,
+            Some(DeltaBase::Oid(base)) => pack
+                .get_index()
+                .find(&base)
+                .with_context(|| format!("VerifyPack: Delta base {} is missing", base))?,
+            None => break 0,
+        };
+        if chain.len() > pack.get_index().len() {
+            bail!("VerifyPack: Delta chain at {} is cyclic", offset);
+        }
+    };
+    for entry in chain.into_iter().rev() {
+        depths.insert(entry, depth);
+        depth += 1;
+    }
+
+    Ok(depth - 1)
+}
@@ -2,9 +2,9 @@ use crate::get_files;
 use anyhow::{bail, Context, Result};
 use is_executable::IsExecutable;
 use log::*;
-use std::fs::File;
+use std::fs::{metadata, File, Metadata};
 use std::io::prelude::*;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 /// The workspace is responsible for the files in the working tree.
 /// All the files that can be directly edited, rather than those stored in `.git`.
@@ -44,7 +44,8 @@ impl Workspace {
     /// Read the content of a file as bytes.
     pub fn read_file(&self, path: &PathBuf) -> Result<Vec<u8>> {
         trace!("Reading contents of file {:?}", path);
-        let mut f = File::open(path).with_context(|| "Workspace: Could not open file")?;
+        let mut f = File::open(self.root_path.join(path))
+            .with_context(|| "Workspace: Could not open file")?;
         let mut buffer = Vec::new();
 
         // read the whole content of the file
@@ -53,9 +54,39 @@ impl Workspace {
         Ok(buffer)
     }
 
+    /// Open a file for streaming its content, returning it with its length.
+    pub fn open_file(&self, path: &PathBuf) -> Result<(File, u64)> {
+        trace!("Opening file {:?}", path);
+        let f = File::open(self.root_path.join(path))
+            .with_context(|| "Workspace: Could not open file")?;
+        let len = f
+            .metadata()
+            .with_context(|| "Workspace: Could not stat file")?
+            .len();
+
+        Ok((f, len))
+    }
+
+    /// Return the files below `path`, or `path` itself if it is a file.
+    pub fn list_files_in(&self, path: &Path) -> Vec<PathBuf> {
+        trace!("Listing files in {:?}", path);
+        self.list_files
+            .iter()
+            .filter(|file| file.starts_with(path))
+            .cloned()
+            .collect()
+    }
+
+    /// Get the filesystem metadata of a file, as cached by the `Index`.
+    pub fn read_metadata(&self, path: &PathBuf) -> Result<Metadata> {
+        trace!("Reading metadata of {:?}", path);
+        metadata(self.root_path.join(path)).with_context(|| "Workspace: Could not stat file")
+    }
+
     /// Stat a given file path.
     pub fn stat_file(&self, path: &PathBuf) -> Result<FileStat> {
         trace!("Getting file stat for {:?}", path);
+        let path = &self.root_path.join(path);
 
         // For safety check if file still exists
         if !path.exists() {